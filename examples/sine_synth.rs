@@ -6,11 +6,14 @@ use once_cell::sync::Lazy;
 use std::{cell::Cell, f64::consts::PI, rc::Rc};
 use uuid::Uuid;
 use vst3::{
-    audio_processor::{AudioProcessor, ProcessInput, ProcessOutput},
-    edit_controller::EditController,
+    audio_processor::{AudioProcessor, GenericProcess, ProcessInput, ProcessOutput},
+    bus::EventBusDeclaration,
+    edit_controller::{EditController, ProgramListId},
     plugin::{read_parameter_values, write_parameter_values, Parameters, Plugin, State},
     plugin_parameter::{NormalizedParameterValue, ParameterInfo, ParameterValueContainer, ParameterWithValue},
+    program::{LoadedPrograms, Program, ProgramList, ProgramLists},
     range::Range,
+    sample::Sample,
     vst_factory::{AudioProcessorInfo, AudioProcessorType, FactoryInfo, VstPluginFactory},
     vst_stream::{VstInStream, VstOutStream},
 };
@@ -25,7 +28,20 @@ static GAIN: Lazy<ParameterInfo> =
 static FREQ: Lazy<ParameterInfo> =
     Lazy::new(|| ParameterInfo::new_linear(2.into(), "Freq", "Hz", 400.0, Range::new(20.0, 2000.0)));
 
-static PARAMS: Lazy<Vec<&'static ParameterInfo>> = Lazy::new(|| vec![&GAIN, &FREQ]);
+static PROGRAMS: Lazy<ProgramLists> = Lazy::new(|| {
+    ProgramLists::new(vec![ProgramList::new(
+        ProgramListId::new(1),
+        3.into(),
+        "Presets",
+        vec![
+            Program::new("Default", &[(&GAIN, 50.0), (&FREQ, 400.0)]),
+            Program::new("Low Hum", &[(&GAIN, 30.0), (&FREQ, 60.0)]),
+            Program::new("High Beep", &[(&GAIN, 20.0), (&FREQ, 1760.0)]),
+        ],
+    )])
+});
+
+static PARAMS: Lazy<Vec<&'static ParameterInfo>> = Lazy::new(|| vec![&GAIN, &FREQ, &PROGRAMS.lists()[0].parameter]);
 
 #[derive(Clone)]
 struct SineSynth {
@@ -35,34 +51,6 @@ struct SineSynth {
     pos:                       Cell<f64>,
 }
 
-impl SineSynth {
-    fn do_process<'t, T>(
-        &self,
-        input: &'t ProcessInput<'t, T>,
-        output: &'t mut ProcessOutput<'t, T>,
-        f: impl Fn(f64) -> T,
-    ) {
-        let p = self.pos.get();
-        let gain = self.gain.update(&input.param_changes).get() / 100.0;
-        let freq = *self.freq.update(&input.param_changes);
-        let c = freq / input.context.sample_rate;
-
-        for bus in output.buses().iter_mut() {
-            for channel in bus.channels().iter_mut() {
-                channel.is_silenced = false;
-
-                for (i, sample) in channel.samples.iter_mut().enumerate() {
-                    let v = gain * (2.0 * PI * (p + c * i as f64)).sin();
-                    *sample = f(v);
-                }
-            }
-        }
-
-        let np = p + c * input.sample_count as f64;
-        self.pos.set(np.fract());
-    }
-}
-
 impl Default for SineSynth {
     fn default() -> Self {
         let parameter_value_container = ParameterValueContainer::new(&PARAMS);
@@ -90,24 +78,46 @@ impl State for SineSynth {
     }
 }
 
-impl AudioProcessor for SineSynth {
-    fn process_f32<'t>(&self, input: &'t ProcessInput<'t, f32>, output: &'t mut ProcessOutput<'t, f32>) {
-        self.do_process(input, output, |v| v as f32)
-    }
+impl GenericProcess for SineSynth {
+    const CAN_PROCESS_F64: bool = true;
+
+    fn process<'t, T: Sample>(&self, input: &ProcessInput<'t, T>, output: &mut ProcessOutput<'t, T>) {
+        PROGRAMS.update(&self.parameter_value_container, &input.param_changes);
+        let p = self.pos.get();
+        let gain = self.gain.update(&input.param_changes).get() / 100.0;
+        let freq = *self.freq.update(&input.param_changes);
+        let c = freq / input.transport.sample_rate;
 
-    fn process_f64<'t>(&self, input: &'t ProcessInput<'t, f64>, output: &'t mut ProcessOutput<'t, f64>) {
-        self.do_process(input, output, |v| v)
+        for bus in output.buses().iter_mut() {
+            for channel in bus.channels().iter_mut() {
+                channel.is_silenced = false;
+
+                for (i, sample) in channel.samples.iter_mut().enumerate() {
+                    let v = gain * (2.0 * PI * (p + c * i as f64)).sin();
+                    *sample = T::from_f64(v);
+                }
+            }
+        }
+
+        let np = p + c * input.sample_count as f64;
+        self.pos.set(np.fract());
     }
 }
 
+impl AudioProcessor for SineSynth {
+    fn get_event_inputs(&self) -> Vec<EventBusDeclaration> { vec![EventBusDeclaration::main("MIDI In", 16)] }
+}
+
 struct SineSynthController {
     parameter_value_container: ParameterValueContainer,
+    loaded_programs:           LoadedPrograms,
 }
 
 impl SineSynthController {
     fn new() -> Self {
         Self {
             parameter_value_container: ParameterValueContainer::new(&PARAMS),
+            loaded_programs:           LoadedPrograms::new(),
         }
     }
 }
@@ -135,6 +145,9 @@ impl EditController for SineSynthController {
     fn set_component_state(&self, stream: &mut VstInStream) -> std::io::Result<()> {
         read_parameter_values(&self.parameter_value_container, stream)
     }
+
+    fn get_programs(&self) -> Option<&ProgramLists> { Some(&PROGRAMS) }
+    fn get_loaded_programs(&self) -> Option<&LoadedPrograms> { Some(&self.loaded_programs) }
 }
 
 static mut INIT_LOGGER: bool = false;
@@ -176,6 +189,7 @@ pub unsafe extern "system" fn GetPluginFactory() -> *mut c_void {
         typ:                   AudioProcessorType::Synth,
         is_distributable:      true,
         simple_mode_supported: false,
+        bypass:                None,
     };
 
     f.add_audio_processor_with_controller_factories(
@@ -188,3 +202,32 @@ pub unsafe extern "system" fn GetPluginFactory() -> *mut c_void {
 
     Box::into_raw(f) as *mut c_void
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use vst3::{
+        conformance,
+        test_host::{TestBlock, TestHost, TestSetup},
+    };
+
+    fn host() -> TestHost { unsafe { TestHost::from_entry_point(GetPluginFactory) }.expect("No plugin factory") }
+
+    #[test]
+    fn renders_after_note_on() {
+        let host = host();
+        let mut plugin = host.create_first_plugin().expect("Failed to create plugin");
+        assert!(plugin.start(TestSetup::default()));
+
+        let output = plugin.process(&TestBlock::new(256).note_on(0, 0, 60, 1.0)).unwrap();
+        assert!(output.is_ok());
+        assert!(output.peak() > 0.1);
+        assert!(output.silence_flags.iter().all(|f| *f == 0));
+
+        let rendered = plugin.render(1000, 256).unwrap();
+        assert!(rendered.iter().flatten().all(|c| c.len() == 1000));
+    }
+
+    #[test]
+    fn passes_conformance_checks() { conformance::run(&host()).assert_ok(); }
+}
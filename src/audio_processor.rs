@@ -1,11 +1,18 @@
 use crate::{
+    bus::{BusDeclaration, BusRole, EventBusDeclaration, STEREO},
+    edit_controller::BusDirection,
+    midi_mapping::{MidiControllerEvent, MidiMapping},
+    midi_out::MidiMessage,
     plugin::{Plugin, State},
     plugin_parameter::{NormalizedParameterValue, ParameterId, ParameterPoint},
+    sample::Sample,
+    sysex::SysEx,
+    transport::Transport,
 };
 use std::collections::HashMap;
 use vst3_sys::vst::{
-    ChordEvent, DataEvent, LegacyMidiCCOutEvent, NoteExpressionTextEvent, NoteExpressionValueEvent, NoteOffEvent,
-    NoteOnEvent, PolyPressureEvent, ProcessContext, ProcessModes, ScaleEvent, SymbolicSampleSizes,
+    ChordEvent, LegacyMidiCCOutEvent, NoteExpressionTextEvent, NoteExpressionValueEvent, NoteOffEvent, NoteOnEvent,
+    PolyPressureEvent, ProcessContext, ProcessModes, ScaleEvent, SymbolicSampleSizes,
 };
 
 pub struct InputParameterChanges<'t> {
@@ -18,11 +25,62 @@ pub struct OutputParameterChanges {
     pub points:       Vec<ParameterPoint>,
 }
 
-#[derive(Copy, Clone)]
-pub enum EventData {
+/// The event was generated by a live input, e.g. a keyboard, and not by playback
+pub const EVENT_IS_LIVE: u16 = 1;
+
+/// Reserved for the plugin, e.g. to mark events it generated itself
+pub const EVENT_USER_RESERVED_1: u16 = 1 << 14;
+pub const EVENT_USER_RESERVED_2: u16 = 1 << 15;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum EventType {
+    NoteOn,
+    NoteOff,
+    Data,
+    PolyPressure,
+    NoteExpressionValue,
+    NoteExpressionText,
+    Chord,
+    Scale,
+    LegacyMidiCcOut,
+}
+
+impl EventType {
+    pub fn from_vst(type_: u16) -> Option<Self> {
+        match type_ {
+            0 => Some(Self::NoteOn),
+            1 => Some(Self::NoteOff),
+            2 => Some(Self::Data),
+            3 => Some(Self::PolyPressure),
+            4 => Some(Self::NoteExpressionValue),
+            5 => Some(Self::NoteExpressionText),
+            6 => Some(Self::Chord),
+            7 => Some(Self::Scale),
+            65535 => Some(Self::LegacyMidiCcOut),
+            _ => None,
+        }
+    }
+
+    pub fn to_vst(self) -> u16 {
+        match self {
+            Self::NoteOn => 0,
+            Self::NoteOff => 1,
+            Self::Data => 2,
+            Self::PolyPressure => 3,
+            Self::NoteExpressionValue => 4,
+            Self::NoteExpressionText => 5,
+            Self::Chord => 6,
+            Self::Scale => 7,
+            Self::LegacyMidiCcOut => 65535,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub enum EventData<'t> {
     NoteOn(NoteOnEvent),
     NoteOff(NoteOffEvent),
-    Data(DataEvent),
+    SysEx(SysEx<'t>),
     PolyPressure(PolyPressureEvent),
     NoteExpressionValue(NoteExpressionValueEvent),
     NoteExpressionText(NoteExpressionTextEvent),
@@ -31,14 +89,78 @@ pub enum EventData {
     LegacyMidiCcOut(LegacyMidiCCOutEvent),
 }
 
-#[repr(C)]
-#[derive(Copy, Clone)]
-pub struct Event {
+#[derive(Clone)]
+pub struct Event<'t> {
     pub bus_index:     i32,
     pub sample_offset: i32,
     pub ppq_position:  f64,
     pub flags:         u16,
-    pub event:         EventData,
+    pub event:         EventData<'t>,
+}
+
+impl<'t> EventData<'t> {
+    pub fn event_type(&self) -> EventType {
+        match self {
+            EventData::NoteOn(_) => EventType::NoteOn,
+            EventData::NoteOff(_) => EventType::NoteOff,
+            EventData::SysEx(_) => EventType::Data,
+            EventData::PolyPressure(_) => EventType::PolyPressure,
+            EventData::NoteExpressionValue(_) => EventType::NoteExpressionValue,
+            EventData::NoteExpressionText(_) => EventType::NoteExpressionText,
+            EventData::Chord(_) => EventType::Chord,
+            EventData::Scale(_) => EventType::Scale,
+            EventData::LegacyMidiCcOut(_) => EventType::LegacyMidiCcOut,
+        }
+    }
+}
+
+impl<'t> Event<'t> {
+    /// An event at a sample offset of bus 0 without flags
+    pub fn new(sample_offset: i32, event: EventData<'t>) -> Self {
+        Self {
+            bus_index: 0,
+            sample_offset,
+            ppq_position: 0.0,
+            flags: 0,
+            event,
+        }
+    }
+
+    pub fn event_type(&self) -> EventType { self.event.event_type() }
+    pub fn is_live(&self) -> bool { self.flags & EVENT_IS_LIVE != 0 }
+
+    pub fn with_bus(mut self, bus_index: i32) -> Self {
+        self.bus_index = bus_index;
+        self
+    }
+
+    pub fn with_flags(mut self, flags: u16) -> Self {
+        self.flags = flags;
+        self
+    }
+
+    /// Copies borrowed SysEx data, e.g. to keep an input event beyond the current block
+    pub fn into_owned(self) -> Event<'static> {
+        let event = match self.event {
+            EventData::NoteOn(e) => EventData::NoteOn(e),
+            EventData::NoteOff(e) => EventData::NoteOff(e),
+            EventData::SysEx(e) => EventData::SysEx(e.into_owned()),
+            EventData::PolyPressure(e) => EventData::PolyPressure(e),
+            EventData::NoteExpressionValue(e) => EventData::NoteExpressionValue(e),
+            EventData::NoteExpressionText(e) => EventData::NoteExpressionText(e),
+            EventData::Chord(e) => EventData::Chord(e),
+            EventData::Scale(e) => EventData::Scale(e),
+            EventData::LegacyMidiCcOut(e) => EventData::LegacyMidiCcOut(e),
+        };
+
+        Event {
+            bus_index: self.bus_index,
+            sample_offset: self.sample_offset,
+            ppq_position: self.ppq_position,
+            flags: self.flags,
+            event,
+        }
+    }
 }
 
 pub struct InChannel<'t, T> {
@@ -47,6 +169,9 @@ pub struct InChannel<'t, T> {
 }
 
 pub struct InBus<'t, T> {
+    pub name:     &'t str,
+    pub role:     BusRole,
+    pub active:   bool,
     pub channels: Vec<InChannel<'t, T>>,
 }
 
@@ -56,12 +181,38 @@ pub struct OutChannel<'t, T> {
 }
 
 pub struct OutBus<'t, T> {
-    channels: Vec<OutChannel<'t, T>>,
+    pub name:   &'t str,
+    pub role:   BusRole,
+    pub active: bool,
+    channels:   Vec<OutChannel<'t, T>>,
+}
+
+impl<'t, T: Sample> OutChannel<'t, T> {
+    /// Flags the channel as silent if no sample exceeds `threshold`, returns the new flag
+    pub fn detect_silence(&mut self, threshold: T) -> bool {
+        self.is_silenced = self.samples.iter().all(|s| s.abs() <= threshold);
+        self.is_silenced
+    }
 }
 
 impl<'t, T> OutBus<'t, T> {
-    pub fn new(channels: Vec<OutChannel<'t, T>>) -> Self { Self { channels } }
+    pub fn new(channels: Vec<OutChannel<'t, T>>) -> Self { Self::with_info("", BusRole::Main, true, channels) }
+
+    pub fn with_info(name: &'t str, role: BusRole, active: bool, channels: Vec<OutChannel<'t, T>>) -> Self {
+        Self {
+            name,
+            role,
+            active,
+            channels,
+        }
+    }
+
     pub fn channels(&mut self) -> &mut [OutChannel<'t, T>] { self.channels.as_mut_slice() }
+
+    /// Bit N is set if channel N is flagged as silent
+    pub fn silence_flags(&self) -> u64 {
+        self.channels.iter().take(64).enumerate().filter(|(_, c)| c.is_silenced).fold(0, |f, (i, _)| f | 1 << i)
+    }
 }
 
 pub struct ProcessInput<'t, T> {
@@ -70,20 +221,59 @@ pub struct ProcessInput<'t, T> {
     pub sample_count:  u32,
     pub buses:         Vec<InBus<'t, T>>,
     pub param_changes: HashMap<ParameterId, Vec<ParameterPoint>>,
-    pub events:        Vec<Event>,
-    pub context:       &'t ProcessContext,
+    pub events:        Vec<Event<'t>>,
+
+    /// Changes of the hidden MIDI controller parameters, sorted by sample offset
+    pub controller_events: Vec<MidiControllerEvent>,
+
+    /// The raw process context, `None` if the host didn't provide one
+    pub context: Option<&'t ProcessContext>,
+
+    /// The decoded process context, always valid
+    pub transport: Transport,
 }
 
 impl<'t, T> ProcessInput<'t, T> {
     pub fn get_last_param_value(&self, id: ParameterId) -> Option<NormalizedParameterValue> {
         self.param_changes.get(&id).map(|v| v.last().map(|p| p.value)).flatten()
     }
+
+    pub fn main_bus(&self) -> Option<&InBus<'t, T>> { self.buses.iter().find(|b| b.role == BusRole::Main) }
+
+    /// The first aux bus activated by the host, e.g. a connected sidechain
+    pub fn aux_bus(&self) -> Option<&InBus<'t, T>> {
+        self.buses.iter().find(|b| b.role == BusRole::Aux && b.active && !b.channels.is_empty())
+    }
+
+    pub fn bus(&self, name: &str) -> Option<&InBus<'t, T>> { self.buses.iter().find(|b| b.name == name) }
+
+    /// An input without buses or parameter changes, for testing event handling
+    #[cfg(test)]
+    pub(crate) fn for_events(
+        sample_count: u32,
+        events: Vec<Event<'t>>,
+        controller_events: Vec<MidiControllerEvent>,
+    ) -> Self {
+        Self {
+            process_mode: ProcessModes::kRealtime,
+            sample_size: SymbolicSampleSizes::kSample32,
+            sample_count,
+            buses: Vec::new(),
+            param_changes: HashMap::new(),
+            events,
+            controller_events,
+            context: None,
+            transport: Transport::new(None, 48000.0),
+        }
+    }
 }
 
 pub struct ProcessOutput<'t, T> {
     buses:             Vec<OutBus<'t, T>>,
     pub param_changes: HashMap<ParameterId, Vec<ParameterPoint>>,
-    pub events:        Vec<Event>,
+
+    /// Sent to the host after processing, SysEx data is copied and kept alive until the next process call
+    pub events: Vec<Event<'t>>,
 }
 
 impl<'t, T> ProcessOutput<'t, T> {
@@ -96,10 +286,89 @@ impl<'t, T> ProcessOutput<'t, T> {
     }
 
     pub fn buses(&mut self) -> &mut [OutBus<'t, T>] { self.buses.as_mut_slice() }
+
+    pub fn main_bus(&mut self) -> Option<&mut OutBus<'t, T>> { self.buses.iter_mut().find(|b| b.role == BusRole::Main) }
+    pub fn bus(&mut self, name: &str) -> Option<&mut OutBus<'t, T>> { self.buses.iter_mut().find(|b| b.name == name) }
+
+    /// The silence flags of all buses as reported to the host
+    pub fn silence_flags(&self) -> impl Iterator<Item = u64> + '_ { self.buses.iter().map(|b| b.silence_flags()) }
+
+    /// Sends a MIDI message on event output bus 0
+    pub fn send_midi(&mut self, sample_offset: i32, channel: u8, message: MidiMessage) {
+        self.events.push(message.to_event(sample_offset, channel));
+    }
+}
+
+impl<'t, T: Sample> ProcessOutput<'t, T> {
+    /// Flags all output channels where no sample exceeds `threshold` as silent
+    pub fn detect_silence(&mut self, threshold: T) {
+        for bus in self.buses.iter_mut() {
+            for channel in bus.channels.iter_mut() {
+                channel.detect_silence(threshold);
+            }
+        }
+    }
 }
 
-pub trait AudioProcessor: Plugin + State {
-    fn process_f32<'t>(&self, input: &'t ProcessInput<'t, f32>, output: &'t mut ProcessOutput<'t, f32>);
-    fn process_f64<'t>(&self, input: &'t ProcessInput<'t, f64>, output: &'t mut ProcessOutput<'t, f64>);
+/// Processing of audio blocks, implement either this or `GenericProcess`
+pub trait Process {
+    fn process_f32<'t>(&self, input: &ProcessInput<'t, f32>, output: &mut ProcessOutput<'t, f32>);
+
+    /// Only called if `can_process_f64` returns true
+    fn process_f64<'t>(&self, _input: &ProcessInput<'t, f64>, _output: &mut ProcessOutput<'t, f64>) {}
+
+    /// If true, 64-bit processing is advertised to the host
+    fn can_process_f64(&self) -> bool { false }
+}
+
+/// A single processing method for both sample sizes, `Process` is implemented for all types implementing this trait
+pub trait GenericProcess {
+    /// If true, 64-bit processing is advertised to the host
+    const CAN_PROCESS_F64: bool = false;
+
+    fn process<'t, T: Sample>(&self, input: &ProcessInput<'t, T>, output: &mut ProcessOutput<'t, T>);
+}
+
+impl<P: GenericProcess> Process for P {
+    fn process_f32<'t>(&self, input: &ProcessInput<'t, f32>, output: &mut ProcessOutput<'t, f32>) {
+        self.process(input, output)
+    }
+
+    fn process_f64<'t>(&self, input: &ProcessInput<'t, f64>, output: &mut ProcessOutput<'t, f64>) {
+        self.process(input, output)
+    }
+
+    fn can_process_f64(&self) -> bool { Self::CAN_PROCESS_F64 }
+}
+
+#[allow(unused_variables)]
+pub trait AudioProcessor: Plugin + State + Process {
+    /// The audio input buses, declared to the host on initialization
+    fn get_audio_inputs(&self) -> Vec<BusDeclaration> { vec![BusDeclaration::main("Stereo In", STEREO)] }
+
+    /// The audio output buses, declared to the host on initialization
+    fn get_audio_outputs(&self) -> Vec<BusDeclaration> { vec![BusDeclaration::main("Stereo Out", STEREO)] }
+
+    /// The event input buses, a processor receiving notes must declare at least one
+    fn get_event_inputs(&self) -> Vec<EventBusDeclaration> { Vec::new() }
+
+    /// The event output buses, a processor sending events to the host, e.g. MIDI CCs, must declare at least one
+    fn get_event_outputs(&self) -> Vec<EventBusDeclaration> { Vec::new() }
+
+    /// Called when the host activates or deactivates a bus, e.g. when a sidechain is connected
+    fn bus_activated(&self, dir: BusDirection, index: i32, active: bool) {}
+
     fn get_tail_samples(&self) -> u32 { 0 }
+
+    /// Reported to the host and used to delay the dry signal when bypassed
+    fn get_latency_samples(&self) -> u32 { 0 }
+
+    /// If true, output channels containing only zeros are flagged as silent after each call to `process`
+    fn detect_output_silence(&self) -> bool { false }
+
+    /// If true, the processor only outputs silence after a panic in `process` until it's terminated
+    fn silence_after_panic(&self) -> bool { true }
+
+    /// If overridden, changes of the hidden controller parameters are provided as `ProcessInput::controller_events`
+    fn get_midi_mapping(&self) -> Option<&MidiMapping> { None }
 }
@@ -0,0 +1,358 @@
+//! Prints the factory info, classes, buses, parameters, units, program lists and MIDI mappings of any VST3 plugin as
+//! text or JSON.
+
+use serde_json::{json, Value};
+use std::path::PathBuf;
+use std::process::exit;
+use vst3::edit_controller::{BusDirection, MediaType};
+use vst3::host::{BusInfo, ClassInfo, Component, Controller, HostParameterInfo};
+use vst3::midi_mapping::{MidiController, MIDI_CHANNEL_COUNT};
+use vst3::plugin_module::PluginModule;
+use vst3::plugin_parameter::ParameterFlags;
+use vst3::test_host::TestHost;
+use vst3::unit::UnitInfo;
+use vst3::vst_factory::AUDIO_MODULE_CLASS;
+
+const USAGE: &str = "Usage: vst3-inspect <plugin> [--json]";
+
+fn flag_names(flags: &ParameterFlags) -> Vec<&'static str> {
+    [
+        (flags.can_automate, "automate"),
+        (flags.is_read_only, "read-only"),
+        (flags.is_wrap_around, "wrap-around"),
+        (flags.is_list, "list"),
+        (flags.is_hidden, "hidden"),
+        (flags.is_program_change, "program-change"),
+        (flags.is_bypass, "bypass"),
+    ]
+    .iter()
+    .filter(|(set, _)| *set)
+    .map(|(_, name)| *name)
+    .collect()
+}
+
+fn class_json(c: &ClassInfo) -> Value {
+    json!({
+        "cid": c.cid.to_string(),
+        "name": c.name,
+        "category": c.category,
+        "subcategories": c.subcategories,
+        "vendor": c.vendor,
+        "version": c.version,
+        "sdk_version": c.sdk_version,
+        "cardinality": c.cardinality,
+        "flags": c.class_flags,
+    })
+}
+
+struct Bus {
+    media_type:  MediaType,
+    dir:         BusDirection,
+    info:        BusInfo,
+    arrangement: Option<u64>,
+}
+
+fn buses(component: &Component) -> Vec<Bus> {
+    let mut result = Vec::new();
+
+    for media_type in [MediaType::Audio, MediaType::Event] {
+        for dir in [BusDirection::Input, BusDirection::Output] {
+            for (i, info) in component.buses(media_type, dir).into_iter().enumerate() {
+                let arrangement = match media_type {
+                    MediaType::Audio => component.bus_arrangement(dir, i as i32),
+                    MediaType::Event => None,
+                };
+
+                result.push(Bus {
+                    media_type,
+                    dir,
+                    info,
+                    arrangement,
+                });
+            }
+        }
+    }
+
+    result
+}
+
+fn bus_kind(bus: &Bus) -> String {
+    format!(
+        "{} {} {}",
+        if bus.info.bus_type == 0 { "main" } else { "aux" },
+        match bus.media_type {
+            MediaType::Audio => "audio",
+            MediaType::Event => "event",
+        },
+        match bus.dir {
+            BusDirection::Input => "input",
+            BusDirection::Output => "output",
+        }
+    )
+}
+
+/// Returns `(bus, channel, controller number, parameter id)` for all assigned controllers
+fn midi_mappings(component: &Component, controller: &Controller) -> Vec<(i32, i16, i16, u32)> {
+    let mut result = Vec::new();
+
+    if !controller.has_midi_mapping() {
+        return result;
+    }
+
+    for bus in 0..component.bus_count(MediaType::Event, BusDirection::Input) {
+        for channel in 0..MIDI_CHANNEL_COUNT {
+            for c in MidiController::all() {
+                if let Some(id) = controller.midi_controller_assignment(bus, channel, c.number()) {
+                    result.push((bus, channel, c.number(), *id));
+                }
+            }
+        }
+    }
+
+    result
+}
+
+struct Processor {
+    class:      ClassInfo,
+    buses:      Vec<Bus>,
+    latency:    u32,
+    tail:       u32,
+    parameters: Vec<(HostParameterInfo, String)>,
+    units:      Vec<UnitInfo>,
+    programs:   Vec<(i32, String, Vec<String>)>,
+    midi:       Vec<(i32, i16, i16, u32)>,
+}
+
+fn inspect(host: &TestHost, class: &ClassInfo) -> Result<Processor, String> {
+    let plugin = host.create_plugin(class.cid).ok_or_else(|| format!("Failed to create {}", class.name))?;
+    let component = plugin.component();
+
+    let mut p = Processor {
+        class:      class.clone(),
+        buses:      buses(component),
+        latency:    component.latency_samples(),
+        tail:       component.tail_samples(),
+        parameters: Vec::new(),
+        units:      Vec::new(),
+        programs:   Vec::new(),
+        midi:       Vec::new(),
+    };
+
+    if let Some(c) = plugin.controller() {
+        p.parameters = c
+            .parameters()
+            .into_iter()
+            .map(|i| {
+                let default = c.value_to_string(i.id, i.default_normalized_value).unwrap_or_default();
+                (i, default)
+            })
+            .collect();
+
+        p.units = c.units();
+
+        p.programs = c
+            .program_lists()
+            .into_iter()
+            .map(|l| {
+                let names = (0..l.program_count).map(|i| c.program_name(l.id, i).unwrap_or_default()).collect();
+                (*l.id, l.name, names)
+            })
+            .collect();
+
+        p.midi = midi_mappings(component, c);
+    }
+
+    Ok(p)
+}
+
+fn processor_json(p: &Processor) -> Value {
+    json!({
+        "class": class_json(&p.class),
+        "latency_samples": p.latency,
+        "tail_samples": p.tail,
+        "buses": p.buses.iter().map(|b| json!({
+            "kind": bus_kind(b),
+            "name": b.info.name,
+            "channel_count": b.info.channel_count,
+            "speaker_arrangement": b.arrangement,
+            "flags": b.info.flags,
+        })).collect::<Vec<_>>(),
+        "parameters": p.parameters.iter().map(|(i, default)| json!({
+            "id": *i.id,
+            "title": i.title,
+            "short_title": i.short_title,
+            "units": i.units,
+            "step_count": i.step_count,
+            "default_normalized_value": *i.default_normalized_value,
+            "default_string": default,
+            "unit_id": *i.unit_id,
+            "flags": flag_names(&i.flags),
+        })).collect::<Vec<_>>(),
+        "units": p.units.iter().map(|u| json!({
+            "id": *u.id,
+            "parent_unit_id": *u.parent_unit_id,
+            "name": u.name,
+            "program_list_id": *u.program_list_id,
+        })).collect::<Vec<_>>(),
+        "program_lists": p.programs.iter().map(|(id, name, programs)| json!({
+            "id": id,
+            "name": name,
+            "programs": programs,
+        })).collect::<Vec<_>>(),
+        "midi_mappings": p.midi.iter().map(|(bus, channel, controller, id)| json!({
+            "bus": bus,
+            "channel": channel,
+            "controller": controller,
+            "parameter_id": id,
+        })).collect::<Vec<_>>(),
+    })
+}
+
+fn print_units(units: &[UnitInfo], parent: i32, depth: usize) {
+    for u in units.iter().filter(|u| *u.parent_unit_id == parent && *u.id != parent) {
+        let list =
+            if *u.program_list_id >= 0 { format!(" (program list {})", *u.program_list_id) } else { String::new() };
+        println!("    {}{} {}{}", "  ".repeat(depth), *u.id, u.name, list);
+        print_units(units, *u.id, depth + 1);
+    }
+}
+
+fn print_processor(p: &Processor) {
+    println!();
+    println!("{} [{}]", p.class.name, p.class.cid);
+    println!("  Subcategories: {}", p.class.subcategories);
+    println!("  Version: {}, SDK: {}", p.class.version, p.class.sdk_version);
+    println!("  Latency: {} samples, tail: {} samples", p.latency, p.tail);
+
+    println!("  Buses:");
+
+    for b in p.buses.iter() {
+        let arrangement = b.arrangement.map(|a| format!(", arrangement {:#x}", a)).unwrap_or_default();
+        println!("    {} \"{}\": {} channels{}", bus_kind(b), b.info.name, b.info.channel_count, arrangement);
+    }
+
+    println!("  Parameters:");
+    println!("    {:>10}  {:<24} {:<8} {:>5}  {:<16} {:>6}  Flags", "Id", "Title", "Units", "Steps", "Default", "Unit");
+
+    for (i, default) in p.parameters.iter() {
+        println!(
+            "    {:>10}  {:<24} {:<8} {:>5}  {:<16} {:>6}  {}",
+            *i.id,
+            i.title,
+            i.units,
+            i.step_count,
+            default,
+            *i.unit_id,
+            flag_names(&i.flags).join(", ")
+        );
+    }
+
+    if !p.units.is_empty() {
+        println!("  Units:");
+        print_units(&p.units, -1, 0);
+    }
+
+    for (id, name, programs) in p.programs.iter() {
+        println!("  Program list {} \"{}\":", id, name);
+
+        for (i, program) in programs.iter().enumerate() {
+            println!("    {:>3}  {}", i, program);
+        }
+    }
+
+    if !p.midi.is_empty() {
+        println!("  MIDI mappings:");
+
+        for (bus, channel, controller, id) in p.midi.iter() {
+            let c = MidiController::from_number(*controller).map(|c| format!("{:?}", c)).unwrap_or_default();
+            println!("    bus {} channel {:>2} {:<20} -> parameter {}", bus, channel + 1, c, id);
+        }
+    }
+}
+
+fn run(path: PathBuf, as_json: bool) -> Result<(), String> {
+    let module = PluginModule::load(&path).map_err(|e| e.to_string())?;
+    let host = TestHost::from_factory(module.factory().map_err(|e| e.to_string())?);
+    let info = host.factory().info().ok_or("get_factory_info failed")?;
+    let classes = host.classes();
+
+    // Classes failing to load are reported without hiding the others
+    let processors =
+        classes.iter().filter(|c| c.category == AUDIO_MODULE_CLASS).map(|c| (c, inspect(&host, c))).collect::<Vec<_>>();
+
+    let failed = processors.iter().filter(|(_, p)| p.is_err()).count();
+
+    if as_json {
+        let value = json!({
+            "path": module.path().display().to_string(),
+            "factory": {
+                "vendor": info.vendor,
+                "url": info.url,
+                "email": info.email,
+                "flags": host.factory().flags(),
+            },
+            "classes": classes.iter().map(class_json).collect::<Vec<_>>(),
+            "processors": processors.iter().map(|(c, p)| match p {
+                Ok(p) => processor_json(p),
+                Err(e) => json!({ "class": class_json(c), "error": e }),
+            }).collect::<Vec<_>>(),
+        });
+
+        println!("{}", serde_json::to_string_pretty(&value).map_err(|e| e.to_string())?);
+    }
+    else {
+        println!("{}", module.path().display());
+        println!("Vendor: {}, {}, {}", info.vendor, info.url, info.email);
+        println!("Classes:");
+
+        for c in classes.iter() {
+            println!("  {} [{}] {}", c.category, c.cid, c.name);
+        }
+
+        for (c, p) in processors.iter() {
+            match p {
+                Ok(p) => print_processor(p),
+                Err(e) => {
+                    println!();
+                    println!("{} [{}]", c.name, c.cid);
+                    println!("  Error: {}", e);
+                }
+            }
+        }
+    }
+
+    if failed > 0 {
+        return Err(format!("{} of {} processor classes failed", failed, processors.len()));
+    }
+
+    Ok(())
+}
+
+fn main() {
+    let mut path = None;
+    let mut as_json = false;
+
+    for arg in std::env::args().skip(1) {
+        match arg.as_str() {
+            "--json" => as_json = true,
+            _ if path.is_none() && !arg.starts_with("--") => path = Some(PathBuf::from(arg)),
+            _ => {
+                eprintln!("{}", USAGE);
+                exit(2);
+            }
+        }
+    }
+
+    let path = match path {
+        Some(p) => p,
+        None => {
+            eprintln!("{}", USAGE);
+            exit(2);
+        }
+    };
+
+    if let Err(e) = run(path, as_json) {
+        eprintln!("{}", e);
+        exit(1);
+    }
+}
@@ -0,0 +1,458 @@
+//! Renders a VST3 plugin offline, feeding it a Standard MIDI File and parameter automation and writing the output
+//! buses to WAV files.
+
+use hound::{SampleFormat, WavSpec, WavWriter};
+use midly::{MetaMessage, MidiMessage, Smf, Timing, TrackEventKind};
+use std::path::{Path, PathBuf};
+use std::process::exit;
+use uuid::Uuid;
+use vst3::audio_processor::{Event, EventData};
+use vst3::host::{ClassInfo, Controller};
+use vst3::midi_mapping::{MidiController, CTRL_AFTER_TOUCH, CTRL_PITCH_BEND};
+use vst3::plugin_module::PluginModule;
+use vst3::plugin_parameter::ParameterId;
+use vst3::sysex::SysEx;
+use vst3::test_host::{TestBlock, TestHost, TestSetup};
+use vst3::vst_factory::AUDIO_MODULE_CLASS;
+use vst3_sys::vst::{NoteOffEvent, NoteOnEvent, PolyPressureEvent, ProcessModes, SymbolicSampleSizes};
+
+const USAGE: &str = "Usage: vst3-render <plugin> <output.wav> [options]
+
+Options:
+  --midi <file.mid>        Standard MIDI File to play
+  --automation <file>      Parameter automation, one \"<seconds> <parameter id> <normalized value>\" per line
+  --param <id>=<value>     Sets a normalized parameter value before rendering, can be repeated
+  --class <name or cid>    Audio processor class to render, defaults to the first one
+  --sample-rate <rate>     Defaults to 48000
+  --block-size <samples>   Defaults to 512
+  --sample-size <32|64>    Floating point sample size used for processing, defaults to 32
+  --bits <16|24|32>        WAV sample format, 32 writes floating point, defaults to 32
+  --length <seconds>       Minimum length, defaults to the end of the MIDI file
+  --tail <seconds>         Extra time rendered after the end, defaults to 1";
+
+struct Args {
+    plugin:      PathBuf,
+    output:      PathBuf,
+    midi:        Option<PathBuf>,
+    automation:  Option<PathBuf>,
+    params:      Vec<(ParameterId, f64)>,
+    class:       Option<String>,
+    sample_rate: f64,
+    block_size:  usize,
+    double:      bool,
+    bits:        u16,
+    length:      f64,
+    tail:        f64,
+}
+
+fn parse_args() -> Result<Args, String> {
+    let mut args = std::env::args().skip(1);
+    let mut positional = Vec::new();
+
+    let mut result = Args {
+        plugin:      PathBuf::new(),
+        output:      PathBuf::new(),
+        midi:        None,
+        automation:  None,
+        params:      Vec::new(),
+        class:       None,
+        sample_rate: 48000.0,
+        block_size:  512,
+        double:      false,
+        bits:        32,
+        length:      0.0,
+        tail:        1.0,
+    };
+
+    while let Some(arg) = args.next() {
+        if !arg.starts_with("--") {
+            positional.push(arg);
+            continue;
+        }
+
+        let value = args.next().ok_or_else(|| format!("Missing value for {}", arg))?;
+        let number = |v: &str| v.parse::<f64>().map_err(|_| format!("Invalid value for {}: {}", arg, v));
+
+        match arg.as_str() {
+            "--midi" => result.midi = Some(value.into()),
+            "--automation" => result.automation = Some(value.into()),
+            "--class" => result.class = Some(value),
+            "--sample-rate" => result.sample_rate = number(&value)?,
+            "--block-size" => result.block_size = number(&value)?.max(1.0) as usize,
+            "--length" => result.length = number(&value)?,
+            "--tail" => result.tail = number(&value)?,
+
+            "--sample-size" => {
+                result.double = match value.as_str() {
+                    "32" => false,
+                    "64" => true,
+                    _ => return Err(format!("Invalid sample size: {}", value)),
+                }
+            }
+
+            "--bits" => {
+                result.bits = match value.as_str() {
+                    "16" => 16,
+                    "24" => 24,
+                    "32" => 32,
+                    _ => return Err(format!("Invalid bit depth: {}", value)),
+                }
+            }
+
+            "--param" => {
+                let (id, v) = value.split_once('=').ok_or_else(|| format!("Invalid parameter: {}", value))?;
+                let id = id.parse::<u32>().map_err(|_| format!("Invalid parameter id: {}", id))?;
+                result.params.push((id.into(), number(v)?));
+            }
+
+            _ => return Err(format!("Unknown option {}", arg)),
+        }
+    }
+
+    match positional.as_slice() {
+        [plugin, output] => {
+            result.plugin = plugin.into();
+            result.output = output.into();
+            Ok(result)
+        }
+
+        _ => Err("Expected a plugin and an output file".into()),
+    }
+}
+
+enum MidiInput {
+    Event(EventData<'static>),
+    Controller(i16, MidiController, f64),
+    Program(u8),
+}
+
+/// Returns the MIDI messages of all tracks with their time in seconds
+fn read_midi(path: &Path) -> Result<Vec<(f64, MidiInput)>, String> {
+    let data = std::fs::read(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    let smf = Smf::parse(&data).map_err(|e| format!("Failed to parse {}: {}", path.display(), e))?;
+    let mut events = Vec::new();
+
+    for track in smf.tracks.iter() {
+        let mut tick = 0u64;
+
+        for e in track.iter() {
+            tick += e.delta.as_int() as u64;
+            events.push((tick, e.kind));
+        }
+    }
+
+    events.sort_by_key(|(tick, _)| *tick);
+
+    let mut result = Vec::new();
+    let mut last_tick = 0;
+    let mut time = 0.0;
+    let mut seconds_per_tick = match smf.header.timing {
+        Timing::Metrical(tpb) => 0.5 / tpb.as_int() as f64,
+        Timing::Timecode(fps, subframes) => 1.0 / (fps.as_f32() as f64 * subframes as f64),
+    };
+
+    for (tick, kind) in events {
+        time += (tick - last_tick) as f64 * seconds_per_tick;
+        last_tick = tick;
+
+        let (channel, message) = match kind {
+            TrackEventKind::Meta(MetaMessage::Tempo(t)) => {
+                if let Timing::Metrical(tpb) = smf.header.timing {
+                    seconds_per_tick = t.as_int() as f64 / 1_000_000.0 / tpb.as_int() as f64;
+                }
+
+                continue;
+            }
+
+            TrackEventKind::Midi { channel, message } => (channel.as_int() as i16, message),
+
+            TrackEventKind::SysEx(data) => {
+                // The data of the file event doesn't include the start byte
+                let mut bytes = vec![0xF0];
+                bytes.extend_from_slice(data);
+
+                if bytes.last() != Some(&0xF7) {
+                    bytes.push(0xF7);
+                }
+
+                result.push((time, MidiInput::Event(EventData::SysEx(SysEx::new(bytes)))));
+                continue;
+            }
+
+            _ => continue,
+        };
+
+        let input = match message {
+            MidiMessage::NoteOn { key, vel } if vel.as_int() > 0 => MidiInput::Event(EventData::NoteOn(NoteOnEvent {
+                channel,
+                pitch: key.as_int() as i16,
+                tuning: 0.0,
+                velocity: vel.as_int() as f32 / 127.0,
+                length: 0,
+                note_id: -1,
+            })),
+
+            MidiMessage::NoteOn { key, vel } | MidiMessage::NoteOff { key, vel } => {
+                MidiInput::Event(EventData::NoteOff(NoteOffEvent {
+                    channel,
+                    pitch: key.as_int() as i16,
+                    velocity: vel.as_int() as f32 / 127.0,
+                    note_id: -1,
+                    tuning: 0.0,
+                }))
+            }
+
+            MidiMessage::Aftertouch { key, vel } => MidiInput::Event(EventData::PolyPressure(PolyPressureEvent {
+                channel,
+                pitch: key.as_int() as i16,
+                pressure: vel.as_int() as f32 / 127.0,
+                note_id: -1,
+            })),
+
+            MidiMessage::Controller { controller, value } => MidiInput::Controller(
+                channel,
+                MidiController::ControlChange(controller.as_int()),
+                value.as_int() as f64 / 127.0,
+            ),
+
+            MidiMessage::ChannelAftertouch { vel } => MidiInput::Controller(
+                channel,
+                MidiController::from_number(CTRL_AFTER_TOUCH).unwrap(),
+                vel.as_int() as f64 / 127.0,
+            ),
+
+            MidiMessage::PitchBend { bend } => MidiInput::Controller(
+                channel,
+                MidiController::from_number(CTRL_PITCH_BEND).unwrap(),
+                bend.0.as_int() as f64 / 16383.0,
+            ),
+
+            MidiMessage::ProgramChange { program } => MidiInput::Program(program.as_int()),
+        };
+
+        result.push((time, input));
+    }
+
+    Ok(result)
+}
+
+fn read_automation(path: &Path) -> Result<Vec<(f64, ParameterId, f64)>, String> {
+    let text = std::fs::read_to_string(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    let mut result = Vec::new();
+
+    for (i, line) in text.lines().enumerate() {
+        let line = line.trim();
+
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let fields = line.split_whitespace().collect::<Vec<_>>();
+        let error = || format!("Invalid automation on line {}: {}", i + 1, line);
+
+        match fields.as_slice() {
+            [time, id, value] => result.push((
+                time.parse().map_err(|_| error())?,
+                id.parse::<u32>().map_err(|_| error())?.into(),
+                value.parse().map_err(|_| error())?,
+            )),
+
+            _ => return Err(error()),
+        }
+    }
+
+    Ok(result)
+}
+
+fn find_class(classes: &[ClassInfo], name: Option<&str>) -> Option<Uuid> {
+    let mut processors = classes.iter().filter(|c| c.category == AUDIO_MODULE_CLASS);
+
+    match name {
+        Some(name) => processors.find(|c| c.name == name || c.cid.to_string().eq_ignore_ascii_case(name)),
+        None => processors.next(),
+    }
+    .map(|c| c.cid)
+}
+
+/// Converts MIDI input to events and parameter changes, controllers are mapped via the plugin `IMidiMapping`
+fn to_block_input(
+    controller: Option<&Controller>,
+    program_parameter: Option<(ParameterId, i32)>,
+    sample_offset: i32,
+    input: &MidiInput,
+) -> (Option<Event<'static>>, Option<(ParameterId, f64)>) {
+    match input {
+        MidiInput::Event(event) => (
+            Some(Event {
+                bus_index: 0,
+                sample_offset,
+                ppq_position: 0.0,
+                flags: 0,
+                event: event.clone(),
+            }),
+            None,
+        ),
+
+        MidiInput::Controller(channel, c, value) => (
+            None,
+            controller.and_then(|ctrl| ctrl.midi_controller_assignment(0, *channel, c.number())).map(|id| (id, *value)),
+        ),
+
+        MidiInput::Program(program) => {
+            (None, program_parameter.map(|(id, steps)| (id, (*program as f64 / steps.max(1) as f64).min(1.0))))
+        }
+    }
+}
+
+fn write_wav(path: &Path, channels: &[Vec<f64>], sample_rate: f64, bits: u16) -> Result<(), String> {
+    let spec = WavSpec {
+        channels:        channels.len() as u16,
+        sample_rate:     sample_rate.round() as u32,
+        bits_per_sample: bits,
+        sample_format:   if bits == 32 { SampleFormat::Float } else { SampleFormat::Int },
+    };
+
+    let error = |e: hound::Error| format!("Failed to write {}: {}", path.display(), e);
+    let mut writer = WavWriter::create(path, spec).map_err(error)?;
+    let length = channels.iter().map(|c| c.len()).max().unwrap_or(0);
+    let scale = ((1i64 << (bits - 1)) - 1) as f64;
+
+    for i in 0..length {
+        for c in channels.iter() {
+            let v = c.get(i).copied().unwrap_or(0.0);
+
+            if bits == 32 {
+                writer.write_sample(v as f32).map_err(error)?;
+            }
+            else {
+                writer.write_sample((v.clamp(-1.0, 1.0) * scale).round() as i32).map_err(error)?;
+            }
+        }
+    }
+
+    writer.finalize().map_err(error)
+}
+
+/// The main bus is written to `output`, further buses to `<output>-bus<index>.wav`
+fn bus_path(output: &Path, bus: usize) -> PathBuf {
+    if bus == 0 {
+        return output.into();
+    }
+
+    let stem = output.file_stem().map(|s| s.to_string_lossy().into_owned()).unwrap_or_default();
+    output.with_file_name(format!("{}-bus{}.wav", stem, bus))
+}
+
+fn render(args: &Args) -> Result<(), String> {
+    let midi = match args.midi.as_ref() {
+        Some(path) => read_midi(path)?,
+        None => Vec::new(),
+    };
+
+    let mut automation = match args.automation.as_ref() {
+        Some(path) => read_automation(path)?,
+        None => Vec::new(),
+    };
+
+    automation.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+    let module = PluginModule::load(&args.plugin).map_err(|e| e.to_string())?;
+    let host = TestHost::from_factory(module.factory().map_err(|e| e.to_string())?);
+    let cid = find_class(&host.classes(), args.class.as_deref()).ok_or("No matching audio processor class")?;
+    let mut plugin = host.create_plugin(cid).ok_or("Failed to create the plugin")?;
+
+    let program_parameter = plugin
+        .controller()
+        .and_then(|c| c.parameters().into_iter().find(|p| p.flags.is_program_change).map(|p| (p.id, p.step_count)));
+
+    for (id, value) in args.params.iter() {
+        plugin.set_parameter(*id, (*value).into());
+    }
+
+    let setup = TestSetup {
+        sample_rate:    args.sample_rate,
+        max_block_size: args.block_size,
+        sample_size:    if args.double { SymbolicSampleSizes::kSample64 } else { SymbolicSampleSizes::kSample32 },
+        process_mode:   ProcessModes::kOffline,
+    };
+
+    if !plugin.start(setup) {
+        return Err("Failed to start processing".into());
+    }
+
+    let end = midi.last().map(|(t, _)| *t).unwrap_or(0.0).max(automation.last().map(|a| a.0).unwrap_or(0.0));
+    let total = ((end.max(args.length) + args.tail) * args.sample_rate).ceil() as usize;
+    let mut outputs: Vec<Vec<Vec<f64>>> = Vec::new();
+    let (mut midi_pos, mut automation_pos, mut pos) = (0, 0, 0);
+
+    while pos < total {
+        let n = args.block_size.min(total - pos);
+        let block_end = (pos + n) as f64 / args.sample_rate;
+        let mut block = TestBlock::new(n);
+
+        while midi_pos < midi.len() && midi[midi_pos].0 < block_end {
+            let (time, input) = &midi[midi_pos];
+            let offset = sample_offset(*time, pos, n, args.sample_rate);
+
+            match to_block_input(plugin.controller(), program_parameter, offset, input) {
+                (Some(event), _) => block = block.event(event),
+                (_, Some((id, value))) => block = block.param_change(id, offset, value),
+                _ => {}
+            }
+
+            midi_pos += 1;
+        }
+
+        while automation_pos < automation.len() && automation[automation_pos].0 < block_end {
+            let (time, id, value) = automation[automation_pos];
+            block = block.param_change(id, sample_offset(time, pos, n, args.sample_rate), value);
+            automation_pos += 1;
+        }
+
+        let output = plugin.process(&block).ok_or("Not processing")?;
+
+        if !output.is_ok() {
+            return Err(format!("Process failed with {}", output.result));
+        }
+
+        outputs.resize_with(output.outputs.len(), Vec::new);
+
+        for (bus, channels) in output.outputs.into_iter().enumerate() {
+            outputs[bus].resize_with(channels.len(), Vec::new);
+
+            for (channel, samples) in channels.into_iter().enumerate() {
+                outputs[bus][channel].extend(samples);
+            }
+        }
+
+        pos += n;
+    }
+
+    plugin.stop();
+
+    for (bus, channels) in outputs.iter().enumerate() {
+        write_wav(&bus_path(&args.output, bus), channels, args.sample_rate, args.bits)?;
+    }
+
+    Ok(())
+}
+
+/// Converts a time in seconds to an offset within the block starting at sample `pos`
+fn sample_offset(time: f64, pos: usize, block_size: usize, sample_rate: f64) -> i32 {
+    ((time * sample_rate).round() as usize).saturating_sub(pos).min(block_size - 1) as i32
+}
+
+fn main() {
+    let args = match parse_args() {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("{}\n\n{}", e, USAGE);
+            exit(2);
+        }
+    };
+
+    if let Err(e) = render(&args) {
+        eprintln!("{}", e);
+        exit(1);
+    }
+}
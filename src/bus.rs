@@ -0,0 +1,84 @@
+use vst3_sys::vst::SpeakerArrangement;
+
+pub const SPEAKER_L: SpeakerArrangement = 1;
+pub const SPEAKER_R: SpeakerArrangement = 1 << 1;
+pub const SPEAKER_M: SpeakerArrangement = 1 << 19;
+
+pub const MONO: SpeakerArrangement = SPEAKER_M;
+pub const STEREO: SpeakerArrangement = SPEAKER_L | SPEAKER_R;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BusRole {
+    Main,
+
+    /// E.g. a sidechain input, inactive until the host activates it
+    Aux,
+}
+
+impl BusRole {
+    pub(crate) fn to_vst(self) -> i32 {
+        match self {
+            BusRole::Main => 0,
+            BusRole::Aux => 1,
+        }
+    }
+}
+
+/// An audio bus of a processor
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BusDeclaration {
+    pub name:                String,
+    pub role:                BusRole,
+    pub speaker_arrangement: SpeakerArrangement,
+
+    /// Tells the host to activate the bus by default
+    pub default_active: bool,
+}
+
+impl BusDeclaration {
+    pub fn main(name: &str, speaker_arrangement: SpeakerArrangement) -> Self {
+        Self {
+            name: name.into(),
+            role: BusRole::Main,
+            speaker_arrangement,
+            default_active: true,
+        }
+    }
+
+    pub fn aux(name: &str, speaker_arrangement: SpeakerArrangement) -> Self {
+        Self {
+            name: name.into(),
+            role: BusRole::Aux,
+            speaker_arrangement,
+            default_active: false,
+        }
+    }
+
+    pub fn channel_count(&self) -> i32 { self.speaker_arrangement.count_ones() as i32 }
+}
+
+/// An event bus of a processor, e.g. for receiving notes
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct EventBusDeclaration {
+    pub name:          String,
+    pub role:          BusRole,
+    pub channel_count: i32,
+}
+
+impl EventBusDeclaration {
+    pub fn main(name: &str, channel_count: i32) -> Self {
+        Self {
+            name: name.into(),
+            role: BusRole::Main,
+            channel_count,
+        }
+    }
+
+    pub fn aux(name: &str, channel_count: i32) -> Self {
+        Self {
+            name: name.into(),
+            role: BusRole::Aux,
+            channel_count,
+        }
+    }
+}
@@ -0,0 +1,234 @@
+use crate::audio_processor::{ProcessInput, ProcessOutput};
+use crate::plugin_parameter::ParameterId;
+use crate::sample::Sample;
+
+/// Length of the crossfade when bypass is toggled
+const FADE_SECONDS: f64 = 0.01;
+
+struct DelayLine {
+    buffer: Vec<f64>,
+    pos:    usize,
+}
+
+impl DelayLine {
+    fn new(length: usize) -> Self {
+        Self {
+            buffer: vec![0.0; length],
+            pos:    0,
+        }
+    }
+
+    fn clear(&mut self) {
+        self.buffer.iter_mut().for_each(|v| *v = 0.0);
+        self.pos = 0;
+    }
+
+    fn process(&mut self, v: f64) -> f64 {
+        if self.buffer.is_empty() {
+            return v;
+        }
+
+        let out = std::mem::replace(&mut self.buffer[self.pos], v);
+        self.pos = (self.pos + 1) % self.buffer.len();
+        out
+    }
+}
+
+/// Routes the inputs to the outputs when the bypass parameter is set, the dry signal is delayed by the latency of the
+/// processor and crossfaded with the processed signal when bypass is toggled
+pub(crate) struct Bypass {
+    parameter: ParameterId,
+    bypassed:  bool,
+
+    /// 0 is fully processed, 1 fully bypassed
+    fade:      f64,
+    fade_step: f64,
+    latency:   usize,
+    delays:    Vec<Vec<DelayLine>>,
+    dry:       Vec<Vec<Vec<f64>>>,
+}
+
+impl Bypass {
+    /// The buffers are allocated for the channel counts of the input buses and blocks of up to `max_block_size`
+    /// samples, so processing doesn't allocate unless the host passes larger blocks or more channels
+    pub fn new(
+        parameter: ParameterId,
+        latency: u32,
+        sample_rate: f64,
+        input_channels: &[usize],
+        max_block_size: usize,
+    ) -> Self {
+        let latency = latency as usize;
+
+        Self {
+            parameter,
+            bypassed: false,
+            fade: 0.0,
+            fade_step: 1.0 / (FADE_SECONDS * sample_rate).max(1.0),
+            latency,
+            delays: input_channels.iter().map(|n| (0..*n).map(|_| DelayLine::new(latency)).collect()).collect(),
+            dry: input_channels.iter().map(|n| (0..*n).map(|_| Vec::with_capacity(max_block_size)).collect()).collect(),
+        }
+    }
+
+    pub fn is_bypassed(&self) -> bool { self.bypassed }
+
+    /// Resets the bypass state and clears the delay lines, e.g. when the processor is activated
+    pub fn reset(&mut self, bypassed: bool) {
+        self.bypassed = bypassed;
+        self.fade = if bypassed { 1.0 } else { 0.0 };
+        self.delays.iter_mut().flatten().for_each(|d| d.clear());
+    }
+
+    fn is_active(&self) -> bool { self.bypassed || self.fade > 0.0 }
+
+    /// Reads the bypass state and stores the delayed dry signal, must be called before processing since the host may
+    /// use the same buffers for inputs and outputs
+    pub fn begin<T: Sample>(&mut self, input: &ProcessInput<T>) {
+        if let Some(v) = input.get_last_param_value(self.parameter) {
+            self.bypassed = *v >= 0.5;
+        }
+
+        // Only resized if the host doesn't pass the buses set up
+        self.delays.resize_with(input.buses.len(), Vec::new);
+        self.dry.resize_with(input.buses.len(), Vec::new);
+
+        for ((bus, delays), dry) in input.buses.iter().zip(self.delays.iter_mut()).zip(self.dry.iter_mut()) {
+            delays.resize_with(bus.channels.len(), || DelayLine::new(self.latency));
+            dry.resize_with(bus.channels.len(), Vec::new);
+
+            for ((channel, delay), dry) in bus.channels.iter().zip(delays.iter_mut()).zip(dry.iter_mut()) {
+                dry.clear();
+                dry.extend(channel.samples.iter().map(|s| delay.process(s.to_f64())));
+            }
+        }
+    }
+
+    /// Mixes the dry signal into the processed output according to the bypass state
+    pub fn end<T: Sample>(&mut self, output: &mut ProcessOutput<T>) {
+        if !self.is_active() {
+            return;
+        }
+
+        let target = if self.bypassed { 1.0 } else { 0.0 };
+        let mut fade = self.fade;
+
+        for (bi, bus) in output.buses().iter_mut().enumerate() {
+            for (ci, channel) in bus.channels().iter_mut().enumerate() {
+                let dry = self.dry.get(bi).and_then(|b| b.get(ci));
+                fade = self.fade;
+
+                for (i, s) in channel.samples.iter_mut().enumerate() {
+                    fade = step_towards(fade, target, self.fade_step);
+                    let d = dry.and_then(|d| d.get(i)).copied().unwrap_or(0.0);
+                    *s = T::from_f64(s.to_f64() * (1.0 - fade) + d * fade);
+                }
+
+                channel.is_silenced = false;
+            }
+        }
+
+        self.fade = fade;
+    }
+}
+
+fn step_towards(v: f64, target: f64, step: f64) -> f64 {
+    if v < target {
+        (v + step).min(target)
+    }
+    else {
+        (v - step).max(target)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audio_processor::{InBus, InChannel, OutBus, OutChannel};
+    use crate::bus::BusRole;
+    use crate::plugin_parameter::ParameterPoint;
+
+    const BYPASS_ID: u32 = 7;
+    const SAMPLE_RATE: f64 = 48000.0;
+
+    /// Runs a block through the bypass with a processor writing `processed` to every output sample
+    fn run(bypass: &mut Bypass, inputs: &[&[f32]], processed: f32, bypassed: Option<bool>) -> Vec<f32> {
+        let len = inputs.first().map_or(256, |i| i.len());
+        let mut input = ProcessInput::<f32>::for_events(len as u32, Vec::new(), Vec::new());
+
+        input.buses = inputs
+            .iter()
+            .map(|samples| InBus {
+                name:     "",
+                role:     BusRole::Main,
+                active:   true,
+                channels: vec![InChannel {
+                    is_silenced: false,
+                    samples:     *samples,
+                }],
+            })
+            .collect();
+
+        if let Some(b) = bypassed {
+            input.param_changes.insert(
+                BYPASS_ID.into(),
+                vec![ParameterPoint {
+                    sample_offset: 0,
+                    value:         if b { 1.0f64 } else { 0.0 }.into(),
+                }],
+            );
+        }
+
+        let mut samples = vec![processed; len];
+        let mut output = ProcessOutput::new(vec![OutBus::new(vec![OutChannel {
+            is_silenced: false,
+            samples:     &mut samples,
+        }])]);
+
+        bypass.begin(&input);
+        bypass.end(&mut output);
+        drop(output);
+        samples
+    }
+
+    #[test]
+    fn dry_signal_is_delayed_by_latency() {
+        let mut bypass = Bypass::new(BYPASS_ID.into(), 10, SAMPLE_RATE, &[1], 64);
+        bypass.reset(true);
+
+        let mut impulse = [0.0; 64];
+        impulse[0] = 1.0;
+
+        let out = run(&mut bypass, &[&impulse], 0.0, None);
+        assert_eq!(out.iter().position(|s| *s != 0.0), Some(10));
+        assert_eq!(out[10], 1.0);
+    }
+
+    #[test]
+    fn fade_completes_within_fade_seconds() {
+        let fade_len = (FADE_SECONDS * SAMPLE_RATE) as usize;
+        let dry = vec![1.0; fade_len + 20];
+        let mut bypass = Bypass::new(BYPASS_ID.into(), 0, SAMPLE_RATE, &[1], dry.len());
+        bypass.reset(false);
+
+        // The processed signal is silent, so the output is the fade position
+        let out = run(&mut bypass, &[&dry], 0.0, Some(true));
+        assert!(out[0] > 0.0 && out[0] < 0.01);
+        assert!((out[fade_len - 1] - 1.0).abs() < 1e-4);
+        assert!(out[fade_len..].iter().all(|s| *s == 1.0));
+
+        let out = run(&mut bypass, &[&dry], 0.0, Some(false));
+        assert!(out[fade_len - 1].abs() < 1e-4);
+        assert!(out[fade_len..].iter().all(|s| *s == 0.0));
+        assert!(!bypass.is_active());
+    }
+
+    #[test]
+    fn instrument_without_inputs_is_silent_when_bypassed() {
+        let mut bypass = Bypass::new(BYPASS_ID.into(), 0, SAMPLE_RATE, &[], 256);
+        bypass.reset(true);
+
+        let out = run(&mut bypass, &[], 0.5, None);
+        assert!(out.iter().all(|s| *s == 0.0));
+    }
+}
@@ -0,0 +1,38 @@
+use std::{cell::RefCell, rc::Rc};
+use vst3_sys::base::kResultOk;
+use vst3_sys::utils::VstPtr;
+use vst3_sys::vst::{IComponentHandler, RestartFlags};
+
+use crate::plugin_parameter::{NormalizedParameterValue, ParameterId};
+
+/// Shared handle to the host component handler, calls are ignored until the host has set the handler
+#[derive(Clone, Default)]
+pub struct ComponentHandler {
+    handler: Rc<RefCell<Option<VstPtr<dyn IComponentHandler>>>>,
+}
+
+impl ComponentHandler {
+    pub(crate) fn set(&self, handler: Option<VstPtr<dyn IComponentHandler>>) { self.handler.replace(handler); }
+
+    pub fn is_connected(&self) -> bool { self.handler.borrow().is_some() }
+
+    pub fn begin_edit(&self, id: ParameterId) -> bool {
+        self.handler.borrow().as_ref().map(|h| unsafe { h.begin_edit(*id) } == kResultOk).unwrap_or(false)
+    }
+
+    pub fn perform_edit(&self, id: ParameterId, value: NormalizedParameterValue) -> bool {
+        self.handler.borrow().as_ref().map(|h| unsafe { h.perform_edit(*id, *value) } == kResultOk).unwrap_or(false)
+    }
+
+    pub fn end_edit(&self, id: ParameterId) -> bool {
+        self.handler.borrow().as_ref().map(|h| unsafe { h.end_edit(*id) } == kResultOk).unwrap_or(false)
+    }
+
+    pub(crate) fn restart_component(&self, flags: RestartFlags) -> bool {
+        self.handler
+            .borrow()
+            .as_ref()
+            .map(|h| unsafe { h.restart_component(flags as i32) } == kResultOk)
+            .unwrap_or(false)
+    }
+}
@@ -0,0 +1,355 @@
+//! Conformance checks modelled on the VST3 SDK validator, a plugin crate can run them from its tests with
+//! `conformance::run(&TestHost::new(factory)).assert_ok()`
+
+use crate::edit_controller::{BusDirection, MediaType};
+use crate::host::ClassInfo;
+use crate::test_host::{TestBlock, TestHost, TestPlugin, TestSetup};
+use crate::vst_factory::AUDIO_MODULE_CLASS;
+use std::collections::HashSet;
+use std::fmt::{Display, Formatter};
+use uuid::Uuid;
+use vst3_sys::vst::{IComponent, IHostApplication, SymbolicSampleSizes, K_SAMPLE32, K_SAMPLE64};
+
+const BLOCK_SIZE: usize = 64;
+
+type CheckResult = Result<(), String>;
+
+fn ensure(condition: bool, message: impl FnOnce() -> String) -> CheckResult {
+    if condition {
+        Ok(())
+    }
+    else {
+        Err(message())
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct CheckFailure {
+    pub check:   &'static str,
+    pub message: String,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct ConformanceReport {
+    pub passed:   Vec<&'static str>,
+    pub failures: Vec<CheckFailure>,
+}
+
+impl ConformanceReport {
+    fn record(&mut self, check: &'static str, result: CheckResult) {
+        match result {
+            Ok(()) => self.passed.push(check),
+            Err(message) => self.failures.push(CheckFailure { check, message }),
+        }
+    }
+
+    pub fn is_ok(&self) -> bool { self.failures.is_empty() }
+
+    /// Panics with a list of all failed checks
+    pub fn assert_ok(&self) {
+        if !self.is_ok() {
+            panic!("{}", self)
+        }
+    }
+}
+
+impl Display for ConformanceReport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "{} checks passed, {} failed", self.passed.len(), self.failures.len())?;
+
+        for failure in self.failures.iter() {
+            writeln!(f, "  {}: {}", failure.check, failure.message)?;
+        }
+
+        Ok(())
+    }
+}
+
+const PROCESSOR_CHECKS: &[(&str, fn(&TestHost, &ClassInfo) -> CheckResult)] = &[
+    ("create instance", check_create_instance),
+    ("initialize after terminate", check_initialize_after_terminate),
+    ("bus consistency", check_buses),
+    ("state round trip", check_state),
+    ("parameter info", check_parameters),
+    ("unit info", check_units),
+    ("process zero samples", check_process_zero_samples),
+    ("silence flags", check_silence_flags),
+    ("sample sizes", check_sample_sizes),
+];
+
+/// Runs all checks for the factory and each of its audio processor classes
+pub fn run(host: &TestHost) -> ConformanceReport {
+    let mut report = ConformanceReport::default();
+    report.record("factory info", check_factory_info(host));
+    report.record("class info", check_class_info(host));
+
+    for class in host.classes().iter().filter(|c| c.category == AUDIO_MODULE_CLASS) {
+        for (name, check) in PROCESSOR_CHECKS {
+            report.record(*name, check(host, class).map_err(|e| format!("{}: {}", class.name, e)));
+        }
+    }
+
+    report
+}
+
+fn check_factory_info(host: &TestHost) -> CheckResult {
+    let info = host.factory().info().ok_or("get_factory_info failed")?;
+    ensure(!info.vendor.is_empty(), || "Empty vendor".into())?;
+    ensure(!host.classes().is_empty(), || "No classes".into())
+}
+
+fn check_class_info(host: &TestHost) -> CheckResult {
+    let classes = host.classes();
+    let mut cids = HashSet::new();
+
+    for c in classes.iter() {
+        ensure(cids.insert(c.cid), || format!("Duplicate class id {}", c.cid))?;
+        ensure(!c.name.is_empty(), || format!("Class {} has no name", c.cid))?;
+        ensure(!c.category.is_empty(), || format!("Class {} has no category", c.name))?;
+    }
+
+    Ok(())
+}
+
+fn create_plugin(host: &TestHost, class: &ClassInfo) -> Result<TestPlugin, String> {
+    host.create_plugin(class.cid).ok_or_else(|| "Failed to create and initialize".into())
+}
+
+fn start(plugin: &mut TestPlugin, sample_size: SymbolicSampleSizes) -> CheckResult {
+    let setup = TestSetup {
+        max_block_size: BLOCK_SIZE,
+        sample_size,
+        ..Default::default()
+    };
+
+    ensure(plugin.start(setup), || "Failed to start processing".into())
+}
+
+fn check_create_instance(host: &TestHost, class: &ClassInfo) -> CheckResult {
+    let factory = host.factory();
+    ensure(factory.create_instance::<dyn IComponent>(class.cid).is_some(), || "IComponent not created".into())?;
+
+    // A plugin never implements the host interface so the factory must respect the requested IID
+    ensure(factory.create_instance::<dyn IHostApplication>(class.cid).is_none(), || {
+        "Returned an object for an unsupported interface".into()
+    })?;
+
+    ensure(factory.create_instance::<dyn IComponent>(Uuid::nil()).is_none(), || {
+        "Returned an object for an unknown class id".into()
+    })
+}
+
+fn check_initialize_after_terminate(host: &TestHost, class: &ClassInfo) -> CheckResult {
+    let component = host.factory().create_component(class.cid).ok_or("IComponent not created")?;
+    let bus_counts = || {
+        [BusDirection::Input, BusDirection::Output]
+            .map(|dir| (component.bus_count(MediaType::Audio, dir), component.bus_count(MediaType::Event, dir)))
+    };
+
+    ensure(component.initialize(), || "First initialize failed".into())?;
+    let counts = bus_counts();
+    ensure(component.terminate(), || "Terminate failed".into())?;
+    ensure(component.initialize(), || "Initialize after terminate failed".into())?;
+    let new_counts = bus_counts();
+    component.terminate();
+    ensure(counts == new_counts, || format!("Bus counts changed from {:?} to {:?}", counts, new_counts))
+}
+
+fn check_buses(host: &TestHost, class: &ClassInfo) -> CheckResult {
+    let plugin = create_plugin(host, class)?;
+    let component = plugin.component();
+
+    for media_type in [MediaType::Audio, MediaType::Event] {
+        for dir in [BusDirection::Input, BusDirection::Output] {
+            let count = component.bus_count(media_type, dir);
+            let mut aux_seen = false;
+
+            for i in 0..count {
+                let info = component.bus_info(media_type, dir, i).ok_or_else(|| format!("No info for bus {}", i))?;
+                ensure(info.media_type == media_type as i32 && info.direction == dir as i32, || {
+                    format!("Bus {} reports the wrong media type or direction", i)
+                })?;
+
+                ensure(!info.name.is_empty(), || format!("Bus {} has no name", i))?;
+                ensure(!(aux_seen && info.bus_type == 0), || format!("Main bus {} after an aux bus", i))?;
+                aux_seen |= info.bus_type != 0;
+
+                if media_type == MediaType::Audio {
+                    if let Some(arr) = component.bus_arrangement(dir, i) {
+                        ensure(arr.count_ones() as i32 == info.channel_count, || {
+                            format!("Bus {} arrangement doesn't match its channel count", i)
+                        })?;
+                    }
+                }
+            }
+
+            ensure(component.bus_info(media_type, dir, count).is_none(), || "Info for a bus out of range".into())?;
+            ensure(component.bus_info(media_type, dir, -1).is_none(), || "Info for a negative bus index".into())?;
+        }
+    }
+
+    Ok(())
+}
+
+fn check_state(host: &TestHost, class: &ClassInfo) -> CheckResult {
+    let plugin = create_plugin(host, class)?;
+    let component = plugin.component();
+    let state = component.get_state().ok_or("Component get_state failed")?;
+    ensure(component.set_state(&state), || "Component set_state failed".into())?;
+    let new_state = component.get_state().ok_or("Component get_state failed")?;
+    ensure(state == new_state, || "Component state changed after a round trip".into())?;
+
+    if let Some(controller) = plugin.controller() {
+        ensure(controller.set_component_state(&state), || "Controller set_component_state failed".into())?;
+
+        if let Some(state) = controller.get_state() {
+            ensure(controller.set_state(&state), || "Controller set_state failed".into())?;
+            let new_state = controller.get_state().ok_or("Controller get_state failed")?;
+            ensure(state == new_state, || "Controller state changed after a round trip".into())?;
+        }
+    }
+
+    Ok(())
+}
+
+fn check_parameters(host: &TestHost, class: &ClassInfo) -> CheckResult {
+    let plugin = create_plugin(host, class)?;
+
+    let controller = match plugin.controller() {
+        Some(c) => c,
+        None => return Ok(()),
+    };
+
+    let params = controller.parameters();
+    ensure(params.len() as i32 == controller.parameter_count(), || "Missing parameter info".into())?;
+    let mut ids = HashSet::new();
+
+    for p in params.iter() {
+        let value = controller.get_normalized(p.id);
+        let name = || format!("{} ({})", p.title, *p.id);
+
+        ensure(ids.insert(p.id), || format!("Duplicate parameter id {}", *p.id))?;
+        ensure(!p.title.is_empty(), || format!("Parameter {} has no title", *p.id))?;
+        ensure(p.step_count >= 0, || format!("{} has a negative step count", name()))?;
+        ensure((0.0..=1.0).contains(&*p.default_normalized_value), || format!("{} default out of range", name()))?;
+        ensure((0.0..=1.0).contains(&*value), || format!("{} value out of range", name()))?;
+        ensure(!p.flags.is_bypass || p.step_count == 1, || format!("{} is a bypass but not a toggle", name()))?;
+        ensure(!p.flags.is_program_change || p.step_count > 0, || {
+            format!("{} is a program change without steps", name())
+        })?;
+        ensure(controller.value_to_string(p.id, value).is_some(), || format!("{} has no value string", name()))?;
+    }
+
+    ensure(params.iter().filter(|p| p.flags.is_bypass).count() <= 1, || "More than one bypass parameter".into())
+}
+
+fn check_units(host: &TestHost, class: &ClassInfo) -> CheckResult {
+    let plugin = create_plugin(host, class)?;
+
+    let controller = match plugin.controller() {
+        Some(c) => c,
+        None => return Ok(()),
+    };
+
+    let units = controller.units();
+    let lists = controller.program_lists();
+    let ids = units.iter().map(|u| u.id).collect::<HashSet<_>>();
+    ensure(ids.len() == units.len(), || "Duplicate unit id".into())?;
+
+    for u in units.iter() {
+        ensure(*u.parent_unit_id < 0 || ids.contains(&u.parent_unit_id), || {
+            format!("Unit {} has a missing parent", u.name)
+        })?;
+
+        ensure(*u.program_list_id < 0 || lists.iter().any(|l| l.id == u.program_list_id), || {
+            format!("Unit {} has a missing program list", u.name)
+        })?;
+    }
+
+    if !units.is_empty() {
+        for p in controller.parameters() {
+            ensure(ids.contains(&p.unit_id), || format!("Parameter {} has a missing unit", p.title))?;
+        }
+    }
+
+    Ok(())
+}
+
+fn supported_sample_size(plugin: &TestPlugin) -> SymbolicSampleSizes {
+    if plugin.component().can_process_sample_size(K_SAMPLE32) {
+        SymbolicSampleSizes::kSample32
+    }
+    else {
+        SymbolicSampleSizes::kSample64
+    }
+}
+
+fn check_process_zero_samples(host: &TestHost, class: &ClassInfo) -> CheckResult {
+    let mut plugin = create_plugin(host, class)?;
+    start(&mut plugin, supported_sample_size(&plugin))?;
+    let output = plugin.process(&TestBlock::new(0)).ok_or("Not processing")?;
+    ensure(output.is_ok(), || format!("Process failed with {}", output.result))
+}
+
+fn check_silence_flags(host: &TestHost, class: &ClassInfo) -> CheckResult {
+    let mut plugin = create_plugin(host, class)?;
+    start(&mut plugin, supported_sample_size(&plugin))?;
+
+    let inputs = plugin.component().buses(MediaType::Audio, BusDirection::Input);
+    let outputs = plugin.component().buses(MediaType::Audio, BusDirection::Output);
+    let mut block = TestBlock::new(BLOCK_SIZE);
+
+    for (i, bus) in inputs.iter().enumerate() {
+        block = block.input_silence_flags(i, channel_mask(bus.channel_count));
+    }
+
+    let output = plugin.process(&block).ok_or("Not processing")?;
+    ensure(output.is_ok(), || format!("Process with silent input failed with {}", output.result))?;
+
+    for (i, (flags, bus)) in output.silence_flags.iter().zip(outputs.iter()).enumerate() {
+        ensure(flags & !channel_mask(bus.channel_count) == 0, || {
+            format!("Output bus {} has silence flags for missing channels", i)
+        })?;
+
+        // Channels flagged as silent must really be silent
+        for (ci, samples) in output.outputs.get(i).into_iter().flatten().enumerate().take(64) {
+            ensure(flags & (1u64 << ci) == 0 || samples.iter().all(|s| *s == 0.0), || {
+                format!("Output bus {} channel {} is flagged as silent but isn't", i, ci)
+            })?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Invalid negative channel counts give an empty mask
+fn channel_mask(channel_count: i32) -> u64 {
+    match channel_count.clamp(0, 64) {
+        64 => u64::MAX,
+        n => (1 << n) - 1,
+    }
+}
+
+fn check_sample_sizes(host: &TestHost, class: &ClassInfo) -> CheckResult {
+    let mut plugin = create_plugin(host, class)?;
+
+    for (size, sample_size) in [
+        (K_SAMPLE32, SymbolicSampleSizes::kSample32),
+        (K_SAMPLE64, SymbolicSampleSizes::kSample64),
+    ] {
+        if !plugin.component().can_process_sample_size(size) {
+            continue;
+        }
+
+        start(&mut plugin, sample_size)?;
+        let output = plugin.process(&TestBlock::new(BLOCK_SIZE)).ok_or("Not processing")?;
+        ensure(output.is_ok(), || format!("Process failed with {}", output.result))?;
+
+        ensure(output.outputs.iter().flatten().flatten().all(|s| s.is_finite()), || {
+            format!("Non finite output with sample size {}", size)
+        })?;
+    }
+
+    plugin.stop();
+    Ok(())
+}
@@ -1,28 +1,28 @@
 use crate::{
+    editor::{Editor, EditorContext},
+    generic_editor::GenericEditor,
+    keyswitch::KeyswitchInfo,
+    midi_learn::MidiLearn,
+    midi_mapping::MidiMapping,
+    note_expression::{NoteExpressionTypeId, NoteExpressionTypeInfo, PhysicalUiMapping},
     plugin::Parameters,
     plugin::{Plugin, State},
     plugin_parameter::{NormalizedParameterValue, ParameterId, ParameterInfo, ParameterValue},
+    program::{LoadedPrograms, ProgramLists},
     type_cell::TypeCell,
+    unit::UnitTree,
     vst_stream::VstInStream,
 };
 
-#[derive(Clone, Copy, Default, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
-pub struct UnitIdTag;
-
-pub type UnitId = TypeCell<UnitIdTag, i32>;
-
-pub struct UnitInfo {
-    pub id:              UnitId,
-    pub parent_unit_id:  UnitId,
-    pub name:            String,
-    pub program_list_id: ProgramListId,
-}
+pub use crate::unit::{UnitId, UnitIdTag, UnitInfo};
+use std::io::{Error, ErrorKind};
 
 #[derive(Clone, Copy, Default, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct ProgramListIdTag;
 
 pub type ProgramListId = TypeCell<ProgramListIdTag, i32>;
 
+#[derive(Clone)]
 pub struct ProgramListInfo {
     pub id:            ProgramListId,
     pub name:          String,
@@ -35,11 +35,13 @@ pub enum KnobMode {
     Linear,
 }
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum BusDirection {
     Input,
     Output,
 }
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum MediaType {
     Audio,
     Event,
@@ -51,6 +53,10 @@ pub trait EditController: Plugin + Parameters + State {
     fn set_component_state(&self, stream: &mut VstInStream) -> std::io::Result<()>;
 
     fn normalized_parameter_value_to_string(&self, param: &ParameterInfo, value: NormalizedParameterValue) -> String {
+        if let Some(s) = param.get_value_string(value) {
+            return s.into();
+        }
+
         format!("{:.1} {}", param.normalized_to_plain_converter.convert(value).get(), param.units)
     }
 
@@ -59,9 +65,24 @@ pub trait EditController: Plugin + Parameters + State {
         param: &ParameterInfo,
         value: &str,
     ) -> Option<NormalizedParameterValue> {
+        if !param.value_strings.is_empty() {
+            return param.find_value_string(value);
+        }
+
         value.parse::<ParameterValue>().ok().map(|v| v.into())
     }
 
+    /// Override to provide a custom plugin editor, `context` should be kept by the editor for host edits.
+    /// By default a generic editor containing all parameters is created on supported platforms.
+    fn create_editor(&self, context: EditorContext) -> Option<Box<dyn Editor>> {
+        if GenericEditor::is_supported() {
+            Some(Box::new(GenericEditor::new(context)))
+        }
+        else {
+            None
+        }
+    }
+
     // IEditController2 methods
 
     fn set_knob_mode(&self, mode: KnobMode) -> bool { false }
@@ -72,7 +93,10 @@ pub trait EditController: Plugin + Parameters + State {
 
     // IUnitInfo methods, if you only have one unit these don't have to be overridden
 
-    fn get_units(&self) -> Option<&[UnitInfo]> { None }
+    /// If overridden `get_units` doesn't have to be overridden
+    fn get_unit_tree(&self) -> Option<&UnitTree> { None }
+
+    fn get_units(&self) -> Option<&[UnitInfo]> { self.get_unit_tree().map(|t| t.units()) }
     fn get_selected_unit(&self) -> UnitId { 0.into() }
     fn select_unit(&self, id: UnitId) -> bool { false }
     fn get_unit_by_bus(&self, type_: MediaType, dir: BusDirection, index: i32, channel: i32) -> Option<UnitId> { None }
@@ -90,15 +114,57 @@ pub trait EditController: Plugin + Parameters + State {
         None
     }
 
-    fn get_program_lists(&self) -> Option<&[ProgramListInfo]> { None }
-    fn get_program_name(&self, program_list: &ProgramListInfo, program_index: i32) -> &str { "" }
+    /// If overridden the program list methods below don't have to be overridden
+    fn get_programs(&self) -> Option<&ProgramLists> { None }
+
+    fn get_program_lists(&self) -> Option<&[ProgramListInfo]> { self.get_programs().map(|p| p.infos()) }
+
+    fn get_program_name(&self, program_list: &ProgramListInfo, program_index: i32) -> &str {
+        self.get_programs()
+            .and_then(|p| p.get(program_list.id))
+            .and_then(|l| l.get_program(program_index))
+            .map(|p| p.name.as_str())
+            .unwrap_or("")
+    }
 
     fn get_program_info(&self, program_list: &ProgramListInfo, program_index: i32, attribute_id: &str) -> Option<&str> {
-        None
+        self.get_programs()
+            .and_then(|p| p.get(program_list.id))
+            .and_then(|l| l.get_program(program_index))
+            .and_then(|p| p.get_attribute(attribute_id))
+    }
+
+    /// Keeps the program data loaded by the host for this instance, needed to support `set_unit_program_data`
+    fn get_loaded_programs(&self) -> Option<&LoadedPrograms> { None }
+
+    /// Loads program data provided by the host, `list_or_unit_id` is either a program list id or a unit id. The data
+    /// is stored in `get_loaded_programs`, it's applied when the program is selected.
+    fn set_unit_program_data(
+        &self,
+        list_or_unit_id: i32,
+        program_index: i32,
+        stream: &mut VstInStream,
+    ) -> std::io::Result<()> {
+        let loaded = self.get_loaded_programs().ok_or_else(|| Error::from(ErrorKind::Unsupported))?;
+
+        let list = self
+            .get_programs()
+            .and_then(|p| p.get_by_list_or_unit(list_or_unit_id, self.get_units()))
+            .ok_or_else(|| Error::from(ErrorKind::NotFound))?;
+
+        loaded.set(list.info.id, program_index, list.read_program_data(program_index, stream)?);
+        Ok(())
+    }
+
+    /// By default the keys of the keyswitches of the first event bus channel are named
+    fn has_program_pitch_names(&self, program_list: &ProgramListInfo, index: i32) -> bool {
+        !self.get_keyswitches(0, 0).is_empty()
     }
 
-    fn has_program_pitch_names(&self, program_list: &ProgramListInfo, index: i32) -> bool { false }
-    fn get_program_pitch_name(&self, program_list: &ProgramListInfo, index: i32, pitch: i16) -> &str { "" }
+    /// An empty name means the pitch has no name
+    fn get_program_pitch_name(&self, program_list: &ProgramListInfo, index: i32, pitch: i16) -> &str {
+        self.get_keyswitches(0, 0).iter().find(|k| k.keys.contains(&pitch)).map_or("", |k| k.title.as_str())
+    }
 
     // IEditControllerHostEditing methods
 
@@ -107,7 +173,54 @@ pub trait EditController: Plugin + Parameters + State {
 
     // IMidiMapping methods
 
+    /// If overridden `get_midi_controller_assignment` doesn't have to be overridden
+    fn get_midi_mapping(&self) -> Option<&MidiMapping> { None }
+
+    /// Enables MIDI learn of the raw controllers of the MIDI mapping, the assignments are saved in a block ahead of
+    /// the controller state written by `get_state` and restored before `set_state`
+    fn get_midi_learn(&self) -> Option<&MidiLearn> { None }
+
     fn get_midi_controller_assignment(&self, bus_index: i32, channel: i16, midi_cc_number: i16) -> Option<ParameterId> {
-        None
+        self.get_midi_learn()
+            .and_then(|l| l.get_assignment(bus_index, channel, midi_cc_number))
+            .or_else(|| self.get_midi_mapping().and_then(|m| m.get_assignment(bus_index, channel, midi_cc_number)))
+    }
+
+    // INoteExpressionController methods
+
+    /// The note expression types supported on a channel of an event input bus, hosts only send these
+    fn get_note_expressions(&self, bus_index: i32, channel: i16) -> &[NoteExpressionTypeInfo] { &[] }
+
+    fn get_note_expression(
+        &self,
+        bus_index: i32,
+        channel: i16,
+        id: NoteExpressionTypeId,
+    ) -> Option<&NoteExpressionTypeInfo> {
+        self.get_note_expressions(bus_index, channel).iter().find(|e| e.id == id)
+    }
+
+    fn note_expression_value_to_string(
+        &self,
+        info: &NoteExpressionTypeInfo,
+        value: NormalizedParameterValue,
+    ) -> String {
+        info.value_to_string(value)
     }
+
+    fn string_to_note_expression_value(
+        &self,
+        info: &NoteExpressionTypeInfo,
+        value: &str,
+    ) -> Option<NormalizedParameterValue> {
+        info.string_to_value(value)
+    }
+
+    // IKeyswitchController methods
+
+    fn get_keyswitches(&self, bus_index: i32, channel: i16) -> &[KeyswitchInfo] { &[] }
+
+    // INoteExpressionPhysicalUIMapping methods
+
+    fn get_physical_ui_mapping(&self, bus_index: i32, channel: i16) -> &[PhysicalUiMapping] { &[] }
 }
@@ -0,0 +1,132 @@
+use std::{os::raw::c_void, rc::Rc};
+
+use crate::{component_handler::ComponentHandler, edit_controller::EditController};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Platform {
+    Hwnd,
+    NsView,
+    X11EmbedWindowId,
+}
+
+impl Platform {
+    pub fn from_type_string(s: &str) -> Option<Self> {
+        match s {
+            "HWND" => Some(Platform::Hwnd),
+            "NSView" => Some(Platform::NsView),
+            "X11EmbedWindowID" => Some(Platform::X11EmbedWindowId),
+            _ => None,
+        }
+    }
+
+    pub fn native() -> Self {
+        if cfg!(target_os = "windows") {
+            Platform::Hwnd
+        }
+        else if cfg!(target_os = "macos") {
+            Platform::NsView
+        }
+        else {
+            Platform::X11EmbedWindowId
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum ParentWindow {
+    Hwnd(*mut c_void),
+    NsView(*mut c_void),
+    X11EmbedWindowId(u32),
+}
+
+impl ParentWindow {
+    pub(crate) fn new(platform: Platform, handle: *mut c_void) -> Self {
+        match platform {
+            Platform::Hwnd => ParentWindow::Hwnd(handle),
+            Platform::NsView => ParentWindow::NsView(handle),
+            Platform::X11EmbedWindowId => ParentWindow::X11EmbedWindowId(handle as usize as u32),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Default, Debug, PartialEq, Eq)]
+pub struct ViewRect {
+    pub left:   i32,
+    pub top:    i32,
+    pub right:  i32,
+    pub bottom: i32,
+}
+
+impl ViewRect {
+    pub fn new(width: i32, height: i32) -> Self {
+        Self {
+            left:   0,
+            top:    0,
+            right:  width,
+            bottom: height,
+        }
+    }
+
+    pub fn width(&self) -> i32 { self.right - self.left }
+    pub fn height(&self) -> i32 { self.bottom - self.top }
+}
+
+#[derive(Clone, Copy, Default, Debug, PartialEq, Eq)]
+pub struct KeyModifiers {
+    pub shift:     bool,
+    pub alternate: bool,
+    pub command:   bool,
+    pub control:   bool,
+}
+
+impl KeyModifiers {
+    pub(crate) fn from_bits(bits: i16) -> Self {
+        Self {
+            shift:     bits & 1 != 0,
+            alternate: bits & 2 != 0,
+            command:   bits & 4 != 0,
+            control:   bits & 8 != 0,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct KeyEvent {
+    pub character: Option<char>,
+    pub key_code:  i16,
+    pub modifiers: KeyModifiers,
+}
+
+/// Passed to `EditController::create_editor`, gives the editor access to parameter values and host edits
+#[derive(Clone)]
+pub struct EditorContext {
+    pub controller:        Rc<dyn EditController>,
+    pub component_handler: ComponentHandler,
+}
+
+#[allow(unused_variables)]
+pub trait Editor {
+    fn is_platform_supported(&self, platform: Platform) -> bool { platform == Platform::native() }
+
+    /// Should create the editor window as a child of `parent`
+    fn attached(&self, parent: ParentWindow) -> bool;
+
+    /// Should destroy the editor window
+    fn removed(&self);
+
+    fn get_size(&self) -> ViewRect;
+    fn on_size(&self, new_size: ViewRect) -> bool { false }
+    fn can_resize(&self) -> bool { false }
+
+    /// Returns the closest size supported by the editor
+    fn check_size_constraint(&self, rect: ViewRect) -> ViewRect { self.get_size() }
+
+    fn set_content_scale_factor(&self, factor: f32) -> bool { false }
+    fn on_key_down(&self, key: KeyEvent) -> bool { false }
+    fn on_key_up(&self, key: KeyEvent) -> bool { false }
+    fn on_wheel(&self, distance: f32) -> bool { false }
+    fn on_focus(&self, state: bool) {}
+
+    /// Called periodically from the host UI thread while attached (currently only on Linux)
+    fn on_idle(&self) {}
+}
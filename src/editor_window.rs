@@ -0,0 +1,217 @@
+pub(crate) enum WindowEvent {
+    MouseDown {
+        x: i32,
+        y: i32,
+    },
+    MouseUp {
+        x: i32,
+        y: i32,
+    },
+    MouseMove {
+        x: i32,
+        y: i32,
+    },
+    Wheel {
+        x:        i32,
+        y:        i32,
+        distance: f32,
+    },
+    Expose,
+}
+
+#[cfg(target_os = "linux")]
+mod platform {
+    use super::WindowEvent;
+    use crate::{editor::ParentWindow, generic_editor::Canvas};
+    use std::{
+        mem::MaybeUninit,
+        os::raw::{c_char, c_void},
+        ptr::{null, null_mut},
+    };
+    use x11::xlib;
+
+    /// Child window embedded into the host provided X11 window, drawn by blitting a canvas
+    pub(crate) struct EditorWindow {
+        display: *mut xlib::Display,
+        window:  xlib::Window,
+        gc:      xlib::GC,
+        image:   *mut xlib::XImage,
+        buffer:  Vec<u32>,
+        width:   i32,
+        height:  i32,
+    }
+
+    impl EditorWindow {
+        pub(crate) fn is_supported() -> bool { true }
+
+        pub(crate) fn open(parent: ParentWindow, width: i32, height: i32) -> Option<Self> {
+            let parent = match parent {
+                ParentWindow::X11EmbedWindowId(id) => id as xlib::Window,
+                _ => return None,
+            };
+
+            unsafe {
+                let display = xlib::XOpenDisplay(null());
+
+                if display.is_null() {
+                    return None;
+                }
+
+                let window = xlib::XCreateSimpleWindow(display, parent, 0, 0, width as u32, height as u32, 0, 0, 0);
+
+                xlib::XSelectInput(
+                    display,
+                    window,
+                    xlib::ExposureMask | xlib::ButtonPressMask | xlib::ButtonReleaseMask | xlib::PointerMotionMask,
+                );
+
+                xlib::XMapWindow(display, window);
+                let gc = xlib::XCreateGC(display, window, 0, null_mut());
+
+                let mut w = Self {
+                    display,
+                    window,
+                    gc,
+                    image: null_mut(),
+                    buffer: Vec::new(),
+                    width,
+                    height,
+                };
+
+                w.create_image();
+                xlib::XFlush(display);
+                Some(w)
+            }
+        }
+
+        unsafe fn create_image(&mut self) {
+            self.destroy_image();
+            self.buffer = vec![0; (self.width * self.height) as usize];
+            let screen = xlib::XDefaultScreen(self.display);
+
+            self.image = xlib::XCreateImage(
+                self.display,
+                xlib::XDefaultVisual(self.display, screen),
+                xlib::XDefaultDepth(self.display, screen) as u32,
+                xlib::ZPixmap,
+                0,
+                self.buffer.as_mut_ptr() as *mut c_char,
+                self.width as u32,
+                self.height as u32,
+                32,
+                0,
+            );
+        }
+
+        unsafe fn destroy_image(&mut self) {
+            if !self.image.is_null() {
+                // The pixel data is owned by the buffer so only the image struct is freed
+                (*self.image).data = null_mut();
+                xlib::XFree(self.image as *mut c_void);
+                self.image = null_mut();
+            }
+        }
+
+        pub(crate) fn resize(&mut self, width: i32, height: i32) {
+            self.width = width;
+            self.height = height;
+
+            unsafe {
+                xlib::XResizeWindow(self.display, self.window, width as u32, height as u32);
+                self.create_image();
+            }
+        }
+
+        pub(crate) fn present(&mut self, canvas: &Canvas) {
+            if self.image.is_null() || canvas.width() != self.width || canvas.height() != self.height {
+                return;
+            }
+
+            self.buffer.copy_from_slice(canvas.pixels());
+
+            unsafe {
+                xlib::XPutImage(
+                    self.display,
+                    self.window,
+                    self.gc,
+                    self.image,
+                    0,
+                    0,
+                    0,
+                    0,
+                    self.width as u32,
+                    self.height as u32,
+                );
+
+                xlib::XFlush(self.display);
+            }
+        }
+
+        pub(crate) fn poll_events(&self) -> Vec<WindowEvent> {
+            let mut events = Vec::new();
+
+            unsafe {
+                while xlib::XPending(self.display) > 0 {
+                    let mut e = MaybeUninit::<xlib::XEvent>::zeroed().assume_init();
+                    xlib::XNextEvent(self.display, &mut e);
+
+                    match e.get_type() {
+                        xlib::ButtonPress => {
+                            let (x, y) = (e.button.x, e.button.y);
+
+                            events.push(match e.button.button {
+                                4 => WindowEvent::Wheel { x, y, distance: 1.0 },
+                                5 => WindowEvent::Wheel { x, y, distance: -1.0 },
+                                _ => WindowEvent::MouseDown { x, y },
+                            })
+                        }
+
+                        xlib::ButtonRelease if e.button.button < 4 => events.push(WindowEvent::MouseUp {
+                            x: e.button.x,
+                            y: e.button.y,
+                        }),
+
+                        xlib::MotionNotify => events.push(WindowEvent::MouseMove {
+                            x: e.motion.x,
+                            y: e.motion.y,
+                        }),
+
+                        xlib::Expose => events.push(WindowEvent::Expose),
+                        _ => {}
+                    }
+                }
+            }
+
+            events
+        }
+    }
+
+    impl Drop for EditorWindow {
+        fn drop(&mut self) {
+            unsafe {
+                self.destroy_image();
+                xlib::XFreeGC(self.display, self.gc);
+                xlib::XDestroyWindow(self.display, self.window);
+                xlib::XCloseDisplay(self.display);
+            }
+        }
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+mod platform {
+    use super::WindowEvent;
+    use crate::{editor::ParentWindow, generic_editor::Canvas};
+
+    pub(crate) struct EditorWindow;
+
+    impl EditorWindow {
+        pub(crate) fn is_supported() -> bool { false }
+        pub(crate) fn open(_parent: ParentWindow, _width: i32, _height: i32) -> Option<Self> { None }
+        pub(crate) fn resize(&mut self, _width: i32, _height: i32) {}
+        pub(crate) fn present(&mut self, _canvas: &Canvas) {}
+        pub(crate) fn poll_events(&self) -> Vec<WindowEvent> { Vec::new() }
+    }
+}
+
+pub(crate) use platform::EditorWindow;
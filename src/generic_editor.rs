@@ -0,0 +1,687 @@
+use std::cell::{Cell, RefCell};
+
+use crate::{
+    edit_controller::EditController,
+    editor::{Editor, EditorContext, ParentWindow, Platform, ViewRect},
+    editor_window::{EditorWindow, WindowEvent},
+    plugin_parameter::{NormalizedParameterValue, ParameterId, ParameterInfo},
+    unit::UnitId,
+};
+
+pub type Color = u32;
+
+const BACKGROUND_COLOR: Color = 0x20_24_28;
+const HEADER_COLOR: Color = 0x2C_31_38;
+const HEADER_TEXT_COLOR: Color = 0xE0_C0_80;
+const TEXT_COLOR: Color = 0xDD_DD_DD;
+const TRACK_COLOR: Color = 0x3C_43_4C;
+const FILL_COLOR: Color = 0x4A_90_D9;
+const READ_ONLY_COLOR: Color = 0x60_66_6E;
+
+const FONT_WIDTH: i32 = 5;
+const FONT_HEIGHT: i32 = 7;
+const FONT_FIRST_CHAR: u32 = 32;
+
+/// 5x7 bitmap font for the printable ASCII characters, one byte per row with the leftmost pixel in bit 4
+#[rustfmt::skip]
+static FONT: [[u8; 7]; 95] = [
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00], // ' '
+    [0x04, 0x04, 0x04, 0x04, 0x04, 0x00, 0x04], // '!'
+    [0x0A, 0x0A, 0x00, 0x00, 0x00, 0x00, 0x00], // '"'
+    [0x0A, 0x0A, 0x1F, 0x0A, 0x1F, 0x0A, 0x0A], // '#'
+    [0x04, 0x0F, 0x14, 0x0E, 0x05, 0x1E, 0x04], // '$'
+    [0x18, 0x19, 0x02, 0x04, 0x08, 0x13, 0x03], // '%'
+    [0x0C, 0x12, 0x14, 0x08, 0x15, 0x12, 0x0D], // '&'
+    [0x04, 0x04, 0x00, 0x00, 0x00, 0x00, 0x00], // "'"
+    [0x02, 0x04, 0x08, 0x08, 0x08, 0x04, 0x02], // '('
+    [0x08, 0x04, 0x02, 0x02, 0x02, 0x04, 0x08], // ')'
+    [0x00, 0x04, 0x15, 0x0E, 0x15, 0x04, 0x00], // '*'
+    [0x00, 0x04, 0x04, 0x1F, 0x04, 0x04, 0x00], // '+'
+    [0x00, 0x00, 0x00, 0x00, 0x06, 0x04, 0x08], // ','
+    [0x00, 0x00, 0x00, 0x1F, 0x00, 0x00, 0x00], // '-'
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x0C, 0x0C], // '.'
+    [0x00, 0x01, 0x02, 0x04, 0x08, 0x10, 0x00], // '/'
+    [0x0E, 0x11, 0x13, 0x15, 0x19, 0x11, 0x0E], // '0'
+    [0x04, 0x0C, 0x04, 0x04, 0x04, 0x04, 0x0E], // '1'
+    [0x0E, 0x11, 0x01, 0x02, 0x04, 0x08, 0x1F], // '2'
+    [0x1F, 0x02, 0x04, 0x02, 0x01, 0x11, 0x0E], // '3'
+    [0x02, 0x06, 0x0A, 0x12, 0x1F, 0x02, 0x02], // '4'
+    [0x1F, 0x10, 0x1E, 0x01, 0x01, 0x11, 0x0E], // '5'
+    [0x06, 0x08, 0x10, 0x1E, 0x11, 0x11, 0x0E], // '6'
+    [0x1F, 0x01, 0x02, 0x04, 0x08, 0x08, 0x08], // '7'
+    [0x0E, 0x11, 0x11, 0x0E, 0x11, 0x11, 0x0E], // '8'
+    [0x0E, 0x11, 0x11, 0x0F, 0x01, 0x02, 0x0C], // '9'
+    [0x00, 0x0C, 0x0C, 0x00, 0x0C, 0x0C, 0x00], // ':'
+    [0x00, 0x0C, 0x0C, 0x00, 0x0C, 0x04, 0x08], // ';'
+    [0x02, 0x04, 0x08, 0x10, 0x08, 0x04, 0x02], // '<'
+    [0x00, 0x00, 0x1F, 0x00, 0x1F, 0x00, 0x00], // '='
+    [0x08, 0x04, 0x02, 0x01, 0x02, 0x04, 0x08], // '>'
+    [0x0E, 0x11, 0x01, 0x02, 0x04, 0x00, 0x04], // '?'
+    [0x0E, 0x11, 0x01, 0x0D, 0x15, 0x15, 0x0E], // '@'
+    [0x0E, 0x11, 0x11, 0x1F, 0x11, 0x11, 0x11], // 'A'
+    [0x1E, 0x11, 0x11, 0x1E, 0x11, 0x11, 0x1E], // 'B'
+    [0x0E, 0x11, 0x10, 0x10, 0x10, 0x11, 0x0E], // 'C'
+    [0x1C, 0x12, 0x11, 0x11, 0x11, 0x12, 0x1C], // 'D'
+    [0x1F, 0x10, 0x10, 0x1E, 0x10, 0x10, 0x1F], // 'E'
+    [0x1F, 0x10, 0x10, 0x1E, 0x10, 0x10, 0x10], // 'F'
+    [0x0E, 0x11, 0x10, 0x17, 0x11, 0x11, 0x0F], // 'G'
+    [0x11, 0x11, 0x11, 0x1F, 0x11, 0x11, 0x11], // 'H'
+    [0x0E, 0x04, 0x04, 0x04, 0x04, 0x04, 0x0E], // 'I'
+    [0x07, 0x02, 0x02, 0x02, 0x02, 0x12, 0x0C], // 'J'
+    [0x11, 0x12, 0x14, 0x18, 0x14, 0x12, 0x11], // 'K'
+    [0x10, 0x10, 0x10, 0x10, 0x10, 0x10, 0x1F], // 'L'
+    [0x11, 0x1B, 0x15, 0x15, 0x11, 0x11, 0x11], // 'M'
+    [0x11, 0x11, 0x19, 0x15, 0x13, 0x11, 0x11], // 'N'
+    [0x0E, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0E], // 'O'
+    [0x1E, 0x11, 0x11, 0x1E, 0x10, 0x10, 0x10], // 'P'
+    [0x0E, 0x11, 0x11, 0x11, 0x15, 0x12, 0x0D], // 'Q'
+    [0x1E, 0x11, 0x11, 0x1E, 0x14, 0x12, 0x11], // 'R'
+    [0x0F, 0x10, 0x10, 0x0E, 0x01, 0x01, 0x1E], // 'S'
+    [0x1F, 0x04, 0x04, 0x04, 0x04, 0x04, 0x04], // 'T'
+    [0x11, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0E], // 'U'
+    [0x11, 0x11, 0x11, 0x11, 0x11, 0x0A, 0x04], // 'V'
+    [0x11, 0x11, 0x11, 0x15, 0x15, 0x15, 0x0A], // 'W'
+    [0x11, 0x11, 0x0A, 0x04, 0x0A, 0x11, 0x11], // 'X'
+    [0x11, 0x11, 0x0A, 0x04, 0x04, 0x04, 0x04], // 'Y'
+    [0x1F, 0x01, 0x02, 0x04, 0x08, 0x10, 0x1F], // 'Z'
+    [0x0E, 0x08, 0x08, 0x08, 0x08, 0x08, 0x0E], // '['
+    [0x00, 0x10, 0x08, 0x04, 0x02, 0x01, 0x00], // '\\'
+    [0x0E, 0x02, 0x02, 0x02, 0x02, 0x02, 0x0E], // ']'
+    [0x04, 0x0A, 0x11, 0x00, 0x00, 0x00, 0x00], // '^'
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x1F], // '_'
+    [0x08, 0x04, 0x00, 0x00, 0x00, 0x00, 0x00], // '`'
+    [0x00, 0x00, 0x0E, 0x01, 0x0F, 0x11, 0x0F], // 'a'
+    [0x10, 0x10, 0x16, 0x19, 0x11, 0x11, 0x1E], // 'b'
+    [0x00, 0x00, 0x0E, 0x10, 0x10, 0x11, 0x0E], // 'c'
+    [0x01, 0x01, 0x0D, 0x13, 0x11, 0x11, 0x0F], // 'd'
+    [0x00, 0x00, 0x0E, 0x11, 0x1F, 0x10, 0x0E], // 'e'
+    [0x06, 0x09, 0x08, 0x1C, 0x08, 0x08, 0x08], // 'f'
+    [0x00, 0x0F, 0x11, 0x11, 0x0F, 0x01, 0x0E], // 'g'
+    [0x10, 0x10, 0x16, 0x19, 0x11, 0x11, 0x11], // 'h'
+    [0x04, 0x00, 0x0C, 0x04, 0x04, 0x04, 0x0E], // 'i'
+    [0x02, 0x00, 0x06, 0x02, 0x02, 0x12, 0x0C], // 'j'
+    [0x10, 0x10, 0x12, 0x14, 0x18, 0x14, 0x12], // 'k'
+    [0x0C, 0x04, 0x04, 0x04, 0x04, 0x04, 0x0E], // 'l'
+    [0x00, 0x00, 0x1A, 0x15, 0x15, 0x11, 0x11], // 'm'
+    [0x00, 0x00, 0x16, 0x19, 0x11, 0x11, 0x11], // 'n'
+    [0x00, 0x00, 0x0E, 0x11, 0x11, 0x11, 0x0E], // 'o'
+    [0x00, 0x00, 0x1E, 0x11, 0x1E, 0x10, 0x10], // 'p'
+    [0x00, 0x00, 0x0D, 0x13, 0x0F, 0x01, 0x01], // 'q'
+    [0x00, 0x00, 0x16, 0x19, 0x10, 0x10, 0x10], // 'r'
+    [0x00, 0x00, 0x0E, 0x10, 0x0E, 0x01, 0x1E], // 's'
+    [0x08, 0x08, 0x1C, 0x08, 0x08, 0x09, 0x06], // 't'
+    [0x00, 0x00, 0x11, 0x11, 0x11, 0x13, 0x0D], // 'u'
+    [0x00, 0x00, 0x11, 0x11, 0x11, 0x0A, 0x04], // 'v'
+    [0x00, 0x00, 0x11, 0x11, 0x15, 0x15, 0x0A], // 'w'
+    [0x00, 0x00, 0x11, 0x0A, 0x04, 0x0A, 0x11], // 'x'
+    [0x00, 0x00, 0x11, 0x11, 0x0F, 0x01, 0x0E], // 'y'
+    [0x00, 0x00, 0x1F, 0x02, 0x04, 0x08, 0x1F], // 'z'
+    [0x02, 0x04, 0x04, 0x08, 0x04, 0x04, 0x02], // '{'
+    [0x04, 0x04, 0x04, 0x04, 0x04, 0x04, 0x04], // '|'
+    [0x08, 0x04, 0x04, 0x02, 0x04, 0x04, 0x08], // '}'
+    [0x00, 0x00, 0x08, 0x15, 0x02, 0x00, 0x00], // '~'
+];
+
+#[derive(Clone, Copy, Default, Debug, PartialEq, Eq)]
+pub struct Rect {
+    pub x:      i32,
+    pub y:      i32,
+    pub width:  i32,
+    pub height: i32,
+}
+
+impl Rect {
+    pub fn new(x: i32, y: i32, width: i32, height: i32) -> Self { Self { x, y, width, height } }
+    pub fn contains(&self, x: i32, y: i32) -> bool {
+        x >= self.x && y >= self.y && x < self.right() && y < self.bottom()
+    }
+    pub fn right(&self) -> i32 { self.x + self.width }
+    pub fn bottom(&self) -> i32 { self.y + self.height }
+
+    pub fn shrink(&self, d: i32) -> Self {
+        Self::new(self.x + d, self.y + d, (self.width - 2 * d).max(0), (self.height - 2 * d).max(0))
+    }
+}
+
+/// Software rendered 0x00RRGGBB pixel buffer
+pub struct Canvas {
+    width:  i32,
+    height: i32,
+    pixels: Vec<u32>,
+}
+
+impl Canvas {
+    pub fn new(width: i32, height: i32) -> Self {
+        Self {
+            width,
+            height,
+            pixels: vec![0; (width.max(0) * height.max(0)) as usize],
+        }
+    }
+
+    pub fn width(&self) -> i32 { self.width }
+    pub fn height(&self) -> i32 { self.height }
+    pub fn pixels(&self) -> &[u32] { &self.pixels }
+
+    pub fn clear(&mut self, color: Color) { self.pixels.iter_mut().for_each(|p| *p = color) }
+
+    pub fn fill_rect(&mut self, rect: Rect, color: Color) {
+        let x0 = rect.x.clamp(0, self.width);
+        // Rectangles with negative sizes are empty
+        let x1 = rect.right().clamp(0, self.width).max(x0);
+        let y0 = rect.y.clamp(0, self.height);
+        let y1 = rect.bottom().clamp(0, self.height).max(y0);
+
+        for y in y0..y1 {
+            let row = (y * self.width) as usize;
+            self.pixels[row + x0 as usize..row + x1 as usize].iter_mut().for_each(|p| *p = color);
+        }
+    }
+
+    pub fn draw_rect(&mut self, rect: Rect, color: Color) {
+        self.fill_rect(Rect::new(rect.x, rect.y, rect.width, 1), color);
+        self.fill_rect(Rect::new(rect.x, rect.bottom() - 1, rect.width, 1), color);
+        self.fill_rect(Rect::new(rect.x, rect.y, 1, rect.height), color);
+        self.fill_rect(Rect::new(rect.right() - 1, rect.y, 1, rect.height), color);
+    }
+
+    pub fn text_width(text: &str, scale: i32) -> i32 { text.chars().count() as i32 * (FONT_WIDTH + 1) * scale }
+    pub fn text_height(scale: i32) -> i32 { FONT_HEIGHT * scale }
+
+    /// Draws text with the top left corner at (x, y), returns the x coordinate after the last character
+    pub fn draw_text(&mut self, x: i32, y: i32, text: &str, color: Color, scale: i32) -> i32 {
+        let mut cx = x;
+
+        for ch in text.chars() {
+            let glyph = FONT
+                .get((ch as u32).wrapping_sub(FONT_FIRST_CHAR) as usize)
+                .unwrap_or(&FONT[('?' as u32 - FONT_FIRST_CHAR) as usize]);
+
+            for (row, bits) in glyph.iter().enumerate() {
+                for col in 0..FONT_WIDTH {
+                    if bits & (0x10 >> col) != 0 {
+                        self.fill_rect(Rect::new(cx + col * scale, y + row as i32 * scale, scale, scale), color);
+                    }
+                }
+            }
+
+            cx += (FONT_WIDTH + 1) * scale;
+        }
+
+        cx
+    }
+
+    /// Draws text vertically centered in `rect`, truncating it to fit the width
+    pub fn draw_text_in(&mut self, rect: Rect, text: &str, color: Color, scale: i32, center: bool) {
+        let max_chars = (rect.width / ((FONT_WIDTH + 1) * scale)).max(0) as usize;
+        let text = text.chars().take(max_chars).collect::<String>();
+        let y = rect.y + (rect.height - Self::text_height(scale)) / 2;
+        let x = if center { rect.x + (rect.width - Self::text_width(&text, scale)) / 2 } else { rect.x };
+        self.draw_text(x, y, &text, color, scale);
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WidgetKind {
+    Slider,
+    Toggle,
+    List,
+}
+
+impl WidgetKind {
+    pub fn for_parameter(param: &ParameterInfo) -> Self {
+        if param.flags.is_list || !param.value_strings.is_empty() {
+            WidgetKind::List
+        }
+        else if param.step_count == 1 {
+            WidgetKind::Toggle
+        }
+        else {
+            WidgetKind::Slider
+        }
+    }
+}
+
+struct ParameterRow {
+    id:        ParameterId,
+    kind:      WidgetKind,
+    read_only: bool,
+    label:     Rect,
+    widget:    Rect,
+    value:     Rect,
+}
+
+enum Row {
+    Header { title: String, rect: Rect },
+    Parameter(ParameterRow),
+}
+
+struct Layout {
+    rows:  Vec<Row>,
+    size:  ViewRect,
+    scale: f32,
+}
+
+impl Layout {
+    /// Scales a size in unscaled pixels, fractional scales like 1.25 and 1.5 are common on Windows
+    fn px(&self, v: i32) -> i32 { scale_px(v, self.scale) }
+
+    /// The bitmap font can only be scaled by whole numbers
+    fn text_scale(&self) -> i32 { ((2.0 * self.scale).round() as i32).max(1) }
+}
+
+fn scale_px(v: i32, scale: f32) -> i32 { (v as f32 * scale).round() as i32 }
+
+/// Groups parameters by unit in the order the units are declared, parameters with undeclared units are put last
+fn group_parameters<'t>(controller: &'t dyn EditController) -> Vec<(Option<String>, Vec<&'t ParameterInfo>)> {
+    let params = controller.get_parameters();
+    let mut groups: Vec<(UnitId, Option<String>, Vec<&ParameterInfo>)> = Vec::new();
+
+    if let Some(units) = controller.get_units() {
+        for u in units {
+            groups.push((u.id, Some(u.name.clone()), Vec::new()));
+        }
+    }
+
+    for p in params.iter().filter(|p| !p.flags.is_hidden) {
+        if let Some(g) = groups.iter_mut().find(|g| g.0 == p.unit_id) {
+            g.2.push(p);
+        }
+        else {
+            groups.push((p.unit_id, None, vec![p]));
+        }
+    }
+
+    let has_headers = groups.iter().filter(|g| !g.2.is_empty()).count() > 1;
+
+    groups
+        .into_iter()
+        .filter(|g| !g.2.is_empty())
+        .map(|(id, name, params)| {
+            (if has_headers { Some(name.unwrap_or_else(|| format!("Unit {}", *id))) } else { None }, params)
+        })
+        .collect()
+}
+
+fn create_layout(controller: &dyn EditController, scale: f32) -> Layout {
+    let pad = scale_px(8, scale);
+    let row_height = scale_px(28, scale);
+    let header_height = scale_px(32, scale);
+    let label_width = scale_px(160, scale);
+    let widget_width = scale_px(200, scale);
+    let value_width = scale_px(140, scale);
+    let width = pad * 4 + label_width + widget_width + value_width;
+
+    let mut rows = Vec::new();
+    let mut y = pad;
+
+    for (title, params) in group_parameters(controller) {
+        if let Some(title) = title {
+            rows.push(Row::Header {
+                title,
+                rect: Rect::new(0, y, width, header_height),
+            });
+
+            y += header_height;
+        }
+
+        for p in params {
+            let x_widget = pad * 2 + label_width;
+            let x_value = x_widget + widget_width + pad;
+
+            rows.push(Row::Parameter(ParameterRow {
+                id:        p.id,
+                kind:      WidgetKind::for_parameter(p),
+                read_only: p.flags.is_read_only,
+                label:     Rect::new(pad, y, label_width, row_height),
+                widget:    Rect::new(x_widget, y, widget_width, row_height).shrink(scale_px(4, scale)),
+                value:     Rect::new(x_value, y, value_width, row_height),
+            }));
+
+            y += row_height;
+        }
+    }
+
+    Layout {
+        rows,
+        size: ViewRect::new(width, y + pad),
+        scale,
+    }
+}
+
+fn step_normalized(param: &ParameterInfo, value: NormalizedParameterValue, steps: i32) -> NormalizedParameterValue {
+    if param.step_count > 0 {
+        let index = (*value * param.step_count as f64).round() as i32 + steps;
+        (index.clamp(0, param.step_count) as f64 / param.step_count as f64).into()
+    }
+    else {
+        (*value + steps as f64 * 0.01).clamp(0.0, 1.0).into()
+    }
+}
+
+/// Generic editor showing all parameters of the edit controller
+pub struct GenericEditor {
+    context:   EditorContext,
+    layout:    RefCell<Layout>,
+    canvas:    RefCell<Canvas>,
+    values:    RefCell<Vec<NormalizedParameterValue>>,
+    drag:      Cell<Option<usize>>,
+    mouse_pos: Cell<(i32, i32)>,
+    dirty:     Cell<bool>,
+    window:    RefCell<Option<EditorWindow>>,
+}
+
+impl GenericEditor {
+    pub fn new(context: EditorContext) -> Self {
+        let layout = create_layout(context.controller.as_ref(), 1.0);
+        let canvas = Canvas::new(layout.size.width(), layout.size.height());
+
+        Self {
+            context,
+            layout: RefCell::new(layout),
+            canvas: RefCell::new(canvas),
+            values: Default::default(),
+            drag: Cell::new(None),
+            mouse_pos: Cell::new((0, 0)),
+            dirty: Cell::new(true),
+            window: Default::default(),
+        }
+    }
+
+    pub fn is_supported() -> bool { EditorWindow::is_supported() }
+
+    fn controller(&self) -> &dyn EditController { self.context.controller.as_ref() }
+
+    fn row_parameter(&self, row_index: usize) -> Option<&ParameterInfo> {
+        match self.layout.borrow().rows.get(row_index) {
+            Some(Row::Parameter(r)) => self.controller().get_parameter_by_id(r.id),
+            _ => None,
+        }
+    }
+
+    fn find_row(&self, x: i32, y: i32) -> Option<usize> {
+        self.layout.borrow().rows.iter().position(|r| match r {
+            Row::Parameter(p) => !p.read_only && p.widget.contains(x, y),
+            _ => false,
+        })
+    }
+
+    fn edit(&self, param: &ParameterInfo, value: NormalizedParameterValue) {
+        self.controller().set_normalized_parameter_value(param, value);
+        self.context.component_handler.perform_edit(param.id, value);
+        self.dirty.set(true);
+    }
+
+    fn single_edit(&self, param: &ParameterInfo, value: NormalizedParameterValue) {
+        self.context.component_handler.begin_edit(param.id);
+        self.edit(param, value);
+        self.context.component_handler.end_edit(param.id);
+    }
+
+    fn slider_value(&self, row_index: usize, param: &ParameterInfo, x: i32) -> NormalizedParameterValue {
+        let layout = self.layout.borrow();
+
+        let widget = match layout.rows.get(row_index) {
+            Some(Row::Parameter(r)) => r.widget,
+            _ => return param.default_normalized_value,
+        };
+
+        let v = ((x - widget.x) as f64 / widget.width.max(1) as f64).clamp(0.0, 1.0);
+
+        if param.step_count > 0 {
+            ((v * param.step_count as f64).round() / param.step_count as f64).into()
+        }
+        else {
+            v.into()
+        }
+    }
+
+    pub fn mouse_down(&self, x: i32, y: i32) {
+        let row_index = match self.find_row(x, y) {
+            Some(i) => i,
+            None => return,
+        };
+
+        let param = match self.row_parameter(row_index) {
+            Some(p) => p,
+            None => return,
+        };
+
+        let value = self.controller().get_normalized_parameter_value(param);
+
+        match WidgetKind::for_parameter(param) {
+            WidgetKind::Slider => {
+                self.context.component_handler.begin_edit(param.id);
+                self.edit(param, self.slider_value(row_index, param, x));
+                self.drag.set(Some(row_index));
+            }
+
+            WidgetKind::Toggle => self.single_edit(param, if *value < 0.5 { 1.0 } else { 0.0 }.into()),
+
+            WidgetKind::List => {
+                let next = match &self.layout.borrow().rows[row_index] {
+                    Row::Parameter(r) if x < r.widget.x + r.widget.width / 2 => -1,
+                    _ => 1,
+                };
+
+                self.single_edit(param, step_normalized(param, value, next))
+            }
+        }
+    }
+
+    pub fn mouse_move(&self, x: i32, y: i32) {
+        self.mouse_pos.set((x, y));
+
+        if let Some(row_index) = self.drag.get() {
+            if let Some(param) = self.row_parameter(row_index) {
+                self.edit(param, self.slider_value(row_index, param, x));
+            }
+        }
+    }
+
+    pub fn mouse_up(&self, _x: i32, _y: i32) {
+        if let Some(row_index) = self.drag.take() {
+            if let Some(param) = self.row_parameter(row_index) {
+                self.context.component_handler.end_edit(param.id);
+            }
+        }
+    }
+
+    pub fn wheel(&self, x: i32, y: i32, distance: f32) -> bool {
+        if self.drag.get().is_some() {
+            return false;
+        }
+
+        if let Some(param) = self.find_row(x, y).and_then(|i| self.row_parameter(i)) {
+            let value = self.controller().get_normalized_parameter_value(param);
+            self.single_edit(param, step_normalized(param, value, distance.signum() as i32));
+            true
+        }
+        else {
+            false
+        }
+    }
+
+    /// Marks the editor for redraw if any parameter value has been changed by the host
+    fn check_values(&self) {
+        let controller = self.controller();
+        let mut values = self.values.borrow_mut();
+        let params = controller.get_parameters();
+
+        if values.len() != params.len() ||
+            params.iter().zip(values.iter()).any(|(p, v)| controller.get_normalized_parameter_value(p) != *v)
+        {
+            *values = params.iter().map(|p| controller.get_normalized_parameter_value(p)).collect();
+            self.dirty.set(true);
+        }
+    }
+
+    pub fn render(&self) {
+        let layout = self.layout.borrow();
+        let mut canvas = self.canvas.borrow_mut();
+        let controller = self.controller();
+        let text_scale = layout.text_scale();
+
+        canvas.clear(BACKGROUND_COLOR);
+
+        for row in layout.rows.iter() {
+            match row {
+                Row::Header { title, rect } => {
+                    canvas.fill_rect(*rect, HEADER_COLOR);
+                    canvas.draw_text_in(rect.shrink(layout.px(8)), title, HEADER_TEXT_COLOR, text_scale, false);
+                }
+
+                Row::Parameter(r) => {
+                    let param = match controller.get_parameter_by_id(r.id) {
+                        Some(p) => p,
+                        None => continue,
+                    };
+
+                    let value = controller.get_normalized_parameter_value(param);
+                    let fill = if r.read_only { READ_ONLY_COLOR } else { FILL_COLOR };
+                    canvas.draw_text_in(r.label, &param.title, TEXT_COLOR, text_scale, false);
+                    canvas.fill_rect(r.widget, TRACK_COLOR);
+
+                    match r.kind {
+                        WidgetKind::Slider => {
+                            let w = (*value * r.widget.width as f64).round() as i32;
+                            canvas.fill_rect(Rect::new(r.widget.x, r.widget.y, w, r.widget.height), fill);
+                        }
+
+                        WidgetKind::Toggle => {
+                            let size = r.widget.height;
+                            let b = Rect::new(r.widget.x, r.widget.y, size, size);
+                            canvas.draw_rect(b, TEXT_COLOR);
+
+                            if *value >= 0.5 {
+                                canvas.fill_rect(b.shrink(layout.px(3)), fill);
+                            }
+                        }
+
+                        WidgetKind::List => {
+                            canvas.draw_text_in(r.widget.shrink(layout.px(2)), "<", TEXT_COLOR, text_scale, false);
+                            let right =
+                                Rect::new(r.widget.right() - layout.px(8), r.widget.y, layout.px(8), r.widget.height);
+                            canvas.draw_text_in(right, ">", TEXT_COLOR, text_scale, false);
+
+                            if let Some(text) = param.get_value_string(value) {
+                                canvas.draw_text_in(r.widget, text, TEXT_COLOR, text_scale, true);
+                            }
+                        }
+                    }
+
+                    let text = controller.normalized_parameter_value_to_string(param, value);
+                    canvas.draw_text_in(r.value, &text, TEXT_COLOR, text_scale, false);
+                }
+            }
+        }
+
+        self.dirty.set(false);
+    }
+
+    fn present(&self) {
+        if let Some(window) = self.window.borrow_mut().as_mut() {
+            window.present(&self.canvas.borrow());
+        }
+    }
+
+    fn set_scale(&self, scale: f32) {
+        let layout = create_layout(self.controller(), scale.max(1.0));
+        let size = layout.size;
+        self.layout.replace(layout);
+        self.canvas.replace(Canvas::new(size.width(), size.height()));
+
+        if let Some(window) = self.window.borrow_mut().as_mut() {
+            window.resize(size.width(), size.height());
+        }
+
+        self.dirty.set(true);
+    }
+}
+
+impl Editor for GenericEditor {
+    fn is_platform_supported(&self, platform: Platform) -> bool {
+        Self::is_supported() && platform == Platform::native()
+    }
+
+    fn attached(&self, parent: ParentWindow) -> bool {
+        let size = self.layout.borrow().size;
+
+        match EditorWindow::open(parent, size.width(), size.height()) {
+            Some(window) => {
+                self.window.replace(Some(window));
+                self.render();
+                self.present();
+                true
+            }
+
+            None => false,
+        }
+    }
+
+    fn removed(&self) {
+        let (x, y) = self.mouse_pos.get();
+        self.mouse_up(x, y);
+        self.window.replace(None);
+    }
+
+    fn get_size(&self) -> ViewRect { self.layout.borrow().size }
+
+    fn set_content_scale_factor(&self, factor: f32) -> bool {
+        self.set_scale(factor);
+        true
+    }
+
+    /// The size is fixed by the scale factor, the host confirms it after `resize_view`
+    fn on_size(&self, new_size: ViewRect) -> bool {
+        let size = self.layout.borrow().size;
+        new_size.width() == size.width() && new_size.height() == size.height()
+    }
+
+    fn on_wheel(&self, distance: f32) -> bool {
+        let (x, y) = self.mouse_pos.get();
+        self.wheel(x, y, distance)
+    }
+
+    fn on_idle(&self) {
+        let events = match self.window.borrow().as_ref() {
+            Some(window) => window.poll_events(),
+            None => return,
+        };
+
+        for e in events {
+            match e {
+                WindowEvent::MouseDown { x, y } => self.mouse_down(x, y),
+                WindowEvent::MouseUp { x, y } => self.mouse_up(x, y),
+                WindowEvent::MouseMove { x, y } => self.mouse_move(x, y),
+                WindowEvent::Wheel { x, y, distance } => {
+                    self.wheel(x, y, distance);
+                }
+                WindowEvent::Expose => self.dirty.set(true),
+            }
+        }
+
+        self.check_values();
+
+        if self.dirty.get() {
+            self.render();
+            self.present();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fill_rect_ignores_negative_sizes() {
+        let mut canvas = Canvas::new(8, 8);
+        canvas.fill_rect(Rect::new(6, 6, -4, -4), 1);
+        canvas.fill_rect(Rect::new(-2, -2, 20, -1), 1);
+        assert!(canvas.pixels().iter().all(|p| *p == 0));
+
+        canvas.fill_rect(Rect::new(-2, 6, 4, 10), 1);
+        assert_eq!(canvas.pixels().iter().filter(|p| **p == 1).count(), 4);
+    }
+}
@@ -0,0 +1,1016 @@
+//! Host side wrappers for loading and driving VST3 plugins, these are used by the test host and the command line tools
+//! but work with any VST3 plugin, not only the ones built with this crate.
+
+use crate::audio_processor::Event;
+use crate::edit_controller::{BusDirection, MediaType, ProgramListId, ProgramListInfo};
+use crate::plugin_parameter::{NormalizedParameterValue, ParameterFlags, ParameterId, ParameterPoint};
+use crate::sample::Sample;
+use crate::sysex::SysEx;
+use crate::transport::{PLAYING, PROJECT_TIME_MUSIC_VALID, TEMPO_VALID, TIME_SIG_VALID};
+use crate::unit::{UnitId, UnitInfo};
+use crate::utils::{char16_to_string, fixed_width_to_string, owned_vst_ptr, string_copy_into_i16, to_shared_vst_ptr};
+use crate::vst_event::{to_owned_vst_event, to_plugin_event};
+use crate::vst_factory::{FactoryInfo, VstPluginFactory};
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::ffi::CString;
+use std::ops::Deref;
+use std::ptr::{copy_nonoverlapping, null_mut};
+use std::rc::Rc;
+use uuid::Uuid;
+use vst3_com::{c_void, ComInterface, VstPtr, IID};
+use vst3_sys::base::{
+    kIBSeekCur, kIBSeekEnd, kIBSeekSet, kInvalidArgument, kNotImplemented, kResultFalse, kResultOk, kResultTrue,
+    tresult, IBStream, IPluginBase, IPluginFactory, IPluginFactory2, IPluginFactory3, PClassInfo, PClassInfo2,
+    PFactoryInfo,
+};
+use vst3_sys::utils::SharedVstPtr;
+use vst3_sys::vst::{
+    AudioBusBuffers, IAudioProcessor, IComponent, IComponentHandler, IEditController, IEventList, IHostApplication,
+    IMidiMapping, IParamValueQueue, IParameterChanges, IUnitInfo, ParamID, ProcessContext, ProcessData, ProcessModes,
+    ProcessSetup, SpeakerArrangement, String128,
+};
+use vst3_sys::VST3;
+
+const HOST_NAME: &str = "vst3-rs host";
+
+/// A COM object implemented by the host, the Rust object stays accessible while the reference is held
+struct HostObject<T, I: ComInterface + ?Sized> {
+    ptr: VstPtr<I>,
+    obj: *const T,
+}
+
+impl<T, I: ComInterface + ?Sized> HostObject<T, I> {
+    /// `I` must be the first interface implemented by `T`
+    fn new(obj: Box<T>) -> Self {
+        let obj = Box::into_raw(obj);
+        let ptr = unsafe { owned_vst_ptr::<I>(obj as *mut c_void) }.expect("Null host object");
+        Self { ptr, obj }
+    }
+
+    fn com(&self) -> &VstPtr<I> { &self.ptr }
+    fn raw(&self) -> *mut c_void { self.ptr.as_raw() as *mut c_void }
+}
+
+impl<T, I: ComInterface + ?Sized> Deref for HostObject<T, I> {
+    type Target = T;
+    fn deref(&self) -> &T { unsafe { &*self.obj } }
+}
+
+#[VST3(implements(IBStream))]
+struct MemoryStream {
+    data: RefCell<Vec<u8>>,
+    pos:  Cell<usize>,
+}
+
+impl MemoryStream {
+    fn create(data: Vec<u8>) -> HostObject<Self, dyn IBStream> {
+        HostObject::new(Self::allocate(RefCell::new(data), Cell::new(0)))
+    }
+}
+
+impl IBStream for MemoryStream {
+    unsafe fn read(&self, buffer: *mut c_void, num_bytes: i32, num_bytes_read: *mut i32) -> tresult {
+        if buffer.is_null() || num_bytes < 0 {
+            return kInvalidArgument;
+        }
+
+        let data = self.data.borrow();
+        let pos = self.pos.get().min(data.len());
+        let count = (num_bytes as usize).min(data.len() - pos);
+        copy_nonoverlapping(data[pos..].as_ptr(), buffer as *mut u8, count);
+        self.pos.set(pos + count);
+
+        if !num_bytes_read.is_null() {
+            *num_bytes_read = count as i32;
+        }
+
+        kResultOk
+    }
+
+    unsafe fn write(&self, buffer: *const c_void, num_bytes: i32, num_bytes_written: *mut i32) -> tresult {
+        if buffer.is_null() || num_bytes < 0 {
+            return kInvalidArgument;
+        }
+
+        let mut data = self.data.borrow_mut();
+        let pos = self.pos.get();
+        let end = pos + num_bytes as usize;
+
+        if data.len() < end {
+            data.resize(end, 0);
+        }
+
+        copy_nonoverlapping(buffer as *const u8, data[pos..].as_mut_ptr(), num_bytes as usize);
+        self.pos.set(end);
+
+        if !num_bytes_written.is_null() {
+            *num_bytes_written = num_bytes;
+        }
+
+        kResultOk
+    }
+
+    unsafe fn seek(&self, pos: i64, mode: i32, result: *mut i64) -> tresult {
+        let base = match mode {
+            kIBSeekSet => 0,
+            kIBSeekCur => self.pos.get() as i64,
+            kIBSeekEnd => self.data.borrow().len() as i64,
+            _ => return kInvalidArgument,
+        };
+
+        if base + pos < 0 {
+            return kInvalidArgument;
+        }
+
+        self.pos.set((base + pos) as usize);
+
+        if !result.is_null() {
+            *result = base + pos;
+        }
+
+        kResultOk
+    }
+
+    unsafe fn tell(&self, pos: *mut i64) -> tresult {
+        if pos.is_null() {
+            return kInvalidArgument;
+        }
+
+        *pos = self.pos.get() as i64;
+        kResultOk
+    }
+}
+
+#[VST3(implements(IEventList))]
+struct EventList {
+    events: RefCell<Vec<vst3_sys::vst::Event>>,
+
+    /// Copies of the SysEx data of the pushed events
+    sysex: RefCell<Vec<SysEx<'static>>>,
+}
+
+impl EventList {
+    fn create() -> HostObject<Self, dyn IEventList> {
+        HostObject::new(Self::allocate(RefCell::default(), RefCell::default()))
+    }
+
+    fn push(&self, event: &Event) {
+        let e = to_owned_vst_event(event, &mut self.sysex.borrow_mut());
+        self.events.borrow_mut().push(e)
+    }
+
+    /// Returns all events known to this crate and clears the list, SysEx data is copied
+    fn take(&self) -> Vec<Event<'static>> {
+        let events = self
+            .events
+            .borrow_mut()
+            .drain(..)
+            .filter_map(|e| unsafe { to_plugin_event(&e) }.map(|e| e.into_owned()))
+            .collect();
+
+        self.sysex.borrow_mut().clear();
+        events
+    }
+
+    fn clear(&self) {
+        self.events.borrow_mut().clear();
+        self.sysex.borrow_mut().clear();
+    }
+}
+
+impl IEventList for EventList {
+    unsafe fn get_event_count(&self) -> i32 { self.events.borrow().len() as i32 }
+
+    unsafe fn get_event(&self, index: i32, e: *mut vst3_sys::vst::Event) -> tresult {
+        match self.events.borrow().get(index as usize) {
+            Some(event) if !e.is_null() => {
+                copy_nonoverlapping(event, e, 1);
+                kResultOk
+            }
+
+            _ => kInvalidArgument,
+        }
+    }
+
+    unsafe fn add_event(&self, e: *mut vst3_sys::vst::Event) -> tresult {
+        if e.is_null() {
+            return kInvalidArgument;
+        }
+
+        self.events.borrow_mut().push(std::ptr::read(e));
+        kResultOk
+    }
+}
+
+#[VST3(implements(IParamValueQueue))]
+struct ParamValueQueue {
+    id:     u32,
+    points: RefCell<Vec<ParameterPoint>>,
+}
+
+impl ParamValueQueue {
+    fn create(id: u32) -> HostObject<Self, dyn IParamValueQueue> {
+        HostObject::new(Self::allocate(id, RefCell::default()))
+    }
+
+    /// Keeps the points sorted by sample offset, a point at an existing offset replaces the old one
+    fn insert(&self, point: ParameterPoint) -> usize {
+        let mut points = self.points.borrow_mut();
+
+        match points.binary_search_by_key(&point.sample_offset, |p| p.sample_offset) {
+            Ok(i) => {
+                points[i] = point;
+                i
+            }
+
+            Err(i) => {
+                points.insert(i, point);
+                i
+            }
+        }
+    }
+}
+
+impl IParamValueQueue for ParamValueQueue {
+    unsafe fn get_parameter_id(&self) -> u32 { self.id }
+    unsafe fn get_point_count(&self) -> i32 { self.points.borrow().len() as i32 }
+
+    unsafe fn get_point(&self, index: i32, sample_offset: *mut i32, value: *mut f64) -> tresult {
+        match self.points.borrow().get(index as usize) {
+            Some(p) if !sample_offset.is_null() && !value.is_null() => {
+                *sample_offset = p.sample_offset;
+                *value = *p.value;
+                kResultOk
+            }
+
+            _ => kInvalidArgument,
+        }
+    }
+
+    unsafe fn add_point(&self, sample_offset: i32, value: f64, index: *mut i32) -> tresult {
+        let i = self.insert(ParameterPoint {
+            sample_offset,
+            value: value.into(),
+        });
+
+        if !index.is_null() {
+            *index = i as i32;
+        }
+
+        kResultOk
+    }
+}
+
+#[VST3(implements(IParameterChanges))]
+struct ParameterChanges {
+    queues: RefCell<Vec<HostObject<ParamValueQueue, dyn IParamValueQueue>>>,
+}
+
+impl ParameterChanges {
+    fn create() -> HostObject<Self, dyn IParameterChanges> { HostObject::new(Self::allocate(RefCell::default())) }
+
+    fn queue_index(&self, id: u32) -> usize {
+        let mut queues = self.queues.borrow_mut();
+
+        match queues.iter().position(|q| q.id == id) {
+            Some(i) => i,
+            None => {
+                queues.push(ParamValueQueue::create(id));
+                queues.len() - 1
+            }
+        }
+    }
+
+    fn add(&self, id: ParameterId, point: ParameterPoint) {
+        let i = self.queue_index(*id);
+        self.queues.borrow()[i].insert(point);
+    }
+
+    /// Returns all changes and clears the queues
+    fn take(&self) -> HashMap<ParameterId, Vec<ParameterPoint>> {
+        self.queues.borrow_mut().drain(..).map(|q| (q.id.into(), q.points.take())).collect()
+    }
+}
+
+impl IParameterChanges for ParameterChanges {
+    unsafe fn get_parameter_count(&self) -> i32 { self.queues.borrow().len() as i32 }
+
+    unsafe fn get_parameter_data(&self, index: i32) -> SharedVstPtr<dyn IParamValueQueue> {
+        to_shared_vst_ptr(self.queues.borrow().get(index as usize).map(|q| q.com()))
+    }
+
+    unsafe fn add_parameter_data(&self, id: *const u32, index: *mut i32) -> SharedVstPtr<dyn IParamValueQueue> {
+        if id.is_null() {
+            return to_shared_vst_ptr(None);
+        }
+
+        let i = self.queue_index(*id);
+
+        if !index.is_null() {
+            *index = i as i32;
+        }
+
+        to_shared_vst_ptr(Some(self.queues.borrow()[i].com()))
+    }
+}
+
+#[VST3(implements(IHostApplication))]
+struct HostApplication {}
+
+impl IHostApplication for HostApplication {
+    unsafe fn get_name(&self, name: *mut String128) -> tresult {
+        if name.is_null() {
+            return kInvalidArgument;
+        }
+
+        string_copy_into_i16(HOST_NAME, &mut *name);
+        kResultOk
+    }
+
+    unsafe fn create_instance(&self, _cid: *mut IID, _iid: *mut IID, _obj: *mut *mut c_void) -> tresult {
+        kNotImplemented
+    }
+}
+
+/// A call made by an edit controller to the host component handler
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum HandlerCall {
+    BeginEdit(ParameterId),
+    PerformEdit(ParameterId, NormalizedParameterValue),
+    EndEdit(ParameterId),
+    RestartComponent(i32),
+}
+
+#[VST3(implements(IComponentHandler))]
+struct ComponentHandlerLog {
+    calls: RefCell<Vec<HandlerCall>>,
+}
+
+impl IComponentHandler for ComponentHandlerLog {
+    unsafe fn begin_edit(&self, id: ParamID) -> tresult {
+        self.calls.borrow_mut().push(HandlerCall::BeginEdit(id.into()));
+        kResultOk
+    }
+
+    unsafe fn perform_edit(&self, id: ParamID, value_normalized: f64) -> tresult {
+        self.calls.borrow_mut().push(HandlerCall::PerformEdit(id.into(), value_normalized.into()));
+        kResultOk
+    }
+
+    unsafe fn end_edit(&self, id: ParamID) -> tresult {
+        self.calls.borrow_mut().push(HandlerCall::EndEdit(id.into()));
+        kResultOk
+    }
+
+    unsafe fn restart_component(&self, flags: i32) -> tresult {
+        self.calls.borrow_mut().push(HandlerCall::RestartComponent(flags));
+        kResultOk
+    }
+}
+
+type HostContext = Rc<HostObject<HostApplication, dyn IHostApplication>>;
+
+unsafe fn initialize_plugin<I: ComInterface + ?Sized>(obj: &VstPtr<I>, context: &HostContext) -> bool {
+    match obj.get_interface::<dyn IPluginBase>() {
+        Some(base) => base.initialize(context.raw()) == kResultOk,
+        None => false,
+    }
+}
+
+unsafe fn terminate_plugin<I: ComInterface + ?Sized>(obj: &VstPtr<I>) -> bool {
+    match obj.get_interface::<dyn IPluginBase>() {
+        Some(base) => base.terminate() == kResultOk,
+        None => false,
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct ClassInfo {
+    pub cid:           Uuid,
+    pub cardinality:   i32,
+    pub category:      String,
+    pub name:          String,
+    pub class_flags:   u32,
+    pub subcategories: String,
+    pub vendor:        String,
+    pub version:       String,
+    pub sdk_version:   String,
+}
+
+impl ClassInfo {
+    fn from_info(i: &PClassInfo) -> Self {
+        Self {
+            cid: Uuid::from_bytes(i.cid.data),
+            cardinality: i.cardinality,
+            category: fixed_width_to_string(&i.category),
+            name: fixed_width_to_string(&i.name),
+            ..Default::default()
+        }
+    }
+
+    fn from_info2(i: &PClassInfo2) -> Self {
+        Self {
+            cid:           Uuid::from_bytes(i.cid.data),
+            cardinality:   i.cardinality,
+            category:      fixed_width_to_string(&i.category),
+            name:          fixed_width_to_string(&i.name),
+            class_flags:   i.class_flags,
+            subcategories: fixed_width_to_string(&i.subcategories),
+            vendor:        fixed_width_to_string(&i.vendor),
+            version:       fixed_width_to_string(&i.version),
+            sdk_version:   fixed_width_to_string(&i.sdk_version),
+        }
+    }
+}
+
+/// A plugin factory, either created in-process or returned by `GetPluginFactory`
+pub struct PluginFactory {
+    factory: VstPtr<dyn IPluginFactory>,
+    context: HostContext,
+}
+
+impl PluginFactory {
+    pub fn new(factory: Box<VstPluginFactory>) -> Self {
+        unsafe { Self::from_raw(Box::into_raw(factory) as *mut c_void) }.expect("Null plugin factory")
+    }
+
+    /// Takes over the reference returned by `GetPluginFactory`
+    pub unsafe fn from_raw(factory: *mut c_void) -> Option<Self> {
+        owned_vst_ptr::<dyn IPluginFactory>(factory).map(Self::from_ptr)
+    }
+
+    fn from_ptr(factory: VstPtr<dyn IPluginFactory>) -> Self {
+        let context = Rc::new(HostObject::new(HostApplication::allocate()));
+
+        if let Some(f) = unsafe { factory.get_interface::<dyn IPluginFactory3>() } {
+            unsafe { f.set_host_context(context.raw()) };
+        }
+
+        Self { factory, context }
+    }
+
+    fn factory_info(&self) -> Option<PFactoryInfo> {
+        let mut info: PFactoryInfo = unsafe { std::mem::zeroed() };
+
+        if unsafe { self.factory.get_factory_info(&mut info) } == kResultOk {
+            Some(info)
+        }
+        else {
+            None
+        }
+    }
+
+    pub fn info(&self) -> Option<FactoryInfo> {
+        self.factory_info().map(|i| FactoryInfo {
+            vendor: fixed_width_to_string(&i.vendor),
+            url:    fixed_width_to_string(&i.url),
+            email:  fixed_width_to_string(&i.email),
+        })
+    }
+
+    pub fn flags(&self) -> i32 { self.factory_info().map(|i| i.flags).unwrap_or(0) }
+
+    pub fn classes(&self) -> Vec<ClassInfo> {
+        let factory2 = unsafe { self.factory.get_interface::<dyn IPluginFactory2>() };
+
+        (0..unsafe { self.factory.count_classes() })
+            .filter_map(|i| unsafe {
+                if let Some(f) = factory2.as_ref() {
+                    let mut info: PClassInfo2 = std::mem::zeroed();
+
+                    if f.get_class_info2(i, &mut info) == kResultOk {
+                        return Some(ClassInfo::from_info2(&info));
+                    }
+                }
+
+                let mut info: PClassInfo = std::mem::zeroed();
+
+                if self.factory.get_class_info(i, &mut info) == kResultOk {
+                    Some(ClassInfo::from_info(&info))
+                }
+                else {
+                    None
+                }
+            })
+            .collect()
+    }
+
+    pub fn create_instance<I: ComInterface + ?Sized>(&self, cid: Uuid) -> Option<VstPtr<I>> {
+        let cid = IID { data: *cid.as_bytes() };
+        let mut obj = null_mut();
+
+        unsafe {
+            if self.factory.create_instance(&cid, &I::IID, &mut obj) == kResultOk {
+                owned_vst_ptr::<I>(obj)
+            }
+            else {
+                None
+            }
+        }
+    }
+
+    pub fn create_component(&self, cid: Uuid) -> Option<Component> {
+        let component = self.create_instance::<dyn IComponent>(cid)?;
+        let processor = unsafe { component.get_interface::<dyn IAudioProcessor>() };
+
+        Some(Component {
+            component,
+            processor,
+            context: self.context.clone(),
+        })
+    }
+
+    pub fn create_controller(&self, cid: Uuid) -> Option<Controller> {
+        let controller = self.create_instance::<dyn IEditController>(cid)?;
+        Some(Controller::new(controller, self.context.clone(), false))
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct BusInfo {
+    pub media_type:    i32,
+    pub direction:     i32,
+    pub channel_count: i32,
+    pub name:          String,
+    pub bus_type:      i32,
+    pub flags:         u32,
+}
+
+fn get_state(f: impl FnOnce(SharedVstPtr<dyn IBStream>) -> tresult) -> Option<Vec<u8>> {
+    let stream = MemoryStream::create(Vec::new());
+
+    if f(to_shared_vst_ptr(Some(stream.com()))) == kResultOk {
+        Some(stream.data.take())
+    }
+    else {
+        None
+    }
+}
+
+fn set_state(data: &[u8], f: impl FnOnce(SharedVstPtr<dyn IBStream>) -> tresult) -> bool {
+    let stream = MemoryStream::create(data.to_vec());
+    f(to_shared_vst_ptr(Some(stream.com()))) == kResultOk
+}
+
+/// The processing part of a plugin, `IComponent` and `IAudioProcessor`
+pub struct Component {
+    component: VstPtr<dyn IComponent>,
+    processor: Option<VstPtr<dyn IAudioProcessor>>,
+    context:   HostContext,
+}
+
+impl Component {
+    pub fn initialize(&self) -> bool { unsafe { initialize_plugin(&self.component, &self.context) } }
+    pub fn terminate(&self) -> bool { unsafe { terminate_plugin(&self.component) } }
+
+    pub fn controller_class_id(&self) -> Option<Uuid> {
+        let mut cid = IID { data: [0; 16] };
+
+        if unsafe { self.component.get_controller_class_id(&mut cid) } == kResultOk {
+            Some(Uuid::from_bytes(cid.data))
+        }
+        else {
+            None
+        }
+    }
+
+    pub fn bus_count(&self, media_type: MediaType, dir: BusDirection) -> i32 {
+        unsafe { self.component.get_bus_count(media_type as i32, dir as i32) }
+    }
+
+    pub fn bus_info(&self, media_type: MediaType, dir: BusDirection, index: i32) -> Option<BusInfo> {
+        let mut info: vst3_sys::vst::BusInfo = unsafe { std::mem::zeroed() };
+
+        match unsafe { self.component.get_bus_info(media_type as i32, dir as i32, index, &mut info) } {
+            kResultOk => Some(BusInfo {
+                media_type:    info.media_type,
+                direction:     info.direction,
+                channel_count: info.channel_count,
+                name:          char16_to_string(&info.name),
+                bus_type:      info.bus_type,
+                flags:         info.flags,
+            }),
+
+            _ => None,
+        }
+    }
+
+    pub fn buses(&self, media_type: MediaType, dir: BusDirection) -> Vec<BusInfo> {
+        (0..self.bus_count(media_type, dir)).filter_map(|i| self.bus_info(media_type, dir, i)).collect()
+    }
+
+    pub fn activate_bus(&self, media_type: MediaType, dir: BusDirection, index: i32, state: bool) -> bool {
+        unsafe { self.component.activate_bus(media_type as i32, dir as i32, index, state as u8) == kResultOk }
+    }
+
+    pub fn set_active(&self, state: bool) -> bool { unsafe { self.component.set_active(state as u8) == kResultOk } }
+
+    pub fn get_state(&self) -> Option<Vec<u8>> { get_state(|s| unsafe { self.component.get_state(s) }) }
+    pub fn set_state(&self, data: &[u8]) -> bool { set_state(data, |s| unsafe { self.component.set_state(s) }) }
+
+    pub fn is_audio_processor(&self) -> bool { self.processor.is_some() }
+
+    /// The edit controller of single component plugins, which implement it in the component class. It's initialized
+    /// and terminated with the component.
+    pub fn controller(&self) -> Option<Controller> {
+        let controller = unsafe { self.component.get_interface::<dyn IEditController>() }?;
+        Some(Controller::new(controller, self.context.clone(), true))
+    }
+
+    pub fn bus_arrangement(&self, dir: BusDirection, index: i32) -> Option<SpeakerArrangement> {
+        let p = self.processor.as_ref()?;
+        let mut arr = 0;
+
+        if unsafe { p.get_bus_arrangement(dir as i32, index, &mut arr) } == kResultOk {
+            Some(arr)
+        }
+        else {
+            None
+        }
+    }
+
+    pub fn set_bus_arrangements(&self, inputs: &[SpeakerArrangement], outputs: &[SpeakerArrangement]) -> bool {
+        let mut inputs = inputs.to_vec();
+        let mut outputs = outputs.to_vec();
+
+        self.processor.as_ref().map_or(false, |p| unsafe {
+            p.set_bus_arrangements(
+                inputs.as_mut_ptr(),
+                inputs.len() as i32,
+                outputs.as_mut_ptr(),
+                outputs.len() as i32,
+            ) == kResultTrue
+        })
+    }
+
+    pub fn can_process_sample_size(&self, symbolic_sample_size: i32) -> bool {
+        self.processor
+            .as_ref()
+            .map_or(false, |p| unsafe { p.can_process_sample_size(symbolic_sample_size) } == kResultTrue)
+    }
+
+    pub fn setup_processing(&self, setup: &ProcessSetup) -> bool {
+        self.processor.as_ref().map_or(false, |p| unsafe { p.setup_processing(setup) } == kResultOk)
+    }
+
+    /// `kNotImplemented` is accepted as success, the call is optional for plugins
+    pub fn set_processing(&self, state: bool) -> bool {
+        self.processor
+            .as_ref()
+            .map_or(false, |p| matches!(unsafe { p.set_processing(state as u8) }, kResultOk | kNotImplemented))
+    }
+
+    pub fn latency_samples(&self) -> u32 { self.processor.as_ref().map_or(0, |p| unsafe { p.get_latency_samples() }) }
+    pub fn tail_samples(&self) -> u32 { self.processor.as_ref().map_or(0, |p| unsafe { p.get_tail_samples() }) }
+
+    /// All pointers in `data` must be valid for the duration of the call
+    pub unsafe fn process(&self, data: &mut ProcessData) -> tresult {
+        match self.processor.as_ref() {
+            Some(p) => p.process(data),
+            None => kResultFalse,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct HostParameterInfo {
+    pub id:                       ParameterId,
+    pub title:                    String,
+    pub short_title:              String,
+    pub units:                    String,
+    pub step_count:               i32,
+    pub default_normalized_value: NormalizedParameterValue,
+    pub unit_id:                  UnitId,
+    pub flags:                    ParameterFlags,
+}
+
+/// The edit controller part of a plugin, the host component handler records all calls made by the controller
+pub struct Controller {
+    controller:       VstPtr<dyn IEditController>,
+    handler:          HostObject<ComponentHandlerLog, dyn IComponentHandler>,
+    context:          HostContext,
+    single_component: bool,
+}
+
+impl Controller {
+    fn new(controller: VstPtr<dyn IEditController>, context: HostContext, single_component: bool) -> Self {
+        Self {
+            controller,
+            handler: HostObject::new(ComponentHandlerLog::allocate(RefCell::default())),
+            context,
+            single_component,
+        }
+    }
+
+    /// Only sets the component handler for single component plugins
+    pub fn initialize(&self) -> bool {
+        unsafe {
+            (self.single_component || initialize_plugin(&self.controller, &self.context)) &&
+                self.controller.set_component_handler(to_shared_vst_ptr(Some(self.handler.com()))) == kResultOk
+        }
+    }
+
+    pub fn terminate(&self) -> bool {
+        unsafe {
+            self.controller.set_component_handler(to_shared_vst_ptr(None));
+            self.single_component || terminate_plugin(&self.controller)
+        }
+    }
+
+    pub fn set_component_state(&self, data: &[u8]) -> bool {
+        set_state(data, |s| unsafe { self.controller.set_component_state(s) })
+    }
+
+    pub fn get_state(&self) -> Option<Vec<u8>> { get_state(|s| unsafe { self.controller.get_state(s) }) }
+    pub fn set_state(&self, data: &[u8]) -> bool { set_state(data, |s| unsafe { self.controller.set_state(s) }) }
+
+    pub fn parameter_count(&self) -> i32 { unsafe { self.controller.get_parameter_count() } }
+
+    pub fn parameter_info(&self, index: i32) -> Option<HostParameterInfo> {
+        let mut i: vst3_sys::vst::ParameterInfo = unsafe { std::mem::zeroed() };
+
+        if unsafe { self.controller.get_parameter_info(index, &mut i) } != kResultOk {
+            return None;
+        }
+
+        Some(HostParameterInfo {
+            id:                       i.id.into(),
+            title:                    char16_to_string(&i.title),
+            short_title:              char16_to_string(&i.short_title),
+            units:                    char16_to_string(&i.units),
+            step_count:               i.step_count,
+            default_normalized_value: i.default_normalized_value.into(),
+            unit_id:                  i.unit_id.into(),
+            flags:                    ParameterFlags::from_bits(i.flags),
+        })
+    }
+
+    pub fn parameters(&self) -> Vec<HostParameterInfo> {
+        (0..self.parameter_count()).filter_map(|i| self.parameter_info(i)).collect()
+    }
+
+    pub fn get_normalized(&self, id: ParameterId) -> NormalizedParameterValue {
+        unsafe { self.controller.get_param_normalized(*id) }.into()
+    }
+
+    pub fn set_normalized(&self, id: ParameterId, value: NormalizedParameterValue) -> bool {
+        unsafe { self.controller.set_param_normalized(*id, *value) == kResultOk }
+    }
+
+    pub fn normalized_to_plain(&self, id: ParameterId, value: NormalizedParameterValue) -> f64 {
+        unsafe { self.controller.normalized_param_to_plain(*id, *value) }
+    }
+
+    pub fn plain_to_normalized(&self, id: ParameterId, value: f64) -> NormalizedParameterValue {
+        unsafe { self.controller.plain_param_to_normalized(*id, value) }.into()
+    }
+
+    pub fn value_to_string(&self, id: ParameterId, value: NormalizedParameterValue) -> Option<String> {
+        let mut s: String128 = [0; 128];
+
+        if unsafe { self.controller.get_param_string_by_value(*id, *value, s.as_mut_ptr()) } == kResultOk {
+            Some(char16_to_string(&s))
+        }
+        else {
+            None
+        }
+    }
+
+    pub fn string_to_value(&self, id: ParameterId, text: &str) -> Option<NormalizedParameterValue> {
+        let mut s: String128 = [0; 128];
+        let mut value = 0.0;
+        string_copy_into_i16(text, &mut s);
+
+        if unsafe { self.controller.get_param_value_by_string(*id, s.as_ptr(), &mut value) } == kResultOk {
+            Some(value.into())
+        }
+        else {
+            None
+        }
+    }
+
+    pub fn units(&self) -> Vec<UnitInfo> {
+        let ui = match unsafe { self.controller.get_interface::<dyn IUnitInfo>() } {
+            Some(ui) => ui,
+            None => return Vec::new(),
+        };
+
+        (0..unsafe { ui.get_unit_count() })
+            .filter_map(|i| {
+                let mut u: vst3_sys::vst::UnitInfo = unsafe { std::mem::zeroed() };
+
+                if unsafe { ui.get_unit_info(i, &mut u) } == kResultOk {
+                    Some(UnitInfo {
+                        id:              u.id.into(),
+                        parent_unit_id:  u.parent_unit_id.into(),
+                        name:            char16_to_string(&u.name),
+                        program_list_id: u.program_list_id.into(),
+                    })
+                }
+                else {
+                    None
+                }
+            })
+            .collect()
+    }
+
+    pub fn program_lists(&self) -> Vec<ProgramListInfo> {
+        let ui = match unsafe { self.controller.get_interface::<dyn IUnitInfo>() } {
+            Some(ui) => ui,
+            None => return Vec::new(),
+        };
+
+        (0..unsafe { ui.get_program_list_count() })
+            .filter_map(|i| {
+                let mut l: vst3_sys::vst::ProgramListInfo = unsafe { std::mem::zeroed() };
+
+                if unsafe { ui.get_program_list_info(i, &mut l) } == kResultOk {
+                    Some(ProgramListInfo {
+                        id:            l.id.into(),
+                        name:          String::from_utf16_lossy(&l.name).trim_end_matches('\0').into(),
+                        program_count: l.program_count,
+                    })
+                }
+                else {
+                    None
+                }
+            })
+            .collect()
+    }
+
+    pub fn program_name(&self, list_id: ProgramListId, program_index: i32) -> Option<String> {
+        let ui = unsafe { self.controller.get_interface::<dyn IUnitInfo>() }?;
+        let mut name = [0u16; 128];
+
+        if unsafe { ui.get_program_name(*list_id, program_index, name.as_mut_ptr()) } == kResultOk {
+            let len = name.iter().position(|c| *c == 0).unwrap_or(name.len());
+            Some(String::from_utf16_lossy(&name[..len]))
+        }
+        else {
+            None
+        }
+    }
+
+    pub fn program_info(&self, list_id: ProgramListId, program_index: i32, attribute: &str) -> Option<String> {
+        let ui = unsafe { self.controller.get_interface::<dyn IUnitInfo>() }?;
+        let attribute = CString::new(attribute).ok()?;
+        let mut value = [0u16; 128];
+
+        if unsafe { ui.get_program_info(*list_id, program_index, attribute.as_ptr() as *const u8, value.as_mut_ptr()) } ==
+            kResultOk
+        {
+            let len = value.iter().position(|c| *c == 0).unwrap_or(value.len());
+            Some(String::from_utf16_lossy(&value[..len]))
+        }
+        else {
+            None
+        }
+    }
+
+    pub fn has_midi_mapping(&self) -> bool { unsafe { self.controller.get_interface::<dyn IMidiMapping>() }.is_some() }
+
+    pub fn midi_controller_assignment(&self, bus_index: i32, channel: i16, controller: i16) -> Option<ParameterId> {
+        let mm = unsafe { self.controller.get_interface::<dyn IMidiMapping>() }?;
+        let mut id = 0;
+
+        if unsafe { mm.get_midi_controller_assignment(bus_index, channel, controller, &mut id) } == kResultTrue {
+            Some(id.into())
+        }
+        else {
+            None
+        }
+    }
+
+    /// Returns and clears the calls made to the host component handler
+    pub fn take_handler_calls(&self) -> Vec<HandlerCall> { self.handler.calls.take() }
+}
+
+/// Owns the audio buffers, event lists, parameter changes and process context passed to `IAudioProcessor::process`
+pub struct ProcessBuffers<T: Sample> {
+    max_block_size:       usize,
+    inputs:               Vec<Vec<Vec<T>>>,
+    outputs:              Vec<Vec<Vec<T>>>,
+    input_silence_flags:  Vec<u64>,
+    output_silence_flags: Vec<u64>,
+    input_events:         HostObject<EventList, dyn IEventList>,
+    output_events:        HostObject<EventList, dyn IEventList>,
+    input_param_changes:  HostObject<ParameterChanges, dyn IParameterChanges>,
+    output_param_changes: HostObject<ParameterChanges, dyn IParameterChanges>,
+    context:              ProcessContext,
+}
+
+impl<T: Sample> ProcessBuffers<T> {
+    /// `inputs` and `outputs` contain the channel count of each bus
+    pub fn new(inputs: &[i32], outputs: &[i32], max_block_size: usize, sample_rate: f64) -> Self {
+        let buses = |channels: &[i32]| {
+            channels.iter().map(|c| vec![vec![T::default(); max_block_size]; *c as usize]).collect::<Vec<_>>()
+        };
+
+        let mut context: ProcessContext = unsafe { std::mem::zeroed() };
+        context.state = PLAYING | PROJECT_TIME_MUSIC_VALID | TEMPO_VALID | TIME_SIG_VALID;
+        context.sample_rate = sample_rate;
+        context.tempo = 120.0;
+        context.time_sig_numerator = 4;
+        context.time_sig_denominator = 4;
+
+        Self {
+            max_block_size,
+            inputs: buses(inputs),
+            outputs: buses(outputs),
+            input_silence_flags: vec![0; inputs.len()],
+            output_silence_flags: vec![0; outputs.len()],
+            input_events: EventList::create(),
+            output_events: EventList::create(),
+            input_param_changes: ParameterChanges::create(),
+            output_param_changes: ParameterChanges::create(),
+            context,
+        }
+    }
+
+    pub fn max_block_size(&self) -> usize { self.max_block_size }
+    pub fn inputs(&self) -> &[Vec<Vec<T>>] { &self.inputs }
+    pub fn outputs(&self) -> &[Vec<Vec<T>>] { &self.outputs }
+    pub fn input_mut(&mut self, bus: usize, channel: usize) -> &mut [T] { &mut self.inputs[bus][channel] }
+    pub fn output(&self, bus: usize, channel: usize) -> &[T] { &self.outputs[bus][channel] }
+    pub fn set_input_silence_flags(&mut self, bus: usize, flags: u64) { self.input_silence_flags[bus] = flags }
+
+    /// The silence flags reported by the plugin in the last process call
+    pub fn output_silence_flags(&self, bus: usize) -> u64 { self.output_silence_flags[bus] }
+
+    pub fn context(&self) -> &ProcessContext { &self.context }
+    pub fn context_mut(&mut self) -> &mut ProcessContext { &mut self.context }
+
+    /// The event is passed to the plugin in the next process call
+    pub fn add_event(&self, event: &Event) { self.input_events.push(event) }
+
+    /// The change is passed to the plugin in the next process call
+    pub fn add_parameter_change(&self, id: ParameterId, point: ParameterPoint) {
+        self.input_param_changes.add(id, point)
+    }
+
+    /// Events written by the plugin in the last process call
+    pub fn take_output_events(&self) -> Vec<Event<'static>> { self.output_events.take() }
+
+    /// Parameter changes written by the plugin in the last process call
+    pub fn take_output_parameter_changes(&self) -> HashMap<ParameterId, Vec<ParameterPoint>> {
+        self.output_param_changes.take()
+    }
+
+    /// Processes `num_samples` (at most the max block size) and advances the process context, the input events and
+    /// parameter changes are cleared afterwards
+    pub fn process(&mut self, component: &Component, process_mode: ProcessModes, num_samples: usize) -> tresult {
+        let num_samples = num_samples.min(self.max_block_size);
+        self.output_events.clear();
+        self.output_param_changes.take();
+
+        let mut input_ptrs: Vec<Vec<*mut T>> =
+            self.inputs.iter_mut().map(|b| b.iter_mut().map(|c| c.as_mut_ptr()).collect()).collect();
+        let mut output_ptrs: Vec<Vec<*mut T>> =
+            self.outputs.iter_mut().map(|b| b.iter_mut().map(|c| c.as_mut_ptr()).collect()).collect();
+        let mut input_buses = bus_buffers(&mut input_ptrs, &self.input_silence_flags);
+        let mut output_buses = bus_buffers(&mut output_ptrs, &vec![0; self.outputs.len()]);
+
+        let mut data = ProcessData {
+            process_mode:         process_mode as i32,
+            symbolic_sample_size: T::SYMBOLIC_SAMPLE_SIZE,
+            num_samples:          num_samples as i32,
+            num_inputs:           input_buses.len() as i32,
+            num_outputs:          output_buses.len() as i32,
+            inputs:               if input_buses.is_empty() { null_mut() } else { input_buses.as_mut_ptr() },
+            outputs:              if output_buses.is_empty() { null_mut() } else { output_buses.as_mut_ptr() },
+            input_param_changes:  to_shared_vst_ptr(Some(self.input_param_changes.com())),
+            output_param_changes: to_shared_vst_ptr(Some(self.output_param_changes.com())),
+            input_events:         to_shared_vst_ptr(Some(self.input_events.com())),
+            output_events:        to_shared_vst_ptr(Some(self.output_events.com())),
+            context:              &mut self.context,
+        };
+
+        let result = unsafe { component.process(&mut data) };
+
+        for (flags, bus) in self.output_silence_flags.iter_mut().zip(output_buses.iter()) {
+            *flags = bus.silence_flags;
+        }
+
+        self.input_events.clear();
+        self.input_param_changes.take();
+
+        let c = &mut self.context;
+        let seconds = num_samples as f64 / c.sample_rate;
+        c.project_time_samples += num_samples as i64;
+        c.continuous_time_samples += num_samples as i64;
+        c.project_time_music += seconds * c.tempo / 60.0;
+        result
+    }
+}
+
+fn bus_buffers<T>(ptrs: &mut Vec<Vec<*mut T>>, silence_flags: &[u64]) -> Vec<AudioBusBuffers> {
+    ptrs.iter_mut()
+        .zip(silence_flags)
+        .map(|(channels, flags)| AudioBusBuffers {
+            num_channels:  channels.len() as i32,
+            silence_flags: *flags,
+            buffers:       channels.as_mut_ptr() as *mut *mut c_void,
+        })
+        .collect()
+}
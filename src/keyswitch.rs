@@ -0,0 +1,76 @@
+use crate::{
+    range::Range,
+    unit::{UnitId, ROOT_UNIT_ID},
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum KeyswitchType {
+    /// Selected by a note on and kept until another keyswitch is played
+    NoteOn,
+
+    /// Only active while the key is held
+    OnTheFly,
+
+    /// Selected by a note off
+    OnRelease,
+
+    /// A range of keys playing the articulation
+    KeyRange,
+}
+
+impl KeyswitchType {
+    pub fn to_vst(self) -> u32 {
+        match self {
+            KeyswitchType::NoteOn => 0,
+            KeyswitchType::OnTheFly => 1,
+            KeyswitchType::OnRelease => 2,
+            KeyswitchType::KeyRange => 3,
+        }
+    }
+}
+
+/// An articulation keyswitch advertised to the host
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct KeyswitchInfo {
+    pub keyswitch_type: KeyswitchType,
+    pub title:          String,
+    pub short_title:    String,
+
+    /// Inclusive range of the keys triggering the keyswitch
+    pub keys: Range<i16>,
+
+    /// The key the host should send instead when the keyswitch is selected, e.g. from an expression map
+    pub remapped_key: Option<i16>,
+    pub unit_id:      UnitId,
+}
+
+impl KeyswitchInfo {
+    pub fn new(keyswitch_type: KeyswitchType, title: &str, keys: Range<i16>) -> Self {
+        Self {
+            keyswitch_type,
+            title: title.into(),
+            short_title: title.into(),
+            keys,
+            remapped_key: None,
+            unit_id: ROOT_UNIT_ID.into(),
+        }
+    }
+
+    /// A note on keyswitch triggered by a single key
+    pub fn new_key(title: &str, key: i16) -> Self { Self::new(KeyswitchType::NoteOn, title, Range::new(key, key)) }
+
+    pub fn with_short_title(mut self, short_title: &str) -> Self {
+        self.short_title = short_title.into();
+        self
+    }
+
+    pub fn with_remapped_key(mut self, key: i16) -> Self {
+        self.remapped_key = Some(key);
+        self
+    }
+
+    pub fn with_unit(mut self, unit_id: UnitId) -> Self {
+        self.unit_id = unit_id;
+        self
+    }
+}
@@ -1,17 +1,46 @@
 #![allow(dead_code)]
 
 pub mod audio_processor;
+pub mod bus;
+mod bypass;
+pub mod component_handler;
+#[cfg(feature = "host")]
+pub mod conformance;
 pub mod converter;
 pub mod edit_controller;
+pub mod editor;
+mod editor_window;
+pub mod generic_editor;
+#[cfg(feature = "host")]
+pub mod host;
+pub mod keyswitch;
+pub mod midi_learn;
+pub mod midi_mapping;
+pub mod midi_out;
+pub mod modulation;
+pub mod note_expression;
 pub mod plugin;
+#[cfg(feature = "tools")]
+pub mod plugin_module;
 pub mod plugin_parameter;
+pub mod program;
 pub mod range;
+pub mod sample;
+pub mod sysex;
+#[cfg(feature = "host")]
+pub mod test_host;
+pub mod transport;
+pub mod tuning;
 pub mod type_cell;
+pub mod unit;
 pub mod utils;
+pub mod voice;
 mod vst_audio_processor;
 mod vst_categories;
 mod vst_edit_controller;
+mod vst_event;
 pub mod vst_factory;
+mod vst_plug_view;
 pub mod vst_stream;
 
 use std::os::raw::c_void;
@@ -0,0 +1,168 @@
+use crate::{component_handler::ComponentHandler, midi_mapping::MidiController, plugin_parameter::ParameterId};
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use std::cell::{Cell, RefCell};
+use std::io::{Error, ErrorKind, Read, Write};
+use vst3_sys::vst::RestartFlags;
+
+/// A controller assigned to a parameter by MIDI learn
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct LearnedAssignment {
+    pub bus_index:  i32,
+    pub channel:    i16,
+    pub controller: MidiController,
+    pub parameter:  ParameterId,
+}
+
+/// Assigns MIDI controllers to parameters at runtime, e.g. by moving a hardware knob after right-clicking a control.
+/// Controllers are captured through the hidden parameters of the `MidiMapping` of the controller, so the controllers
+/// to learn must be included in its raw controllers. Learned assignments take precedence over the assignments of the
+/// mapping and the host is notified whenever they change.
+#[derive(Default)]
+pub struct MidiLearn {
+    target:            Cell<Option<ParameterId>>,
+    assignments:       RefCell<Vec<LearnedAssignment>>,
+    component_handler: RefCell<ComponentHandler>,
+}
+
+impl MidiLearn {
+    pub fn new() -> Self { Self::default() }
+
+    pub(crate) fn set_component_handler(&self, handler: ComponentHandler) { self.component_handler.replace(handler); }
+
+    /// The next controller received is assigned to `id`. Learned assignments are suspended until then, so that
+    /// controllers which are already assigned reach their raw controller parameters and can be learned again.
+    pub fn start(&self, id: ParameterId) {
+        if self.target.replace(Some(id)).is_none() {
+            self.notify();
+        }
+    }
+
+    pub fn cancel(&self) {
+        if self.target.take().is_some() {
+            self.notify();
+        }
+    }
+
+    /// The parameter waiting for a controller
+    pub fn target(&self) -> Option<ParameterId> { self.target.get() }
+
+    /// Called with the source of a received raw controller value, returns true if it was assigned
+    pub fn capture(&self, bus_index: i32, channel: i16, controller: MidiController) -> bool {
+        match self.target.take() {
+            Some(id) => {
+                self.assign(bus_index, channel, controller, id);
+                true
+            }
+
+            None => false,
+        }
+    }
+
+    /// Replaces any previous assignment of the controller and the parameter
+    pub fn assign(&self, bus_index: i32, channel: i16, controller: MidiController, id: ParameterId) {
+        let mut assignments = self.assignments.borrow_mut();
+
+        assignments.retain(|a| {
+            a.parameter != id && (a.bus_index, a.channel, a.controller) != (bus_index, channel, controller)
+        });
+
+        assignments.push(LearnedAssignment {
+            bus_index,
+            channel,
+            controller,
+            parameter: id,
+        });
+
+        drop(assignments);
+        self.notify();
+    }
+
+    /// Returns false if the parameter had no assignment
+    pub fn unassign(&self, id: ParameterId) -> bool {
+        let mut assignments = self.assignments.borrow_mut();
+        let count = assignments.len();
+        assignments.retain(|a| a.parameter != id);
+        let removed = assignments.len() != count;
+
+        drop(assignments);
+
+        if removed {
+            self.notify();
+        }
+
+        removed
+    }
+
+    pub fn clear(&self) {
+        self.target.set(None);
+
+        if !self.assignments.take().is_empty() {
+            self.notify();
+        }
+    }
+
+    pub fn assignments(&self) -> Vec<LearnedAssignment> { self.assignments.borrow().clone() }
+
+    /// The controller assigned to a parameter, e.g. to show it in the UI
+    pub fn get_controller(&self, id: ParameterId) -> Option<LearnedAssignment> {
+        self.assignments.borrow().iter().find(|a| a.parameter == id).copied()
+    }
+
+    /// Returns None while learning, see `start`
+    pub fn get_assignment(&self, bus_index: i32, channel: i16, controller_number: i16) -> Option<ParameterId> {
+        if self.target.get().is_some() {
+            return None;
+        }
+
+        let controller = MidiController::from_number(controller_number)?;
+
+        self.assignments
+            .borrow()
+            .iter()
+            .find(|a| a.bus_index == bus_index && a.channel == channel && a.controller == controller)
+            .map(|a| a.parameter)
+    }
+
+    /// Reads assignments written by `write`, e.g. as part of the controller state
+    pub fn read(&self, stream: &mut impl Read) -> std::io::Result<()> {
+        let count = stream.read_u32::<LittleEndian>()?;
+        let mut assignments = Vec::with_capacity(count.min(1024) as usize);
+
+        for _ in 0..count {
+            let bus_index = stream.read_i32::<LittleEndian>()?;
+            let channel = stream.read_i16::<LittleEndian>()?;
+            let controller = MidiController::from_number(stream.read_i16::<LittleEndian>()?)
+                .ok_or_else(|| Error::new(ErrorKind::InvalidData, "Invalid MIDI controller"))?;
+            let parameter = stream.read_u32::<LittleEndian>()?.into();
+
+            assignments.push(LearnedAssignment {
+                bus_index,
+                channel,
+                controller,
+                parameter,
+            });
+        }
+
+        self.target.set(None);
+        self.assignments.replace(assignments);
+        self.notify();
+        Ok(())
+    }
+
+    pub fn write(&self, stream: &mut impl Write) -> std::io::Result<()> {
+        let assignments = self.assignments.borrow();
+        stream.write_u32::<LittleEndian>(assignments.len() as u32)?;
+
+        for a in assignments.iter() {
+            stream.write_i32::<LittleEndian>(a.bus_index)?;
+            stream.write_i16::<LittleEndian>(a.channel)?;
+            stream.write_i16::<LittleEndian>(a.controller.number())?;
+            stream.write_u32::<LittleEndian>(*a.parameter)?;
+        }
+
+        Ok(())
+    }
+
+    /// Makes the host query the assignments again
+    fn notify(&self) { self.component_handler.borrow().restart_component(RestartFlags::kMidiCCAssignmentChanged); }
+}
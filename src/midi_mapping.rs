@@ -0,0 +1,258 @@
+use std::collections::HashMap;
+
+use crate::{
+    plugin_parameter::{ParameterId, ParameterInfo, ParameterPoint},
+    range::Range,
+};
+
+pub const CONTROL_CHANGE_COUNT: i16 = 128;
+pub const CTRL_AFTER_TOUCH: i16 = 128;
+pub const CTRL_PITCH_BEND: i16 = 129;
+pub const MIDI_CHANNEL_COUNT: i16 = 16;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum MidiController {
+    ControlChange(u8),
+    ChannelPressure,
+    PitchBend,
+}
+
+impl MidiController {
+    pub fn from_number(number: i16) -> Option<Self> {
+        match number {
+            0..=127 => Some(MidiController::ControlChange(number as u8)),
+            CTRL_AFTER_TOUCH => Some(MidiController::ChannelPressure),
+            CTRL_PITCH_BEND => Some(MidiController::PitchBend),
+            _ => None,
+        }
+    }
+
+    /// Returns the VST3 controller number
+    pub fn number(&self) -> i16 {
+        match self {
+            MidiController::ControlChange(cc) => *cc as i16,
+            MidiController::ChannelPressure => CTRL_AFTER_TOUCH,
+            MidiController::PitchBend => CTRL_PITCH_BEND,
+        }
+    }
+
+    pub fn all() -> impl Iterator<Item = MidiController> {
+        (0..CONTROL_CHANGE_COUNT as u8)
+            .map(MidiController::ControlChange)
+            .chain([MidiController::ChannelPressure, MidiController::PitchBend])
+    }
+
+    fn title(&self) -> String {
+        match self {
+            MidiController::ControlChange(cc) => format!("CC {}", cc),
+            MidiController::ChannelPressure => "Channel Pressure".into(),
+            MidiController::PitchBend => "Pitch Bend".into(),
+        }
+    }
+
+    fn max_value(&self) -> f64 {
+        match self {
+            MidiController::PitchBend => 16383.0,
+            _ => 127.0,
+        }
+    }
+
+    fn default_value(&self) -> f64 {
+        match self {
+            MidiController::PitchBend => 8192.0,
+            _ => 0.0,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MidiControllerEvent {
+    pub bus_index:     i32,
+    pub channel:       i16,
+    pub sample_offset: i32,
+    pub controller:    MidiController,
+
+    /// Normalized value in the range [0, 1]
+    pub value: f64,
+}
+
+impl MidiControllerEvent {
+    pub fn value_7bit(&self) -> u8 { (self.value * 127.0).round().clamp(0.0, 127.0) as u8 }
+    pub fn value_14bit(&self) -> u16 { (self.value * 16383.0).round().clamp(0.0, 16383.0) as u16 }
+
+    /// Returns the value in the range [-1, 1], useful for pitch bend
+    pub fn value_bipolar(&self) -> f64 { (self.value_14bit() as f64 - 8192.0) / 8191.0 }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+struct MidiSource {
+    bus_index:  i32,
+    channel:    i16,
+    controller: MidiController,
+}
+
+pub struct MidiMappingBuilder {
+    next_id:     u32,
+    bus_count:   i32,
+    channels:    i16,
+    controllers: Vec<MidiController>,
+    assignments: Vec<(i32, Option<i16>, MidiController, ParameterId)>,
+}
+
+impl MidiMappingBuilder {
+    pub fn buses(mut self, count: i32) -> Self {
+        self.bus_count = count;
+        self
+    }
+
+    pub fn channels(mut self, count: i16) -> Self {
+        self.channels = count.clamp(1, MIDI_CHANNEL_COUNT);
+        self
+    }
+
+    /// Creates hidden parameters receiving the raw values of these controllers
+    pub fn raw_controllers(mut self, controllers: &[MidiController]) -> Self {
+        self.controllers.extend_from_slice(controllers);
+        self
+    }
+
+    /// Maps a controller directly to a plugin parameter, `channel` None means all channels
+    pub fn assign(mut self, bus_index: i32, channel: Option<i16>, controller: MidiController, id: ParameterId) -> Self {
+        self.assignments.push((bus_index, channel, controller, id));
+        self
+    }
+
+    pub fn build(mut self) -> MidiMapping {
+        let mut mapping = MidiMapping {
+            assignments: HashMap::new(),
+            sources:     HashMap::new(),
+            parameters:  Vec::new(),
+        };
+
+        for bus_index in 0..self.bus_count {
+            for channel in 0..self.channels {
+                for controller in self.controllers.iter() {
+                    let source = MidiSource {
+                        bus_index,
+                        channel,
+                        controller: *controller,
+                    };
+
+                    let id = ParameterId::new(self.next_id);
+                    self.next_id += 1;
+
+                    let mut title = controller.title();
+
+                    if self.channels > 1 {
+                        title = format!("{} Ch {}", title, channel + 1);
+                    }
+
+                    if self.bus_count > 1 {
+                        title = format!("{} Bus {}", title, bus_index + 1);
+                    }
+
+                    let mut p = ParameterInfo::new_linear(
+                        id,
+                        &title,
+                        "",
+                        controller.default_value(),
+                        Range::new(0.0, controller.max_value()),
+                    );
+
+                    p.step_count = controller.max_value() as i32;
+                    p.flags.can_automate = false;
+                    p.flags.is_hidden = true;
+
+                    mapping.assignments.insert(source, id);
+                    mapping.sources.insert(id, source);
+                    mapping.parameters.push(p);
+                }
+            }
+        }
+
+        for (bus_index, channel, controller, id) in self.assignments {
+            let channels = match channel {
+                Some(c) => c..c + 1,
+                None => 0..self.channels,
+            };
+
+            for channel in channels {
+                mapping.assignments.insert(
+                    MidiSource {
+                        bus_index,
+                        channel,
+                        controller,
+                    },
+                    id,
+                );
+            }
+        }
+
+        mapping
+    }
+}
+
+/// Maps MIDI controllers to parameters, the host converts incoming controller messages to parameter changes
+pub struct MidiMapping {
+    assignments: HashMap<MidiSource, ParameterId>,
+    sources:     HashMap<ParameterId, MidiSource>,
+    parameters:  Vec<ParameterInfo>,
+}
+
+impl MidiMapping {
+    /// Hidden parameters are allocated consecutive ids starting at `first_parameter_id`
+    pub fn builder(first_parameter_id: ParameterId) -> MidiMappingBuilder {
+        MidiMappingBuilder {
+            next_id:     *first_parameter_id,
+            bus_count:   1,
+            channels:    MIDI_CHANNEL_COUNT,
+            controllers: Vec::new(),
+            assignments: Vec::new(),
+        }
+    }
+
+    /// The hidden parameters, these must be included in the plugin parameters
+    pub fn parameters(&self) -> impl Iterator<Item = &ParameterInfo> { self.parameters.iter() }
+
+    pub fn get_assignment(&self, bus_index: i32, channel: i16, controller_number: i16) -> Option<ParameterId> {
+        let controller = MidiController::from_number(controller_number)?;
+
+        self.assignments
+            .get(&MidiSource {
+                bus_index,
+                channel,
+                controller,
+            })
+            .copied()
+    }
+
+    /// Returns the controller of a hidden parameter
+    pub fn get_source(&self, id: ParameterId) -> Option<(i32, i16, MidiController)> {
+        self.sources.get(&id).map(|s| (s.bus_index, s.channel, s.controller))
+    }
+
+    pub fn is_raw_controller_parameter(&self, id: ParameterId) -> bool { self.sources.contains_key(&id) }
+
+    /// Converts changes of the hidden parameters to controller events sorted by sample offset
+    pub fn to_controller_events(
+        &self,
+        param_changes: &HashMap<ParameterId, Vec<ParameterPoint>>,
+    ) -> Vec<MidiControllerEvent> {
+        let mut events = Vec::new();
+
+        for (id, points) in param_changes.iter() {
+            if let Some(s) = self.sources.get(id) {
+                events.extend(points.iter().map(|p| MidiControllerEvent {
+                    bus_index:     s.bus_index,
+                    channel:       s.channel,
+                    sample_offset: p.sample_offset,
+                    controller:    s.controller,
+                    value:         *p.value,
+                }));
+            }
+        }
+
+        events.sort_by_key(|e| e.sample_offset);
+        events
+    }
+}
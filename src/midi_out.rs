@@ -0,0 +1,128 @@
+use crate::{
+    audio_processor::{Event, EventData},
+    midi_mapping::{MidiController, CTRL_AFTER_TOUCH, CTRL_PITCH_BEND},
+};
+use vst3_sys::vst::LegacyMIDICCOutEvent;
+
+pub const CTRL_PROGRAM_CHANGE: u8 = 130;
+pub const CTRL_POLY_PRESSURE: u8 = 131;
+pub const CTRL_QUARTER_FRAME: u8 = 132;
+
+/// A MIDI channel message sent to the host as a legacy MIDI CC output event, values are clamped to their MIDI range
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum MidiMessage {
+    ControlChange {
+        cc:    u8,
+        value: u8,
+    },
+
+    /// 14-bit value, 8192 is the center
+    PitchBend(u16),
+
+    ChannelPressure(u8),
+    PolyPressure {
+        key:      u8,
+        pressure: u8,
+    },
+    ProgramChange(u8),
+
+    /// The data byte of a MIDI time code quarter frame, the channel is ignored
+    QuarterFrame(u8),
+}
+
+impl MidiMessage {
+    /// Converts a normalized controller value, e.g. of a parameter mapped with `MidiMapping`
+    pub fn from_controller(controller: MidiController, value: f64) -> Self {
+        let value_7bit = (value * 127.0).round().clamp(0.0, 127.0) as u8;
+
+        match controller {
+            MidiController::ControlChange(cc) => Self::ControlChange { cc, value: value_7bit },
+            MidiController::ChannelPressure => Self::ChannelPressure(value_7bit),
+            MidiController::PitchBend => Self::PitchBend((value * 16383.0).round().clamp(0.0, 16383.0) as u16),
+        }
+    }
+
+    /// Pitch bend in the range [-1, 1]
+    pub fn pitch_bend_bipolar(value: f64) -> Self {
+        Self::PitchBend((8192.0 + value * 8191.0).round().clamp(0.0, 16383.0) as u16)
+    }
+
+    /// Channels above 15 are clamped like the values
+    pub fn to_vst(&self, channel: u8) -> LegacyMIDICCOutEvent {
+        let (control_number, value, value2) = match *self {
+            Self::ControlChange { cc, value } => (cc & 0x7F, value.min(127), 0),
+            Self::PitchBend(v) => (CTRL_PITCH_BEND as u8, (v & 0x7F) as u8, (v.min(16383) >> 7) as u8),
+            Self::ChannelPressure(v) => (CTRL_AFTER_TOUCH as u8, v.min(127), 0),
+            Self::PolyPressure { key, pressure } => (CTRL_POLY_PRESSURE, key.min(127), pressure.min(127)),
+            Self::ProgramChange(p) => (CTRL_PROGRAM_CHANGE, p.min(127), 0),
+            Self::QuarterFrame(v) => (CTRL_QUARTER_FRAME, v.min(127), 0),
+        };
+
+        LegacyMIDICCOutEvent {
+            control_number,
+            channel: channel.min(15) as i8,
+            value: value as i8,
+            value2: value2 as i8,
+        }
+    }
+
+    /// Returns the channel and message, `None` for unknown control numbers
+    pub fn from_vst(e: &LegacyMIDICCOutEvent) -> Option<(u8, Self)> {
+        let value = e.value as u8 & 0x7F;
+        let value2 = e.value2 as u8 & 0x7F;
+
+        let message = match e.control_number {
+            cc @ 0..=127 => Self::ControlChange { cc, value },
+            n if n as i16 == CTRL_PITCH_BEND => Self::PitchBend((value2 as u16) << 7 | value as u16),
+            n if n as i16 == CTRL_AFTER_TOUCH => Self::ChannelPressure(value),
+            CTRL_POLY_PRESSURE => Self::PolyPressure {
+                key:      value,
+                pressure: value2,
+            },
+            CTRL_PROGRAM_CHANGE => Self::ProgramChange(value),
+            CTRL_QUARTER_FRAME => Self::QuarterFrame(value),
+            _ => return None,
+        };
+
+        Some((e.channel as u8 & 0x0F, message))
+    }
+
+    /// An output event on bus 0, push it onto `ProcessOutput::events` to send it to the host
+    pub fn to_event(&self, sample_offset: i32, channel: u8) -> Event<'static> {
+        Event::new(sample_offset, EventData::LegacyMidiCcOut(self.to_vst(channel)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn messages_round_trip() {
+        let messages = [
+            MidiMessage::ControlChange { cc: 7, value: 100 },
+            MidiMessage::PitchBend(0),
+            MidiMessage::PitchBend(8192),
+            MidiMessage::PitchBend(16383),
+            MidiMessage::ChannelPressure(64),
+            MidiMessage::PolyPressure {
+                key:      60,
+                pressure: 127,
+            },
+            MidiMessage::ProgramChange(5),
+            MidiMessage::QuarterFrame(0x71),
+        ];
+
+        for m in messages.iter() {
+            for channel in [0, 9, 15].iter() {
+                assert_eq!(MidiMessage::from_vst(&m.to_vst(*channel)), Some((*channel, *m)));
+            }
+        }
+    }
+
+    #[test]
+    fn channels_are_clamped() {
+        let e = MidiMessage::ProgramChange(1).to_vst(16);
+        assert_eq!(e.channel, 15);
+    }
+}
@@ -0,0 +1,394 @@
+use crate::{
+    audio_processor::{EventData, ProcessInput},
+    midi_mapping::MidiController,
+    note_expression::{
+        NoteExpressionTypeId, BRIGHTNESS_TYPE_ID, INVALID_TYPE_ID, PAN_TYPE_ID, TUNING_TYPE_ID, VOLUME_TYPE_ID,
+    },
+    voice::Note,
+};
+
+const TIMBRE_CC: u8 = 74;
+
+/// Released notes are kept until `note_finished` is called, the oldest ones are dropped when this limit is reached
+const MAX_NOTES: usize = 256;
+
+/// Expressions of further types of a note are ignored
+const MAX_EXPRESSIONS: usize = 8;
+
+/// Changes of a value per block, further changes replace the last one
+const MAX_POINTS: usize = 32;
+
+/// The tuning expression covers +/- 10 octaves
+const TUNING_RANGE_SEMITONES: f64 = 240.0;
+
+/// The modulation of a note at a sample offset
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct NoteModulation {
+    /// Offset in semitones, the pitch bend of the channel plus the tuning expression of the note
+    pub pitch_bend: f64,
+
+    /// In the range [0, 1], the poly pressure of the note or else the channel pressure
+    pub pressure: f64,
+
+    /// In the range [0, 1], the brightness expression of the note or else CC 74 of the channel
+    pub timbre: f64,
+
+    /// Normalized volume expression, 0.25 is 0 dB
+    pub volume: f64,
+
+    /// 0 is left, 0.5 center and 1 right
+    pub pan: f64,
+}
+
+impl Default for NoteModulation {
+    fn default() -> Self {
+        Self {
+            pitch_bend: 0.0,
+            pressure:   0.0,
+            timbre:     0.5,
+            volume:     0.25,
+            pan:        0.5,
+        }
+    }
+}
+
+/// The value at the start of the block and the changes within it
+#[derive(Debug)]
+struct Curve {
+    value:  Option<f64>,
+    points: Vec<(i32, f64)>,
+}
+
+impl Default for Curve {
+    fn default() -> Self {
+        Self {
+            value:  None,
+            points: Vec::with_capacity(MAX_POINTS),
+        }
+    }
+}
+
+impl Curve {
+    fn clear(&mut self) {
+        self.value = None;
+        self.points.clear();
+    }
+
+    fn begin_block(&mut self) {
+        if let Some(p) = self.points.last() {
+            self.value = Some(p.1);
+        }
+
+        self.points.clear();
+    }
+
+    fn set(&mut self, sample_offset: i32, value: f64) {
+        match self.points.last_mut() {
+            Some(last) if self.points.len() == MAX_POINTS => *last = (sample_offset, value),
+            _ => self.points.push((sample_offset, value)),
+        }
+    }
+
+    fn value_at(&self, sample_offset: i32) -> Option<f64> {
+        self.points.iter().rev().find(|p| p.0 <= sample_offset).map(|p| p.1).or(self.value)
+    }
+}
+
+/// Preallocated and reused for new notes so no memory is allocated while processing
+struct NoteState {
+    note:             Note,
+    released:         bool,
+    pressure:         Curve,
+    expressions:      Vec<(u32, Curve)>,
+    expression_count: usize,
+}
+
+impl Default for NoteState {
+    fn default() -> Self {
+        Self {
+            note:             Note::default(),
+            released:         false,
+            pressure:         Curve::default(),
+            expressions:      (0..MAX_EXPRESSIONS).map(|_| (INVALID_TYPE_ID, Curve::default())).collect(),
+            expression_count: 0,
+        }
+    }
+}
+
+impl NoteState {
+    fn start(&mut self, note: Note) {
+        self.note = note;
+        self.released = false;
+        self.pressure.clear();
+        self.expression_count = 0;
+    }
+
+    fn expressions_mut(&mut self) -> impl Iterator<Item = &mut Curve> {
+        self.expressions[..self.expression_count].iter_mut().map(|(_, c)| c)
+    }
+
+    fn expression(&self, type_id: u32) -> Option<&Curve> {
+        self.expressions[..self.expression_count].iter().find(|(id, _)| *id == type_id).map(|(_, c)| c)
+    }
+
+    fn expression_mut(&mut self, type_id: u32) -> Option<&mut Curve> {
+        match self.expressions[..self.expression_count].iter().position(|(id, _)| *id == type_id) {
+            Some(i) => Some(&mut self.expressions[i].1),
+            None if self.expression_count < MAX_EXPRESSIONS => {
+                let (id, curve) = &mut self.expressions[self.expression_count];
+                self.expression_count += 1;
+                *id = type_id;
+                curve.clear();
+                Some(curve)
+            }
+
+            None => None,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct ChannelState {
+    /// In the range [-1, 1]
+    pitch_bend: Curve,
+    pressure:   Curve,
+    timbre:     Curve,
+}
+
+/// Tracks the per-note modulation of MPE and note expression capable instruments. The channel-wide pitch bend,
+/// channel pressure and CC 74 are only received if they're mapped with a `MidiMapping`.
+pub struct ModulationTracker {
+    notes:            Vec<NoteState>,
+    free_notes:       Vec<NoteState>,
+    channels:         Vec<ChannelState>,
+    pitch_bend_range: f64,
+}
+
+impl ModulationTracker {
+    /// `pitch_bend_range` is in semitones, MPE uses 48 by default
+    pub fn new(pitch_bend_range: f64) -> Self {
+        Self {
+            notes: Vec::with_capacity(MAX_NOTES),
+            free_notes: (0..MAX_NOTES).map(|_| NoteState::default()).collect(),
+            channels: (0..16).map(|_| ChannelState::default()).collect(),
+            pitch_bend_range,
+        }
+    }
+
+    pub fn pitch_bend_range(&self) -> f64 { self.pitch_bend_range }
+    pub fn set_pitch_bend_range(&mut self, pitch_bend_range: f64) { self.pitch_bend_range = pitch_bend_range; }
+
+    pub fn reset(&mut self) {
+        self.free_notes.append(&mut self.notes);
+
+        for c in self.channels.iter_mut() {
+            c.pitch_bend.clear();
+            c.pressure.clear();
+            c.timbre.clear();
+        }
+    }
+
+    /// Drops the state of a note, e.g. when its voice has finished the release phase
+    pub fn note_finished(&mut self, note: &Note) {
+        if let Some(i) = self.notes.iter().rposition(|s| s.note.matches(note.channel, note.pitch, note.note_id)) {
+            self.free_note(i);
+        }
+    }
+
+    /// Must be called once per block before any snapshots are taken
+    pub fn update<T>(&mut self, input: &ProcessInput<T>) {
+        for n in self.notes.iter_mut() {
+            n.pressure.begin_block();
+            n.expressions_mut().for_each(|c| c.begin_block());
+        }
+
+        for c in self.channels.iter_mut() {
+            c.pitch_bend.begin_block();
+            c.pressure.begin_block();
+            c.timbre.begin_block();
+        }
+
+        for e in input.events.iter() {
+            match e.event {
+                EventData::NoteOn(n) => self.note_on(Note {
+                    channel:  n.channel,
+                    pitch:    n.pitch,
+                    velocity: n.velocity,
+                    tuning:   n.tuning,
+                    note_id:  n.note_id,
+                }),
+
+                EventData::NoteOff(n) => {
+                    if let Some(s) = self.find_held_mut(n.channel, n.pitch, n.note_id) {
+                        s.released = true;
+                    }
+                }
+
+                EventData::PolyPressure(p) => {
+                    if let Some(s) = self.find_held_mut(p.channel, p.pitch, p.note_id) {
+                        s.pressure.set(e.sample_offset, p.pressure as f64);
+                    }
+                }
+
+                EventData::NoteExpressionValue(v) => {
+                    if let Some(s) =
+                        self.notes.iter_mut().rev().find(|s| v.note_id != -1 && s.note.note_id == v.note_id)
+                    {
+                        if let Some(c) = s.expression_mut(v.type_id) {
+                            c.set(e.sample_offset, v.value);
+                        }
+                    }
+                }
+
+                _ => {}
+            }
+        }
+
+        for e in input.controller_events.iter() {
+            let channel = match self.channels.get_mut(e.channel as usize) {
+                Some(c) => c,
+                None => continue,
+            };
+
+            match e.controller {
+                MidiController::PitchBend => channel.pitch_bend.set(e.sample_offset, (e.value - 0.5) * 2.0),
+                MidiController::ChannelPressure => channel.pressure.set(e.sample_offset, e.value),
+                MidiController::ControlChange(TIMBRE_CC) => channel.timbre.set(e.sample_offset, e.value),
+                _ => {}
+            }
+        }
+    }
+
+    /// The merged modulation of a note at a sample offset in the current block
+    pub fn snapshot(&self, note: &Note, sample_offset: i32) -> NoteModulation {
+        let state = self.find(note);
+        let channel = self.channels.get(note.channel as usize);
+        let expression = |id: u32| state.and_then(|s| s.expression(id)).and_then(|c| c.value_at(sample_offset));
+        let default = NoteModulation::default();
+
+        let bend = channel.and_then(|c| c.pitch_bend.value_at(sample_offset)).unwrap_or(0.0) * self.pitch_bend_range;
+        let tuning = expression(TUNING_TYPE_ID).map_or(0.0, |v| (v - 0.5) * TUNING_RANGE_SEMITONES);
+
+        NoteModulation {
+            pitch_bend: bend + tuning,
+            pressure:   state
+                .and_then(|s| s.pressure.value_at(sample_offset))
+                .or_else(|| channel.and_then(|c| c.pressure.value_at(sample_offset)))
+                .unwrap_or(default.pressure),
+            timbre:     expression(BRIGHTNESS_TYPE_ID)
+                .or_else(|| channel.and_then(|c| c.timbre.value_at(sample_offset)))
+                .unwrap_or(default.timbre),
+            volume:     expression(VOLUME_TYPE_ID).unwrap_or(default.volume),
+            pan:        expression(PAN_TYPE_ID).unwrap_or(default.pan),
+        }
+    }
+
+    /// The normalized value of any note expression type of a note, e.g. a custom one
+    pub fn expression(&self, note: &Note, id: NoteExpressionTypeId, sample_offset: i32) -> Option<f64> {
+        self.find(note)?.expression(*id)?.value_at(sample_offset)
+    }
+
+    fn note_on(&mut self, note: Note) {
+        if note.note_id != -1 {
+            while let Some(i) = self.notes.iter().position(|s| s.note.note_id == note.note_id) {
+                self.free_note(i);
+            }
+        }
+
+        if self.free_notes.is_empty() {
+            let i = self.notes.iter().position(|s| s.released).unwrap_or(0);
+            self.free_note(i);
+        }
+
+        if let Some(mut state) = self.free_notes.pop() {
+            state.start(note);
+            self.notes.push(state);
+        }
+    }
+
+    /// Both vectors have the capacity for all notes so this doesn't allocate
+    fn free_note(&mut self, i: usize) {
+        let state = self.notes.remove(i);
+        self.free_notes.push(state);
+    }
+
+    fn find(&self, note: &Note) -> Option<&NoteState> {
+        self.notes.iter().rev().find(|s| s.note.matches(note.channel, note.pitch, note.note_id))
+    }
+
+    fn find_held_mut(&mut self, channel: i16, pitch: i16, note_id: i32) -> Option<&mut NoteState> {
+        self.notes.iter_mut().rev().find(|s| !s.released && s.note.matches(channel, pitch, note_id))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audio_processor::Event;
+    use vst3_sys::vst::{NoteExpressionValueEvent, NoteOnEvent};
+
+    const NOTE: Note = Note {
+        channel:  0,
+        pitch:    60,
+        velocity: 1.0,
+        tuning:   0.0,
+        note_id:  1,
+    };
+
+    fn note_on() -> Event<'static> {
+        Event::new(
+            0,
+            EventData::NoteOn(NoteOnEvent {
+                channel:  NOTE.channel,
+                pitch:    NOTE.pitch,
+                tuning:   0.0,
+                velocity: 1.0,
+                length:   0,
+                note_id:  NOTE.note_id,
+            }),
+        )
+    }
+
+    fn expression(sample_offset: i32, type_id: u32, value: f64) -> Event<'static> {
+        let mut e: NoteExpressionValueEvent = unsafe { std::mem::zeroed() };
+        e.type_id = type_id;
+        e.note_id = NOTE.note_id;
+        e.value = value;
+        Event::new(sample_offset, EventData::NoteExpressionValue(e))
+    }
+
+    fn update(tracker: &mut ModulationTracker, events: Vec<Event<'static>>) {
+        tracker.update(&ProcessInput::<f32>::for_events(256, events, Vec::new()));
+    }
+
+    #[test]
+    fn finished_notes_are_dropped() {
+        let mut tracker = ModulationTracker::new(48.0);
+        update(&mut tracker, vec![note_on(), expression(10, VOLUME_TYPE_ID, 0.5)]);
+        assert_eq!(tracker.snapshot(&NOTE, 0).volume, 0.25);
+        assert_eq!(tracker.snapshot(&NOTE, 10).volume, 0.5);
+
+        tracker.note_finished(&NOTE);
+        assert_eq!(tracker.notes.len(), 0);
+        assert_eq!(tracker.free_notes.len(), MAX_NOTES);
+        assert_eq!(tracker.snapshot(&NOTE, 10), NoteModulation::default());
+    }
+
+    #[test]
+    fn expressions_use_preallocated_slots() {
+        let mut tracker = ModulationTracker::new(48.0);
+        let mut events = vec![note_on()];
+        events.extend((0..MAX_POINTS as i32 * 2).map(|i| expression(i, PAN_TYPE_ID, i as f64 / 100.0)));
+        events.extend((0..MAX_EXPRESSIONS as u32 + 2).map(|id| expression(0, 100 + id, 1.0)));
+        update(&mut tracker, events);
+
+        let state = &tracker.notes[0];
+        assert_eq!(state.expressions.capacity(), MAX_EXPRESSIONS);
+        assert_eq!(state.expression(PAN_TYPE_ID).unwrap().points.capacity(), MAX_POINTS);
+
+        // The last change of the block is kept
+        let last = MAX_POINTS as i32 * 2 - 1;
+        assert_eq!(tracker.snapshot(&NOTE, last).pan, last as f64 / 100.0);
+        assert_eq!(tracker.expression(&NOTE, (100 + MAX_EXPRESSIONS as u32).into(), 0), None);
+    }
+}
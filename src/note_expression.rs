@@ -0,0 +1,226 @@
+use crate::{
+    converter::{Converter, IsoConverter},
+    plugin_parameter::{
+        LinearParameterConverter, NormalizedParameterValue, ParameterId, ParameterValue, ParameterValueConverter,
+        PlainParameterValue,
+    },
+    range::Range,
+    type_cell::TypeCell,
+    unit::{UnitId, ROOT_UNIT_ID},
+};
+
+#[derive(Clone, Copy, Default, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct NoteExpressionTypeIdTag;
+
+pub type NoteExpressionTypeId = TypeCell<NoteExpressionTypeIdTag, u32>;
+
+// Predefined note expression types
+pub const VOLUME_TYPE_ID: u32 = 0;
+pub const PAN_TYPE_ID: u32 = 1;
+pub const TUNING_TYPE_ID: u32 = 2;
+pub const VIBRATO_TYPE_ID: u32 = 3;
+pub const EXPRESSION_TYPE_ID: u32 = 4;
+pub const BRIGHTNESS_TYPE_ID: u32 = 5;
+
+/// Plugin specific types must have ids starting from here
+pub const CUSTOM_TYPE_ID_START: u32 = 100000;
+
+pub const INVALID_TYPE_ID: u32 = 0xFFFFFFFF;
+
+const IS_BIPOLAR: i32 = 1;
+const IS_ONE_SHOT: i32 = 1 << 1;
+const IS_ABSOLUTE: i32 = 1 << 2;
+const ASSOCIATED_PARAMETER_ID_VALID: i32 = 1 << 3;
+
+#[derive(Clone, Default, Debug, PartialEq, Eq)]
+pub struct NoteExpressionFlags {
+    /// The default value is the center of the range
+    pub is_bipolar: bool,
+
+    /// Only one value is sent per note, e.g. at the start of it
+    pub is_one_shot: bool,
+
+    /// Values are absolute instead of relative to the initial value of the note
+    pub is_absolute: bool,
+}
+
+impl NoteExpressionFlags {
+    pub fn to_bits(&self) -> i32 {
+        let bit = |set: bool, f: i32| if set { f } else { 0 };
+        bit(self.is_bipolar, IS_BIPOLAR) | bit(self.is_one_shot, IS_ONE_SHOT) | bit(self.is_absolute, IS_ABSOLUTE)
+    }
+}
+
+/// Maps the normalized volume expression to decibels, 0.25 is 0 dB and 1 is +12 dB
+#[derive(Clone, PartialEq, Debug)]
+pub struct VolumeConverter;
+
+impl Converter<NormalizedParameterValue, PlainParameterValue> for VolumeConverter {
+    fn convert(&self, value: NormalizedParameterValue) -> PlainParameterValue {
+        (20.0 * (4.0 * value.get()).log10()).into()
+    }
+}
+
+impl IsoConverter<NormalizedParameterValue, PlainParameterValue> for VolumeConverter {
+    fn convert_inverse(&self, value: PlainParameterValue) -> NormalizedParameterValue {
+        (10f64.powf(value.get() / 20.0) / 4.0).clamp(0.0, 1.0).into()
+    }
+}
+
+/// A note expression type supported by the plugin, values are normalized and converted to plain values for display
+pub struct NoteExpressionTypeInfo {
+    pub id:                            NoteExpressionTypeId,
+    pub title:                         String,
+    pub short_title:                   String,
+    pub units:                         String,
+    pub unit_id:                       UnitId,
+    pub default_normalized_value:      NormalizedParameterValue,
+    pub step_count:                    i32,
+    pub flags:                         NoteExpressionFlags,
+    pub associated_parameter:          Option<ParameterId>,
+    pub normalized_to_plain_converter: ParameterValueConverter,
+}
+
+impl NoteExpressionTypeInfo {
+    pub fn new(
+        id: NoteExpressionTypeId,
+        title: &str,
+        units: &str,
+        default_value: ParameterValue,
+        normalized_to_plain_converter: ParameterValueConverter,
+    ) -> Self {
+        Self {
+            id,
+            title: title.into(),
+            short_title: title.into(),
+            units: units.into(),
+            unit_id: ROOT_UNIT_ID.into(),
+            default_normalized_value: normalized_to_plain_converter.convert_inverse(default_value.into()),
+            step_count: 0,
+            flags: NoteExpressionFlags::default(),
+            associated_parameter: None,
+            normalized_to_plain_converter,
+        }
+    }
+
+    /// A plugin specific type with a linear range, `index` is added to `CUSTOM_TYPE_ID_START`
+    pub fn new_custom(
+        index: u32,
+        title: &str,
+        units: &str,
+        default_value: ParameterValue,
+        value_range: Range<ParameterValue>,
+    ) -> Self {
+        Self::new(
+            (CUSTOM_TYPE_ID_START + index).into(),
+            title,
+            units,
+            default_value,
+            Box::new(LinearParameterConverter::new(value_range.to())),
+        )
+    }
+
+    pub fn new_volume() -> Self { Self::new(VOLUME_TYPE_ID.into(), "Volume", "dB", 0.0, Box::new(VolumeConverter)) }
+
+    pub fn new_pan() -> Self {
+        Self::new(
+            PAN_TYPE_ID.into(),
+            "Pan",
+            "%",
+            0.0,
+            Box::new(LinearParameterConverter::new(Range::new(-100.0, 100.0).to())),
+        )
+        .bipolar()
+    }
+
+    /// Tuning in semitones, the range is +/- 10 octaves
+    pub fn new_tuning() -> Self {
+        Self::new(
+            TUNING_TYPE_ID.into(),
+            "Tuning",
+            "st",
+            0.0,
+            Box::new(LinearParameterConverter::new(Range::new(-120.0, 120.0).to())),
+        )
+        .bipolar()
+    }
+
+    pub fn new_brightness() -> Self {
+        Self::new(
+            BRIGHTNESS_TYPE_ID.into(),
+            "Brightness",
+            "%",
+            0.0,
+            Box::new(LinearParameterConverter::new(Range::new(-100.0, 100.0).to())),
+        )
+        .bipolar()
+    }
+
+    pub fn with_short_title(mut self, short_title: &str) -> Self {
+        self.short_title = short_title.into();
+        self
+    }
+
+    pub fn with_unit(mut self, unit_id: UnitId) -> Self {
+        self.unit_id = unit_id;
+        self
+    }
+
+    pub fn with_associated_parameter(mut self, id: ParameterId) -> Self {
+        self.associated_parameter = Some(id);
+        self
+    }
+
+    pub fn bipolar(mut self) -> Self {
+        self.flags.is_bipolar = true;
+        self
+    }
+
+    pub fn flag_bits(&self) -> i32 {
+        self.flags.to_bits() | if self.associated_parameter.is_some() { ASSOCIATED_PARAMETER_ID_VALID } else { 0 }
+    }
+
+    pub fn value_to_string(&self, value: NormalizedParameterValue) -> String {
+        let v = self.normalized_to_plain_converter.convert(value).get();
+
+        if self.units.is_empty() {
+            format!("{:.1}", v)
+        }
+        else {
+            format!("{:.1} {}", v, self.units)
+        }
+    }
+
+    /// Parses a plain value, the units are optional
+    pub fn string_to_value(&self, value: &str) -> Option<NormalizedParameterValue> {
+        let value = value.trim();
+        let value = value.strip_suffix(self.units.as_str()).unwrap_or(value).trim();
+        let v = value.parse::<ParameterValue>().ok()?;
+        Some(self.normalized_to_plain_converter.convert_inverse(v.into()).get().clamp(0.0, 1.0).into())
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PhysicalUi {
+    XMovement,
+    YMovement,
+    Pressure,
+}
+
+impl PhysicalUi {
+    pub fn from_vst(id: u32) -> Option<Self> {
+        match id {
+            0 => Some(Self::XMovement),
+            1 => Some(Self::YMovement),
+            2 => Some(Self::Pressure),
+            _ => None,
+        }
+    }
+}
+
+/// Maps a physical control of e.g. an MPE controller to a note expression type
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PhysicalUiMapping {
+    pub physical_ui:     PhysicalUi,
+    pub note_expression: NoteExpressionTypeId,
+}
@@ -31,22 +31,22 @@ pub trait State {
     fn get_state(&self, stream: &mut VstOutStream) -> std::io::Result<()>;
 }
 
+/// Parameters missing in the state are reset to their defaults. Each parameter is set once, so listeners see a
+/// single change per restored value.
 pub fn read_parameter_values<T: Parameters>(obj: &T, stream: &mut VstInStream) -> std::io::Result<()> {
     let param_count = stream.read_u32::<LittleEndian>()?;
-
-    // Reset all parameters
-    for p in obj.get_parameters() {
-        obj.set_normalized_parameter_value(p, p.default_normalized_value)
-    }
+    let mut values = Vec::with_capacity(param_count.min(1024) as usize);
 
     // Read parameter values from state
     for _ in 0..param_count {
         let id = stream.read_u32::<LittleEndian>()?;
         let value = stream.read_f64::<LittleEndian>()?;
+        values.push((id, value));
+    }
 
-        if let Some(p) = obj.get_parameter_by_id(id.into()) {
-            obj.set_normalized_parameter_value(p, value.into())
-        }
+    for p in obj.get_parameters() {
+        let value = values.iter().rev().find(|(id, _)| *id == *p.id).map(|(_, v)| (*v).into());
+        obj.set_normalized_parameter_value(p, value.unwrap_or(p.default_normalized_value));
     }
 
     Ok(())
@@ -0,0 +1,222 @@
+//! Loading of plugin libraries and bundles from disk
+
+use crate::host::PluginFactory;
+use libloading::{Library, Symbol};
+use std::fmt::{Display, Formatter};
+use std::path::{Path, PathBuf};
+use vst3_com::c_void;
+
+#[derive(Debug)]
+pub enum ModuleError {
+    NotFound(PathBuf),
+    Load(libloading::Error),
+    MissingEntryPoint(&'static str),
+    InitFailed,
+    NoFactory,
+}
+
+impl Display for ModuleError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ModuleError::NotFound(path) => write!(f, "No plugin library found at {}", path.display()),
+            ModuleError::Load(e) => write!(f, "Failed to load plugin library: {}", e),
+            ModuleError::MissingEntryPoint(name) => write!(f, "The plugin library doesn't export {}", name),
+            ModuleError::InitFailed => write!(f, "The plugin library failed to initialize"),
+            ModuleError::NoFactory => write!(f, "GetPluginFactory returned null"),
+        }
+    }
+}
+
+impl std::error::Error for ModuleError {}
+
+type GetPluginFactory = unsafe extern "system" fn() -> *mut c_void;
+
+#[cfg(target_os = "linux")]
+const ENTRY: (&str, &str) = ("ModuleEntry", "ModuleExit");
+
+#[cfg(target_os = "windows")]
+const ENTRY: (&str, &str) = ("InitDll", "ExitDll");
+
+#[cfg(target_os = "macos")]
+const ENTRY: (&str, &str) = ("bundleEntry", "bundleExit");
+
+/// Returns the library inside a `.vst3` bundle directory, other paths are returned unchanged
+pub fn resolve_bundle(path: &Path) -> PathBuf {
+    if !path.is_dir() {
+        return path.into();
+    }
+
+    let stem = path.file_stem().map(|s| s.to_string_lossy().into_owned()).unwrap_or_default();
+    let contents = path.join("Contents");
+
+    if cfg!(target_os = "windows") {
+        contents.join(format!("{}-win", std::env::consts::ARCH)).join(format!("{}.vst3", stem))
+    }
+    else if cfg!(target_os = "macos") {
+        contents.join("MacOS").join(stem)
+    }
+    else {
+        contents.join(format!("{}-linux", std::env::consts::ARCH)).join(format!("{}.so", stem))
+    }
+}
+
+/// A loaded plugin library, factories and all objects created from them must be dropped before the module
+pub struct PluginModule {
+    path:    PathBuf,
+    library: Library,
+    handle:  *mut c_void,
+}
+
+impl PluginModule {
+    /// Loads a plugin library or `.vst3` bundle and calls its module entry function
+    pub fn load(path: &Path) -> Result<Self, ModuleError> {
+        let path = resolve_bundle(path);
+
+        if !path.is_file() {
+            return Err(ModuleError::NotFound(path));
+        }
+
+        let (library, handle) = open(&path)?;
+
+        // The exit function is only called after a successful entry
+        if let Err(e) = unsafe { enter(&library, handle) } {
+            unsafe { close_handle(handle) };
+            return Err(e);
+        }
+
+        Ok(Self { path, library, handle })
+    }
+
+    pub fn path(&self) -> &Path { &self.path }
+
+    /// Calls `GetPluginFactory`, each call returns a new reference to the factory
+    pub fn factory(&self) -> Result<PluginFactory, ModuleError> {
+        unsafe {
+            let get_factory: Symbol<GetPluginFactory> = self
+                .library
+                .get(b"GetPluginFactory")
+                .map_err(|_| ModuleError::MissingEntryPoint("GetPluginFactory"))?;
+
+            PluginFactory::from_raw(get_factory()).ok_or(ModuleError::NoFactory)
+        }
+    }
+}
+
+impl Drop for PluginModule {
+    fn drop(&mut self) {
+        unsafe {
+            if let Ok(exit) = self.library.get::<unsafe extern "system" fn() -> bool>(ENTRY.1.as_bytes()) {
+                exit();
+            }
+
+            close_handle(self.handle);
+        }
+    }
+}
+
+/// Releases the handle returned by `open`, the library itself is closed when dropped
+#[cfg(target_os = "macos")]
+unsafe fn close_handle(handle: *mut c_void) { mac::release_bundle(handle) }
+
+#[cfg(not(target_os = "macos"))]
+unsafe fn close_handle(_handle: *mut c_void) {}
+
+/// `InitDll` takes no arguments and is optional
+#[cfg(target_os = "windows")]
+unsafe fn enter(library: &Library, _handle: *mut c_void) -> Result<(), ModuleError> {
+    match library.get::<unsafe extern "system" fn() -> bool>(ENTRY.0.as_bytes()) {
+        Ok(init) if !init() => Err(ModuleError::InitFailed),
+        _ => Ok(()),
+    }
+}
+
+/// `ModuleEntry` receives the library handle and `bundleEntry` the bundle, both are required
+#[cfg(not(target_os = "windows"))]
+unsafe fn enter(library: &Library, handle: *mut c_void) -> Result<(), ModuleError> {
+    let entry: Symbol<unsafe extern "system" fn(*mut c_void) -> bool> =
+        library.get(ENTRY.0.as_bytes()).map_err(|_| ModuleError::MissingEntryPoint(ENTRY.0))?;
+
+    if entry(handle) {
+        Ok(())
+    }
+    else {
+        Err(ModuleError::InitFailed)
+    }
+}
+
+/// Returns the library and the native handle passed to the module entry function
+#[cfg(target_os = "linux")]
+fn open(path: &Path) -> Result<(Library, *mut c_void), ModuleError> {
+    use libloading::os::unix;
+
+    unsafe {
+        let library = unix::Library::open(Some(path), unix::RTLD_LAZY | unix::RTLD_LOCAL).map_err(ModuleError::Load)?;
+        let handle = library.into_raw();
+        Ok((unix::Library::from_raw(handle).into(), handle))
+    }
+}
+
+/// The handle is the `CFBundleRef` of the bundle containing the library
+#[cfg(target_os = "macos")]
+fn open(path: &Path) -> Result<(Library, *mut c_void), ModuleError> {
+    // The library is at <name>.vst3/Contents/MacOS/<name>
+    let bundle_path = path.ancestors().nth(3).ok_or_else(|| ModuleError::NotFound(path.into()))?;
+    let bundle = unsafe { mac::create_bundle(bundle_path) };
+
+    if bundle.is_null() {
+        return Err(ModuleError::NotFound(bundle_path.into()));
+    }
+
+    match unsafe { Library::new(path) } {
+        Ok(library) => Ok((library, bundle)),
+        Err(e) => {
+            unsafe { close_handle(bundle) };
+            Err(ModuleError::Load(e))
+        }
+    }
+}
+
+#[cfg(target_os = "macos")]
+mod mac {
+    use std::os::unix::ffi::OsStrExt;
+    use std::path::Path;
+    use vst3_com::c_void;
+
+    #[link(name = "CoreFoundation", kind = "framework")]
+    extern "C" {
+        fn CFURLCreateFromFileSystemRepresentation(
+            allocator: *const c_void,
+            buffer: *const u8,
+            length: isize,
+            is_directory: u8,
+        ) -> *const c_void;
+
+        fn CFBundleCreate(allocator: *const c_void, bundle_url: *const c_void) -> *mut c_void;
+        fn CFRelease(cf: *const c_void);
+    }
+
+    /// Returns null if the path isn't a bundle
+    pub unsafe fn create_bundle(path: &Path) -> *mut c_void {
+        let bytes = path.as_os_str().as_bytes();
+        let url = CFURLCreateFromFileSystemRepresentation(std::ptr::null(), bytes.as_ptr(), bytes.len() as isize, 1);
+
+        if url.is_null() {
+            return std::ptr::null_mut();
+        }
+
+        let bundle = CFBundleCreate(std::ptr::null(), url);
+        CFRelease(url);
+        bundle
+    }
+
+    pub unsafe fn release_bundle(bundle: *mut c_void) {
+        if !bundle.is_null() {
+            CFRelease(bundle);
+        }
+    }
+}
+
+#[cfg(target_os = "windows")]
+fn open(path: &Path) -> Result<(Library, *mut c_void), ModuleError> {
+    unsafe { Library::new(path).map(|l| (l, std::ptr::null_mut())).map_err(ModuleError::Load) }
+}
@@ -1,11 +1,17 @@
-use std::{cell::Cell, collections::HashMap, rc::Rc};
+use std::{
+    cell::{Cell, RefCell},
+    collections::HashMap,
+    rc::Rc,
+};
 
 use crate::{
     converter::{Converter, IsoConverter},
     plugin::Parameters,
     range::Range,
     type_cell::TypeCell,
+    unit::{UnitId, ROOT_UNIT_ID},
 };
+use vst3_sys::vst::ParameterFlags as VstParameterFlags;
 
 #[derive(Clone, Copy, Default, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct ParameterIdTag;
@@ -31,16 +37,45 @@ pub struct ParameterPoint {
     pub value:         NormalizedParameterValue,
 }
 
-#[derive(Clone, Default)]
+#[derive(Clone, Default, Debug, PartialEq, Eq)]
 pub struct ParameterFlags {
     pub can_automate:      bool,
     pub is_read_only:      bool,
     pub is_wrap_around:    bool,
     pub is_list:           bool,
+    pub is_hidden:         bool,
     pub is_program_change: bool,
     pub is_bypass:         bool,
 }
 
+impl ParameterFlags {
+    pub fn from_bits(bits: i32) -> Self {
+        let has = |f: VstParameterFlags| bits & f as i32 != 0;
+
+        Self {
+            can_automate:      has(VstParameterFlags::kCanAutomate),
+            is_read_only:      has(VstParameterFlags::kIsReadOnly),
+            is_wrap_around:    has(VstParameterFlags::kIsWrapAround),
+            is_list:           has(VstParameterFlags::kIsList),
+            is_hidden:         has(VstParameterFlags::kIsHidden),
+            is_program_change: has(VstParameterFlags::kIsProgramChange),
+            is_bypass:         has(VstParameterFlags::kIsBypass),
+        }
+    }
+
+    pub fn to_bits(&self) -> i32 {
+        let bit = |set: bool, f: VstParameterFlags| if set { f as i32 } else { 0 };
+
+        bit(self.can_automate, VstParameterFlags::kCanAutomate) |
+            bit(self.is_read_only, VstParameterFlags::kIsReadOnly) |
+            bit(self.is_wrap_around, VstParameterFlags::kIsWrapAround) |
+            bit(self.is_list, VstParameterFlags::kIsList) |
+            bit(self.is_hidden, VstParameterFlags::kIsHidden) |
+            bit(self.is_program_change, VstParameterFlags::kIsProgramChange) |
+            bit(self.is_bypass, VstParameterFlags::kIsBypass)
+    }
+}
+
 pub struct ParameterInfo {
     pub id:                            ParameterId,
     pub title:                         String,
@@ -48,9 +83,10 @@ pub struct ParameterInfo {
     pub units:                         String,
     pub step_count:                    i32,
     pub default_normalized_value:      NormalizedParameterValue,
-    pub unit_id:                       i32,
+    pub unit_id:                       UnitId,
     pub flags:                         ParameterFlags,
     pub normalized_to_plain_converter: ParameterValueConverter,
+    pub value_strings:                 Vec<String>,
 }
 
 impl ParameterInfo {
@@ -70,14 +106,59 @@ impl ParameterInfo {
             units: units.into(),
             step_count: 0,
             default_normalized_value: normalized_to_plain_converter.convert_inverse(default_value.into()),
-            unit_id: 0,
+            unit_id: ROOT_UNIT_ID.into(),
             flags: ParameterFlags {
                 can_automate: true,
                 ..Default::default()
             },
             normalized_to_plain_converter,
+            value_strings: Vec::new(),
         }
     }
+
+    pub fn new_list(id: ParameterId, title: &str, default_index: i32, items: &[&str]) -> Self {
+        let step_count = (items.len() as i32 - 1).max(0);
+        let normalized_to_plain_converter = Box::new(ListParameterConverter::new(step_count));
+
+        Self {
+            id,
+            title: title.into(),
+            short_title: title.into(),
+            units: "".into(),
+            step_count,
+            default_normalized_value: normalized_to_plain_converter.convert_inverse((default_index as f64).into()),
+            unit_id: ROOT_UNIT_ID.into(),
+            flags: ParameterFlags {
+                can_automate: true,
+                is_list: true,
+                ..Default::default()
+            },
+            normalized_to_plain_converter,
+            value_strings: items.iter().map(|s| s.to_string()).collect(),
+        }
+    }
+
+    /// An on/off parameter flagged as the bypass of the plugin, created for `AudioProcessorInfo::bypass`
+    pub fn new_bypass(id: ParameterId) -> Self {
+        let mut info = Self::new_list(id, "Bypass", 0, &["Off", "On"]);
+        info.flags.is_bypass = true;
+        info
+    }
+
+    pub fn with_unit(mut self, unit_id: UnitId) -> Self {
+        self.unit_id = unit_id;
+        self
+    }
+
+    pub fn get_value_string(&self, value: NormalizedParameterValue) -> Option<&str> {
+        let index = *self.normalized_to_plain_converter.convert(value) as usize;
+        self.value_strings.get(index).map(|s| s.as_str())
+    }
+
+    pub fn find_value_string(&self, value: &str) -> Option<NormalizedParameterValue> {
+        let index = self.value_strings.iter().position(|s| s == value)?;
+        Some(self.normalized_to_plain_converter.convert_inverse((index as f64).into()))
+    }
 }
 
 #[derive(Clone, PartialEq, Debug)]
@@ -111,27 +192,60 @@ impl IsoConverter<NormalizedParameterValue, PlainParameterValue> for LinearParam
     }
 }
 
+#[derive(Clone, PartialEq, Debug)]
+pub struct ListParameterConverter {
+    step_count: i32,
+}
+
+impl ListParameterConverter {
+    pub fn new(step_count: i32) -> Self { Self { step_count } }
+    pub fn step_count(&self) -> i32 { self.step_count }
+}
+
+impl Converter<NormalizedParameterValue, PlainParameterValue> for ListParameterConverter {
+    fn convert(&self, value: NormalizedParameterValue) -> PlainParameterValue {
+        let steps = self.step_count as f64;
+        PlainParameterValue::new((value.get() * (steps + 1.0)).floor().clamp(0.0, steps))
+    }
+}
+
+impl IsoConverter<NormalizedParameterValue, PlainParameterValue> for ListParameterConverter {
+    fn convert_inverse(&self, value: PlainParameterValue) -> NormalizedParameterValue {
+        if self.step_count > 0 {
+            (value.get() / self.step_count as f64).clamp(0.0, 1.0).into()
+        }
+        else {
+            0.0.into()
+        }
+    }
+}
+
+pub type ParameterListener = Rc<dyn Fn(&ParameterWithValue)>;
+
+/// A parameter value, changes can be polled with `take_changed` or observed with `subscribe`. Both see changes from
+/// the host, state restore and automation alike.
 #[derive(Clone)]
 pub struct ParameterWithValue {
     pub parameter:        &'static ParameterInfo,
     pub value:            Cell<PlainParameterValue>,
     pub normalized_value: Cell<NormalizedParameterValue>,
+    change_count:         Cell<u64>,
+    listeners:            RefCell<Vec<ParameterListener>>,
 }
 
 impl ParameterWithValue {
     pub fn new(parameter: &'static ParameterInfo, value: PlainParameterValue) -> Self {
-        Self {
-            parameter,
-            value: value.into(),
-            normalized_value: parameter.normalized_to_plain_converter.convert_inverse(value).into(),
-        }
+        Self::new_normalized(parameter, parameter.normalized_to_plain_converter.convert_inverse(value))
     }
 
+    /// New values count as changed so values depending on them are computed on the first poll
     pub fn new_normalized(parameter: &'static ParameterInfo, value: NormalizedParameterValue) -> Self {
         Self {
             parameter,
             value: parameter.normalized_to_plain_converter.convert(value).into(),
             normalized_value: value.into(),
+            change_count: Cell::new(1),
+            listeners: RefCell::default(),
         }
     }
 
@@ -140,15 +254,71 @@ impl ParameterWithValue {
     }
 
     pub fn set(&self, value: PlainParameterValue) {
-        self.normalized_value.set(self.parameter.normalized_to_plain_converter.convert_inverse(value));
-        self.value.set(value);
+        let normalized_value = self.parameter.normalized_to_plain_converter.convert_inverse(value);
+        self.store(value, normalized_value);
     }
 
     pub fn get(&self) -> PlainParameterValue { self.value.get() }
 
     pub fn set_normalized(&self, value: NormalizedParameterValue) {
-        self.normalized_value.set(value);
-        self.value.set(self.parameter.normalized_to_plain_converter.convert(value));
+        self.store(self.parameter.normalized_to_plain_converter.convert(value), value);
+    }
+
+    /// Listeners are only called when the value actually changes
+    fn store(&self, value: PlainParameterValue, normalized_value: NormalizedParameterValue) {
+        let unchanged = self.normalized_value.get() == normalized_value && self.value.get() == value;
+        self.normalized_value.set(normalized_value);
+        self.value.set(value);
+
+        if unchanged {
+            return;
+        }
+
+        self.change_count.set(self.change_count.get().wrapping_add(1));
+
+        // Not borrowed while calling so listeners can subscribe, unsubscribe or set other values
+        let mut i = 0;
+
+        loop {
+            let listener = match self.listeners.borrow().get(i) {
+                Some(l) => l.clone(),
+                None => break,
+            };
+
+            listener(self);
+
+            // A listener removing itself shifts the following ones down
+            if self.listeners.borrow().get(i).map_or(false, |l| Rc::ptr_eq(l, &listener)) {
+                i += 1;
+            }
+        }
+    }
+
+    /// Incremented on every change of the value
+    pub fn change_count(&self) -> u64 { self.change_count.get() }
+
+    /// Returns true if the value changed since the change count in `last_seen`, which is then updated. Each consumer
+    /// keeps its own count starting at 0, e.g. to recompute filter coefficients in the processor only when needed
+    /// without missing changes seen by the editor.
+    pub fn take_changed(&self, last_seen: &mut u64) -> bool {
+        let count = self.change_count.get();
+        let changed = *last_seen != count;
+        *last_seen = count;
+        changed
+    }
+
+    pub fn is_changed(&self, last_seen: u64) -> bool { last_seen != self.change_count.get() }
+
+    /// Calls `listener` after every change of the value
+    pub fn subscribe(&self, listener: impl Fn(&ParameterWithValue) + 'static) -> ParameterListener {
+        let listener: ParameterListener = Rc::new(listener);
+        self.listeners.borrow_mut().push(listener.clone());
+        listener
+    }
+
+    /// Removes a listener returned by `subscribe`
+    pub fn unsubscribe(&self, listener: &ParameterListener) {
+        self.listeners.borrow_mut().retain(|l| !Rc::ptr_eq(l, listener));
     }
 
     pub fn get_normalized(&self) -> NormalizedParameterValue { self.normalized_value.get() }
@@ -177,7 +347,64 @@ impl ParameterValueContainer {
     }
 
     pub fn get_value(&self, id: ParameterId) -> Option<&Rc<ParameterWithValue>> { self.id_to_param.get(&id) }
+    /// Panics if there's no parameter with the id
     pub fn clone_value(&self, id: ParameterId) -> Rc<ParameterWithValue> { self.get_value(id).unwrap().clone() }
+
+    /// The change counts seen by a new consumer, all parameters are reported as changed by its first poll
+    pub fn new_tracker(&self) -> ChangeTracker {
+        ChangeTracker {
+            last_seen: vec![0; self.params.len()],
+        }
+    }
+
+    /// Fills `changed` with the ids of the parameters changed since the last call with `tracker` in declaration
+    /// order and returns their number. Changes that don't fit in `changed` are returned by the next call. Doesn't
+    /// allocate for trackers from `new_tracker`, so it can be called from the audio thread.
+    pub fn take_changed(&self, tracker: &mut ChangeTracker, changed: &mut [ParameterId]) -> usize {
+        if tracker.last_seen.len() != self.params.len() {
+            tracker.last_seen.resize(self.params.len(), 0);
+        }
+
+        let mut count = 0;
+
+        for (p, last_seen) in self.params.iter().zip(tracker.last_seen.iter_mut()) {
+            if count == changed.len() {
+                break;
+            }
+
+            if self.id_to_param[&p.id].take_changed(last_seen) {
+                changed[count] = p.id;
+                count += 1;
+            }
+        }
+
+        count
+    }
+
+    /// Calls `listener` after every change of any parameter
+    pub fn subscribe(&self, listener: impl Fn(&ParameterWithValue) + 'static) -> ParameterListener {
+        let listener: ParameterListener = Rc::new(listener);
+
+        for v in self.id_to_param.values() {
+            v.listeners.borrow_mut().push(listener.clone());
+        }
+
+        listener
+    }
+
+    /// Removes a listener returned by `subscribe` from all parameters
+    pub fn unsubscribe(&self, listener: &ParameterListener) {
+        for v in self.id_to_param.values() {
+            v.unsubscribe(listener);
+        }
+    }
+}
+
+/// The change counts of the parameters of a `ParameterValueContainer` last seen by one consumer, e.g. the processor
+/// or the editor
+#[derive(Clone, Debug, Default)]
+pub struct ChangeTracker {
+    last_seen: Vec<u64>,
 }
 
 impl Parameters for ParameterValueContainer {
@@ -193,3 +420,96 @@ impl Parameters for ParameterValueContainer {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn id(id: u32) -> ParameterId { id.into() }
+
+    fn params() -> &'static [&'static ParameterInfo] {
+        let gain: &'static ParameterInfo =
+            Box::leak(Box::new(ParameterInfo::new_linear(1.into(), "Gain", "%", 50.0, Range::new(0.0, 100.0))));
+        let freq: &'static ParameterInfo =
+            Box::leak(Box::new(ParameterInfo::new_linear(2.into(), "Freq", "Hz", 400.0, Range::new(20.0, 2000.0))));
+
+        Box::leak(vec![gain, freq].into_boxed_slice())
+    }
+
+    #[test]
+    fn consumers_track_changes_independently() {
+        let container = ParameterValueContainer::new(params());
+        let mut processor = container.new_tracker();
+        let mut editor = container.new_tracker();
+        let mut changed = [ParameterId::default(); 2];
+
+        assert_eq!(container.take_changed(&mut processor, &mut changed), 2);
+        assert_eq!(changed, [id(1), id(2)]);
+        assert_eq!(container.take_changed(&mut processor, &mut changed), 0);
+
+        container.clone_value(id(2)).set(1000.0.into());
+        assert_eq!(container.take_changed(&mut processor, &mut changed), 1);
+        assert_eq!(changed[0], id(2));
+
+        // Setting the same value again isn't a change
+        container.clone_value(id(2)).set(1000.0.into());
+        assert_eq!(container.take_changed(&mut processor, &mut changed), 0);
+
+        // The editor hasn't polled yet so it still sees all parameters
+        assert_eq!(container.take_changed(&mut editor, &mut changed), 2);
+    }
+
+    #[test]
+    fn changes_not_fitting_the_buffer_are_kept() {
+        let container = ParameterValueContainer::new(params());
+        let mut tracker = container.new_tracker();
+        let mut changed = [ParameterId::default(); 1];
+
+        assert_eq!(container.take_changed(&mut tracker, &mut changed), 1);
+        assert_eq!(changed[0], id(1));
+        assert_eq!(container.take_changed(&mut tracker, &mut changed), 1);
+        assert_eq!(changed[0], id(2));
+        assert_eq!(container.take_changed(&mut tracker, &mut changed), 0);
+    }
+
+    #[test]
+    fn unsubscribed_listeners_are_not_called() {
+        let container = ParameterValueContainer::new(params());
+        let calls = Rc::new(Cell::new(0));
+        let c = calls.clone();
+        let listener = container.subscribe(move |_| c.set(c.get() + 1));
+
+        container.clone_value(id(1)).set(10.0.into());
+        container.clone_value(id(2)).set(100.0.into());
+        assert_eq!(calls.get(), 2);
+
+        container.unsubscribe(&listener);
+        container.clone_value(id(1)).set(20.0.into());
+        assert_eq!(calls.get(), 2);
+    }
+
+    #[test]
+    fn listeners_can_unsubscribe_themselves() {
+        let value = Rc::new(ParameterWithValue::new_default(params()[0]));
+        let handle: Rc<RefCell<Option<ParameterListener>>> = Rc::default();
+        let calls = Rc::new(Cell::new(0));
+
+        let (v, h, c) = (value.clone(), handle.clone(), calls.clone());
+        let once = value.subscribe(move |_| {
+            c.set(c.get() + 1);
+            v.unsubscribe(h.borrow().as_ref().unwrap());
+        });
+
+        handle.replace(Some(once));
+
+        // The following listener is still called after the first one removed itself
+        let c = calls.clone();
+        value.subscribe(move |_| c.set(c.get() + 10));
+
+        value.set(10.0.into());
+        assert_eq!(calls.get(), 11);
+
+        value.set(20.0.into());
+        assert_eq!(calls.get(), 21);
+    }
+}
@@ -0,0 +1,209 @@
+use byteorder::{LittleEndian, ReadBytesExt};
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    io::{Error, ErrorKind},
+};
+
+use crate::{
+    edit_controller::{ProgramListId, ProgramListInfo},
+    plugin::Parameters,
+    plugin_parameter::{NormalizedParameterValue, ParameterId, ParameterInfo, ParameterPoint, ParameterValue},
+    unit::UnitInfo,
+    vst_stream::VstInStream,
+};
+
+pub type ProgramValues = Vec<(ParameterId, NormalizedParameterValue)>;
+
+/// A snapshot of parameter values, program lists are usually shared by all plugin instances so programs are immutable
+pub struct Program {
+    pub name:       String,
+    pub attributes: Vec<(String, String)>,
+    values:         ProgramValues,
+}
+
+impl Program {
+    /// Creates a program from a snapshot of plain parameter values
+    pub fn new(name: &str, values: &[(&ParameterInfo, ParameterValue)]) -> Self {
+        Self {
+            name:       name.into(),
+            attributes: Vec::new(),
+            values:     values
+                .iter()
+                .map(|(p, v)| (p.id, p.normalized_to_plain_converter.convert_inverse((*v).into())))
+                .collect(),
+        }
+    }
+
+    pub fn with_attribute(mut self, attribute_id: &str, value: &str) -> Self {
+        self.attributes.push((attribute_id.into(), value.into()));
+        self
+    }
+
+    pub fn get_attribute(&self, attribute_id: &str) -> Option<&str> {
+        self.attributes.iter().find(|(id, _)| id == attribute_id).map(|(_, v)| v.as_str())
+    }
+
+    pub fn get_values(&self) -> &[(ParameterId, NormalizedParameterValue)] { &self.values }
+}
+
+pub struct ProgramList {
+    pub info:      ProgramListInfo,
+    pub parameter: ParameterInfo,
+    programs:      Vec<Program>,
+}
+
+impl ProgramList {
+    /// Creates a program list together with its program change parameter
+    pub fn new(id: ProgramListId, parameter_id: ParameterId, name: &str, programs: Vec<Program>) -> Self {
+        let names = programs.iter().map(|p| p.name.as_str()).collect::<Vec<_>>();
+        let mut parameter = ParameterInfo::new_list(parameter_id, name, 0, &names);
+        parameter.flags.is_program_change = true;
+
+        Self {
+            info: ProgramListInfo {
+                id,
+                name: name.into(),
+                program_count: programs.len() as i32,
+            },
+            parameter,
+            programs,
+        }
+    }
+
+    pub fn programs(&self) -> &[Program] { &self.programs }
+
+    pub fn get_program(&self, index: i32) -> Option<&Program> {
+        if index >= 0 {
+            self.programs.get(index as usize)
+        }
+        else {
+            None
+        }
+    }
+
+    pub fn index_from_normalized(&self, value: NormalizedParameterValue) -> i32 {
+        *self.parameter.normalized_to_plain_converter.convert(value) as i32
+    }
+
+    pub fn normalized_from_index(&self, index: i32) -> NormalizedParameterValue {
+        self.parameter.normalized_to_plain_converter.convert_inverse((index as ParameterValue).into())
+    }
+
+    pub fn get_selected_index<T: Parameters + ?Sized>(&self, params: &T) -> i32 {
+        self.index_from_normalized(params.get_normalized_parameter_value(&self.parameter))
+    }
+
+    /// Applies the values of a program to all parameters in the snapshot, returns false if there is no such program
+    pub fn apply<T: Parameters + ?Sized>(&self, params: &T, index: i32) -> bool {
+        if let Some(program) = self.get_program(index) {
+            Self::apply_values(params, program.get_values());
+            true
+        }
+        else {
+            false
+        }
+    }
+
+    /// Program change parameters are skipped
+    pub fn apply_values<T: Parameters + ?Sized>(params: &T, values: &[(ParameterId, NormalizedParameterValue)]) {
+        for (id, value) in values {
+            if let Some(p) = params.get_parameter_by_id(*id) {
+                if !p.flags.is_program_change {
+                    params.set_normalized_parameter_value(p, *value);
+                }
+            }
+        }
+    }
+
+    pub fn apply_normalized<T: Parameters + ?Sized>(&self, params: &T, value: NormalizedParameterValue) -> bool {
+        self.apply(params, self.index_from_normalized(value))
+    }
+
+    /// Should be called from the audio processor to apply program changes sent by the host
+    pub fn update<T: Parameters + ?Sized>(
+        &self,
+        params: &T,
+        param_changes: &HashMap<ParameterId, Vec<ParameterPoint>>,
+    ) -> bool {
+        if let Some(v) = param_changes.get(&self.parameter.id).and_then(|v| v.last().map(|p| p.value)) {
+            params.set_normalized_parameter_value(&self.parameter, v);
+            self.apply_normalized(params, v)
+        }
+        else {
+            false
+        }
+    }
+
+    /// Reads the values of a program written in the same format as `write_parameter_values`
+    pub fn read_program_data(&self, index: i32, stream: &mut VstInStream) -> std::io::Result<ProgramValues> {
+        self.get_program(index).ok_or_else(|| Error::from(ErrorKind::NotFound))?;
+        let param_count = stream.read_u32::<LittleEndian>()?;
+        // The count comes from the host, a corrupt stream must not cause a huge allocation
+        let mut values = Vec::with_capacity(param_count.min(1024) as usize);
+
+        for _ in 0..param_count {
+            let id = stream.read_u32::<LittleEndian>()?;
+            let value = stream.read_f64::<LittleEndian>()?;
+            values.push((id.into(), value.into()));
+        }
+
+        Ok(values)
+    }
+}
+
+pub struct ProgramLists {
+    lists: Vec<ProgramList>,
+    infos: Vec<ProgramListInfo>,
+}
+
+impl ProgramLists {
+    pub fn new(lists: Vec<ProgramList>) -> Self {
+        let infos = lists.iter().map(|l| l.info.clone()).collect();
+        Self { lists, infos }
+    }
+
+    pub fn lists(&self) -> &[ProgramList] { &self.lists }
+    pub fn infos(&self) -> &[ProgramListInfo] { &self.infos }
+    pub fn get(&self, id: ProgramListId) -> Option<&ProgramList> { self.lists.iter().find(|l| l.info.id == id) }
+
+    pub fn get_by_parameter(&self, id: ParameterId) -> Option<&ProgramList> {
+        self.lists.iter().find(|l| l.parameter.id == id)
+    }
+
+    /// Hosts pass either a program list id or the id of a unit with a program list
+    pub fn get_by_list_or_unit(&self, id: i32, units: Option<&[UnitInfo]>) -> Option<&ProgramList> {
+        self.get(id.into()).or_else(|| units?.iter().find(|u| *u.id == id).and_then(|u| self.get(u.program_list_id)))
+    }
+
+    pub fn parameters(&self) -> impl Iterator<Item = &ParameterInfo> { self.lists.iter().map(|l| &l.parameter) }
+
+    pub fn update<T: Parameters + ?Sized>(
+        &self,
+        params: &T,
+        param_changes: &HashMap<ParameterId, Vec<ParameterPoint>>,
+    ) -> bool {
+        self.lists.iter().fold(false, |changed, l| l.update(params, param_changes) || changed)
+    }
+}
+
+/// Program data loaded by the host, e.g. from a preset file, kept per controller instance since the program lists are
+/// shared. The values replace the snapshots of the program lists when a program is selected.
+#[derive(Default)]
+pub struct LoadedPrograms {
+    programs: RefCell<HashMap<(ProgramListId, i32), ProgramValues>>,
+}
+
+impl LoadedPrograms {
+    pub fn new() -> Self { Self::default() }
+
+    pub fn set(&self, list: ProgramListId, index: i32, values: ProgramValues) {
+        self.programs.borrow_mut().insert((list, index), values);
+    }
+
+    pub fn get(&self, list: ProgramListId, index: i32) -> Option<ProgramValues> {
+        self.programs.borrow().get(&(list, index)).cloned()
+    }
+
+    pub fn clear(&self) { self.programs.borrow_mut().clear(); }
+}
@@ -0,0 +1,106 @@
+use std::fmt::Debug;
+use std::ops::{Add, AddAssign, Div, DivAssign, Mul, MulAssign, Neg, Sub, SubAssign};
+use vst3_sys::vst::{SymbolicSampleSizes, K_SAMPLE32, K_SAMPLE64};
+
+/// An audio sample type, `f32` or `f64`
+pub trait Sample:
+    Copy
+    + Default
+    + Debug
+    + PartialEq
+    + PartialOrd
+    + Add<Output = Self>
+    + Sub<Output = Self>
+    + Mul<Output = Self>
+    + Div<Output = Self>
+    + Neg<Output = Self>
+    + AddAssign
+    + SubAssign
+    + MulAssign
+    + DivAssign
+    + Send
+    + Sync
+    + 'static
+{
+    const ZERO: Self;
+    const ONE: Self;
+    const SYMBOLIC_SAMPLE_SIZE: i32;
+
+    fn symbolic_sample_size() -> SymbolicSampleSizes;
+
+    fn from_f32(v: f32) -> Self;
+    fn from_f64(v: f64) -> Self;
+    fn to_f32(self) -> f32;
+    fn to_f64(self) -> f64;
+
+    fn abs(self) -> Self;
+    fn min(self, other: Self) -> Self;
+    fn max(self, other: Self) -> Self;
+    fn clamp(self, min: Self, max: Self) -> Self;
+    fn sqrt(self) -> Self;
+    fn exp(self) -> Self;
+    fn ln(self) -> Self;
+    fn powf(self, n: Self) -> Self;
+    fn sin(self) -> Self;
+    fn cos(self) -> Self;
+    fn tanh(self) -> Self;
+
+    /// Linear interpolation between `self` and `other`
+    fn lerp(self, other: Self, t: Self) -> Self { self + (other - self) * t }
+
+    /// Converts decibels to a linear gain factor
+    fn from_db(db: Self) -> Self { Self::from_f64(10.0f64.powf(db.to_f64() / 20.0)) }
+
+    /// Converts a linear gain factor to decibels
+    fn to_db(self) -> Self { Self::from_f64(20.0 * self.to_f64().log10()) }
+}
+
+impl Sample for f32 {
+    const ZERO: Self = 0.0;
+    const ONE: Self = 1.0;
+    const SYMBOLIC_SAMPLE_SIZE: i32 = K_SAMPLE32;
+
+    fn symbolic_sample_size() -> SymbolicSampleSizes { SymbolicSampleSizes::kSample32 }
+
+    fn from_f32(v: f32) -> Self { v }
+    fn from_f64(v: f64) -> Self { v as f32 }
+    fn to_f32(self) -> f32 { self }
+    fn to_f64(self) -> f64 { self as f64 }
+
+    fn abs(self) -> Self { f32::abs(self) }
+    fn min(self, other: Self) -> Self { f32::min(self, other) }
+    fn max(self, other: Self) -> Self { f32::max(self, other) }
+    fn clamp(self, min: Self, max: Self) -> Self { f32::clamp(self, min, max) }
+    fn sqrt(self) -> Self { f32::sqrt(self) }
+    fn exp(self) -> Self { f32::exp(self) }
+    fn ln(self) -> Self { f32::ln(self) }
+    fn powf(self, n: Self) -> Self { f32::powf(self, n) }
+    fn sin(self) -> Self { f32::sin(self) }
+    fn cos(self) -> Self { f32::cos(self) }
+    fn tanh(self) -> Self { f32::tanh(self) }
+}
+
+impl Sample for f64 {
+    const ZERO: Self = 0.0;
+    const ONE: Self = 1.0;
+    const SYMBOLIC_SAMPLE_SIZE: i32 = K_SAMPLE64;
+
+    fn symbolic_sample_size() -> SymbolicSampleSizes { SymbolicSampleSizes::kSample64 }
+
+    fn from_f32(v: f32) -> Self { v as f64 }
+    fn from_f64(v: f64) -> Self { v }
+    fn to_f32(self) -> f32 { self as f32 }
+    fn to_f64(self) -> f64 { self }
+
+    fn abs(self) -> Self { f64::abs(self) }
+    fn min(self, other: Self) -> Self { f64::min(self, other) }
+    fn max(self, other: Self) -> Self { f64::max(self, other) }
+    fn clamp(self, min: Self, max: Self) -> Self { f64::clamp(self, min, max) }
+    fn sqrt(self) -> Self { f64::sqrt(self) }
+    fn exp(self) -> Self { f64::exp(self) }
+    fn ln(self) -> Self { f64::ln(self) }
+    fn powf(self, n: Self) -> Self { f64::powf(self, n) }
+    fn sin(self) -> Self { f64::sin(self) }
+    fn cos(self) -> Self { f64::cos(self) }
+    fn tanh(self) -> Self { f64::tanh(self) }
+}
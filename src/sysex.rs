@@ -0,0 +1,345 @@
+use std::borrow::Cow;
+
+const START: u8 = 0xF0;
+const END: u8 = 0xF7;
+const UNIVERSAL_NON_REAL_TIME: u8 = 0x7E;
+const UNIVERSAL_REAL_TIME: u8 = 0x7F;
+const TUNING_STANDARD: u8 = 0x08;
+
+/// The bytes of a SysEx message including the leading 0xF0 and trailing 0xF7, input messages borrow the data of the
+/// host and are only valid for the current block
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct SysEx<'t> {
+    bytes: Cow<'t, [u8]>,
+}
+
+impl<'t> SysEx<'t> {
+    pub fn borrowed(bytes: &'t [u8]) -> Self {
+        Self {
+            bytes: Cow::Borrowed(bytes),
+        }
+    }
+
+    pub fn bytes(&self) -> &[u8] { &self.bytes }
+
+    /// Copies borrowed data, e.g. to keep an input message beyond the current block
+    pub fn into_owned(self) -> SysEx<'static> {
+        SysEx {
+            bytes: Cow::Owned(self.bytes.into_owned()),
+        }
+    }
+
+    /// The data between the start and end bytes, `None` if they're missing
+    pub fn payload(&self) -> Option<&[u8]> { self.bytes.strip_prefix(&[START])?.strip_suffix(&[END]) }
+
+    pub fn parse(&self) -> Option<SysExMessage> { SysExMessage::parse(self.payload()?) }
+}
+
+impl SysEx<'static> {
+    pub fn new(bytes: Vec<u8>) -> Self {
+        Self {
+            bytes: Cow::Owned(bytes),
+        }
+    }
+}
+
+/// A key retuned by the MIDI Tuning Standard
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct NoteTuning {
+    pub key: u8,
+
+    /// The equal tempered note the key is tuned to
+    pub semitone: u8,
+
+    /// Fraction of a semitone above `semitone` in units of 100/16384 cents
+    pub fraction: u16,
+}
+
+impl NoteTuning {
+    fn parse(key: u8, data: &[u8]) -> Option<Self> {
+        match *data {
+            // 7F 7F 7F means no change
+            [0x7F, 0x7F, 0x7F] => None,
+            [semitone, msb, lsb] => Some(Self {
+                key,
+                semitone,
+                fraction: (msb as u16) << 7 | lsb as u16,
+            }),
+            _ => None,
+        }
+    }
+
+    /// The tuned pitch as a fractional note number
+    pub fn pitch(&self) -> f64 { self.semitone as f64 + self.fraction as f64 / 16384.0 }
+
+    /// The frequency in Hz, A4 is 440 Hz
+    pub fn frequency(&self) -> f64 { 440.0 * 2f64.powf((self.pitch() - 69.0) / 12.0) }
+
+    /// The offset from the equal tempered pitch of the key in cents
+    pub fn cents_offset(&self) -> f64 { (self.pitch() - self.key as f64) * 100.0 }
+}
+
+/// Common universal SysEx messages
+#[derive(Clone, Debug, PartialEq)]
+pub enum SysExMessage {
+    IdentityRequest {
+        device_id: u8,
+    },
+
+    /// 14-bit volume
+    MasterVolume {
+        device_id: u8,
+        volume:    u16,
+    },
+
+    /// MTS bulk tuning dump of all 128 keys, keys that aren't changed are `None`
+    BulkTuningDump {
+        device_id: u8,
+        program:   u8,
+        name:      String,
+        tunings:   Vec<Option<NoteTuning>>,
+    },
+
+    /// MTS single note tuning change, `bank` is only present in the bank select variant
+    NoteTuningChange {
+        device_id: u8,
+        bank:      Option<u8>,
+        program:   u8,
+        real_time: bool,
+        changes:   Vec<NoteTuning>,
+    },
+
+    /// MTS scale/octave tuning, the same offset in cents is applied to every octave of a pitch class
+    ScaleOctaveTuning {
+        device_id: u8,
+
+        /// Bit N is set if MIDI channel N is affected
+        channels:  u16,
+        real_time: bool,
+
+        /// Offsets in cents from C to B
+        offsets: [f64; 12],
+    },
+}
+
+impl SysExMessage {
+    /// Parses the payload of a SysEx message, i.e. without the start and end bytes
+    pub fn parse(payload: &[u8]) -> Option<Self> {
+        let (&kind, &device_id, &sub_id1, &sub_id2, data) = match payload {
+            [kind, device_id, sub_id1, sub_id2, data @ ..] => (kind, device_id, sub_id1, sub_id2, data),
+            _ => return None,
+        };
+
+        let real_time = match kind {
+            UNIVERSAL_NON_REAL_TIME => false,
+            UNIVERSAL_REAL_TIME => true,
+            _ => return None,
+        };
+
+        match (real_time, sub_id1, sub_id2) {
+            (false, 0x06, 0x01) => Some(Self::IdentityRequest { device_id }),
+
+            (true, 0x04, 0x01) => match *data {
+                [lsb, msb] => Some(Self::MasterVolume {
+                    device_id,
+                    volume: (msb as u16) << 7 | lsb as u16,
+                }),
+                _ => None,
+            },
+
+            (false, TUNING_STANDARD, 0x01) => Self::parse_bulk_dump(device_id, data),
+            (true, TUNING_STANDARD, 0x02) => Self::parse_note_changes(device_id, None, real_time, data),
+
+            (_, TUNING_STANDARD, 0x07) => {
+                let (&bank, data) = data.split_first()?;
+                Self::parse_note_changes(device_id, Some(bank), real_time, data)
+            }
+
+            (_, TUNING_STANDARD, 0x08) => Self::parse_scale_octave(device_id, real_time, data, 1),
+            (_, TUNING_STANDARD, 0x09) => Self::parse_scale_octave(device_id, real_time, data, 2),
+            _ => None,
+        }
+    }
+
+    fn parse_bulk_dump(device_id: u8, data: &[u8]) -> Option<Self> {
+        // Program, 16 bytes name, 128 tunings and a checksum
+        if data.len() < 1 + 16 + 128 * 3 {
+            return None;
+        }
+
+        let name = data[1..17].iter().map(|&b| b as char).collect::<String>().trim_end().to_string();
+
+        Some(Self::BulkTuningDump {
+            device_id,
+            program: data[0],
+            name,
+            tunings: data[17..17 + 128 * 3].chunks(3).enumerate().map(|(k, t)| NoteTuning::parse(k as u8, t)).collect(),
+        })
+    }
+
+    fn parse_note_changes(device_id: u8, bank: Option<u8>, real_time: bool, data: &[u8]) -> Option<Self> {
+        let (&program, data) = data.split_first()?;
+        let (&count, data) = data.split_first()?;
+
+        if data.len() < count as usize * 4 {
+            return None;
+        }
+
+        Some(Self::NoteTuningChange {
+            device_id,
+            bank,
+            program,
+            real_time,
+            changes: data.chunks(4).take(count as usize).filter_map(|c| NoteTuning::parse(c[0], &c[1..])).collect(),
+        })
+    }
+
+    fn parse_scale_octave(device_id: u8, real_time: bool, data: &[u8], bytes_per_offset: usize) -> Option<Self> {
+        if data.len() < 3 + 12 * bytes_per_offset {
+            return None;
+        }
+
+        // Channels 16-15, 14-8 and 7-1
+        let channels = (data[0] as u16 & 0x03) << 14 | (data[1] as u16 & 0x7F) << 7 | data[2] as u16 & 0x7F;
+        let mut offsets = [0.0; 12];
+
+        for (o, d) in offsets.iter_mut().zip(data[3..].chunks(bytes_per_offset)) {
+            *o = match *d {
+                // 0x40 is 0 cents, the range is -64 to +63 cents
+                [v] => v as f64 - 64.0,
+
+                // 0x2000 is 0 cents, the range is -100 to +100 cents
+                [msb, lsb] => (((msb as u16) << 7 | lsb as u16) as f64 - 8192.0) * 100.0 / 8192.0,
+                _ => 0.0,
+            };
+        }
+
+        Some(Self::ScaleOctaveTuning {
+            device_id,
+            channels,
+            real_time,
+            offsets,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(payload: &[u8]) -> Option<SysExMessage> {
+        let mut bytes = vec![START];
+        bytes.extend_from_slice(payload);
+        bytes.push(END);
+        SysEx::new(bytes).parse()
+    }
+
+    #[test]
+    fn payload_requires_start_and_end() {
+        assert_eq!(SysEx::borrowed(&[START, 1, 2, END]).payload(), Some(&[1, 2][..]));
+        assert_eq!(SysEx::borrowed(&[START, 1, 2]).payload(), None);
+        assert_eq!(SysEx::borrowed(&[1, 2, END]).payload(), None);
+    }
+
+    #[test]
+    fn parses_identity_request_and_master_volume() {
+        assert_eq!(
+            message(&[UNIVERSAL_NON_REAL_TIME, 0x7F, 0x06, 0x01]),
+            Some(SysExMessage::IdentityRequest { device_id: 0x7F })
+        );
+
+        assert_eq!(
+            message(&[UNIVERSAL_REAL_TIME, 0x10, 0x04, 0x01, 0x00, 0x40]),
+            Some(SysExMessage::MasterVolume {
+                device_id: 0x10,
+                volume:    0x2000,
+            })
+        );
+    }
+
+    #[test]
+    fn parses_single_note_tuning_change() {
+        // Key 69 to 69 + 8192/16384 semitones, key 60 unchanged
+        let payload = [
+            UNIVERSAL_REAL_TIME,
+            0x7F,
+            TUNING_STANDARD,
+            0x02,
+            3,
+            2,
+            69,
+            69,
+            0x40,
+            0x00,
+            60,
+            0x7F,
+            0x7F,
+            0x7F,
+        ];
+
+        match message(&payload) {
+            Some(SysExMessage::NoteTuningChange {
+                program,
+                bank,
+                real_time,
+                changes,
+                ..
+            }) => {
+                assert_eq!((program, bank, real_time), (3, None, true));
+                assert_eq!(changes.len(), 1);
+                assert_eq!(changes[0].key, 69);
+                assert!((changes[0].cents_offset() - 50.0).abs() < 1e-9);
+            }
+
+            m => panic!("Unexpected message {:?}", m),
+        }
+    }
+
+    #[test]
+    fn parses_bulk_tuning_dump() {
+        let mut payload = vec![UNIVERSAL_NON_REAL_TIME, 0x7F, TUNING_STANDARD, 0x01, 5];
+        payload.extend_from_slice(b"Test            ");
+
+        for key in 0..128u8 {
+            payload.extend_from_slice(&[key, 0, 0]);
+        }
+
+        payload.push(0);
+
+        match message(&payload) {
+            Some(SysExMessage::BulkTuningDump {
+                program, name, tunings, ..
+            }) => {
+                assert_eq!((program, name.as_str()), (5, "Test"));
+                assert_eq!(tunings.len(), 128);
+                assert!(tunings.iter().flatten().all(|t| t.cents_offset() == 0.0));
+            }
+
+            m => panic!("Unexpected message {:?}", m),
+        }
+    }
+
+    #[test]
+    fn parses_scale_octave_tuning() {
+        let mut payload = vec![UNIVERSAL_NON_REAL_TIME, 0x7F, TUNING_STANDARD, 0x08, 0x03, 0x7F, 0x7F];
+        payload.extend((0..12).map(|i| 0x40 + i as u8));
+
+        match message(&payload) {
+            Some(SysExMessage::ScaleOctaveTuning { channels, offsets, .. }) => {
+                assert_eq!(channels, 0xFFFF);
+                assert_eq!(offsets[0], 0.0);
+                assert_eq!(offsets[11], 11.0);
+            }
+
+            m => panic!("Unexpected message {:?}", m),
+        }
+    }
+
+    #[test]
+    fn rejects_truncated_and_unknown_messages() {
+        assert_eq!(message(&[UNIVERSAL_NON_REAL_TIME, 0x7F, TUNING_STANDARD, 0x01, 0]), None);
+        assert_eq!(message(&[UNIVERSAL_REAL_TIME, 0x7F, TUNING_STANDARD, 0x02, 0, 2, 60]), None);
+        assert_eq!(message(&[0x41, 0x10, 0x42, 0x12]), None);
+        assert_eq!(message(&[UNIVERSAL_REAL_TIME]), None);
+    }
+}
@@ -0,0 +1,423 @@
+//! In-process host for exercising plugins from `cargo test` without loading them into a DAW
+
+use crate::audio_processor::{Event, EventData};
+use crate::edit_controller::{BusDirection, MediaType};
+use crate::host::{ClassInfo, Component, Controller, PluginFactory, ProcessBuffers};
+use crate::plugin_parameter::{NormalizedParameterValue, ParameterId, ParameterPoint};
+use crate::sample::Sample;
+use crate::sysex::SysEx;
+use crate::vst_factory::{VstPluginFactory, AUDIO_MODULE_CLASS};
+use std::collections::HashMap;
+use uuid::Uuid;
+use vst3_com::c_void;
+use vst3_sys::base::{kResultOk, tresult};
+use vst3_sys::vst::{
+    NoteOffEvent, NoteOnEvent, ProcessModes, ProcessSetup, SymbolicSampleSizes, K_SAMPLE32, K_SAMPLE64,
+};
+
+pub struct TestSetup {
+    pub sample_rate:    f64,
+    pub max_block_size: usize,
+    pub sample_size:    SymbolicSampleSizes,
+    pub process_mode:   ProcessModes,
+}
+
+impl Default for TestSetup {
+    fn default() -> Self {
+        Self {
+            sample_rate:    48000.0,
+            max_block_size: 512,
+            sample_size:    SymbolicSampleSizes::kSample32,
+            process_mode:   ProcessModes::kRealtime,
+        }
+    }
+}
+
+/// Input for one process call, missing input channels are filled with silence
+#[derive(Default)]
+pub struct TestBlock {
+    pub num_samples:         usize,
+    pub inputs:              Vec<Vec<Vec<f64>>>,
+    pub input_silence_flags: Vec<u64>,
+    pub events:              Vec<Event<'static>>,
+    pub param_changes:       Vec<(ParameterId, ParameterPoint)>,
+}
+
+impl TestBlock {
+    pub fn new(num_samples: usize) -> Self {
+        Self {
+            num_samples,
+            ..Default::default()
+        }
+    }
+
+    pub fn input(mut self, bus: usize, channel: usize, samples: &[f64]) -> Self {
+        if self.inputs.len() <= bus {
+            self.inputs.resize(bus + 1, Vec::new());
+        }
+
+        if self.inputs[bus].len() <= channel {
+            self.inputs[bus].resize(channel + 1, Vec::new());
+        }
+
+        self.inputs[bus][channel] = samples.to_vec();
+        self
+    }
+
+    pub fn input_silence_flags(mut self, bus: usize, flags: u64) -> Self {
+        if self.input_silence_flags.len() <= bus {
+            self.input_silence_flags.resize(bus + 1, 0);
+        }
+
+        self.input_silence_flags[bus] = flags;
+        self
+    }
+
+    pub fn event(mut self, event: Event<'static>) -> Self {
+        self.events.push(event);
+        self
+    }
+
+    pub fn note_on(self, sample_offset: i32, channel: i16, pitch: i16, velocity: f32) -> Self {
+        self.event(Event::new(
+            sample_offset,
+            EventData::NoteOn(NoteOnEvent {
+                channel,
+                pitch,
+                tuning: 0.0,
+                velocity,
+                length: 0,
+                note_id: -1,
+            }),
+        ))
+    }
+
+    pub fn note_off(self, sample_offset: i32, channel: i16, pitch: i16) -> Self {
+        self.event(Event::new(
+            sample_offset,
+            EventData::NoteOff(NoteOffEvent {
+                channel,
+                pitch,
+                velocity: 0.0,
+                note_id: -1,
+                tuning: 0.0,
+            }),
+        ))
+    }
+
+    /// `bytes` must include the start and end bytes
+    pub fn sysex(self, sample_offset: i32, bytes: &[u8]) -> Self {
+        self.event(Event::new(sample_offset, EventData::SysEx(SysEx::new(bytes.to_vec()))))
+    }
+
+    pub fn param_change(mut self, id: ParameterId, sample_offset: i32, value: f64) -> Self {
+        self.param_changes.push((
+            id,
+            ParameterPoint {
+                sample_offset,
+                value: value.into(),
+            },
+        ));
+
+        self
+    }
+}
+
+pub struct TestOutput {
+    pub result:        tresult,
+    pub outputs:       Vec<Vec<Vec<f64>>>,
+    pub silence_flags: Vec<u64>,
+    pub events:        Vec<Event<'static>>,
+    pub param_changes: HashMap<ParameterId, Vec<ParameterPoint>>,
+}
+
+impl TestOutput {
+    pub fn is_ok(&self) -> bool { self.result == kResultOk }
+    pub fn channel(&self, bus: usize, channel: usize) -> &[f64] { &self.outputs[bus][channel] }
+
+    /// The highest absolute sample value of all output channels
+    pub fn peak(&self) -> f64 { self.outputs.iter().flatten().flatten().fold(0.0, |p, s| p.max(s.abs())) }
+}
+
+enum Buffers {
+    F32(ProcessBuffers<f32>),
+    F64(ProcessBuffers<f64>),
+}
+
+fn run<T: Sample>(
+    buffers: &mut ProcessBuffers<T>,
+    component: &Component,
+    process_mode: ProcessModes,
+    block: &TestBlock,
+) -> TestOutput {
+    let num_samples = block.num_samples.min(buffers.max_block_size());
+
+    for bus in 0..buffers.inputs().len() {
+        for channel in 0..buffers.inputs()[bus].len() {
+            let src = block.inputs.get(bus).and_then(|b| b.get(channel));
+            let dst = buffers.input_mut(bus, channel);
+
+            for (i, s) in dst.iter_mut().enumerate() {
+                *s = T::from_f64(src.and_then(|src| src.get(i)).copied().unwrap_or(0.0));
+            }
+        }
+
+        buffers.set_input_silence_flags(bus, block.input_silence_flags.get(bus).copied().unwrap_or(0));
+    }
+
+    for e in block.events.iter() {
+        buffers.add_event(e);
+    }
+
+    for (id, point) in block.param_changes.iter() {
+        buffers.add_parameter_change(*id, *point);
+    }
+
+    let result = buffers.process(component, process_mode, num_samples);
+
+    TestOutput {
+        result,
+        outputs: buffers
+            .outputs()
+            .iter()
+            .map(|b| b.iter().map(|c| c[..num_samples].iter().map(|s| s.to_f64()).collect()).collect())
+            .collect(),
+        silence_flags: (0..buffers.outputs().len()).map(|b| buffers.output_silence_flags(b)).collect(),
+        events: buffers.take_output_events(),
+        param_changes: buffers.take_output_parameter_changes(),
+    }
+}
+
+pub struct TestHost {
+    factory: PluginFactory,
+}
+
+impl TestHost {
+    pub fn new(factory: Box<VstPluginFactory>) -> Self {
+        Self {
+            factory: PluginFactory::new(factory),
+        }
+    }
+
+    pub fn from_factory(factory: PluginFactory) -> Self { Self { factory } }
+
+    /// Uses the exported `GetPluginFactory` function of a plugin
+    pub unsafe fn from_entry_point(get_plugin_factory: unsafe extern "system" fn() -> *mut c_void) -> Option<Self> {
+        PluginFactory::from_raw(get_plugin_factory()).map(|factory| Self { factory })
+    }
+
+    pub fn factory(&self) -> &PluginFactory { &self.factory }
+    pub fn classes(&self) -> Vec<ClassInfo> { self.factory.classes() }
+
+    /// Creates and initializes the processor and its edit controller, the controller receives the component state.
+    /// Single component plugins without a controller class are queried for the controller interface.
+    pub fn create_plugin(&self, processor_cid: Uuid) -> Option<TestPlugin> {
+        let component = self.factory.create_component(processor_cid)?;
+
+        if !component.initialize() {
+            return None;
+        }
+
+        let controller = match component.controller_class_id() {
+            Some(cid) => self.factory.create_controller(cid),
+            None => component.controller(),
+        };
+
+        if let Some(c) = controller.as_ref() {
+            if !c.initialize() {
+                component.terminate();
+                return None;
+            }
+
+            if let Some(state) = component.get_state() {
+                c.set_component_state(&state);
+            }
+        }
+
+        Some(TestPlugin {
+            component,
+            controller,
+            process_mode: ProcessModes::kRealtime,
+            buffers: None,
+            pending_changes: Vec::new(),
+        })
+    }
+
+    /// Creates the first audio processor class of the factory
+    pub fn create_first_plugin(&self) -> Option<TestPlugin> {
+        let cid = self.classes().into_iter().find(|c| c.category == AUDIO_MODULE_CLASS)?.cid;
+        self.create_plugin(cid)
+    }
+}
+
+pub struct TestPlugin {
+    component:       Component,
+    controller:      Option<Controller>,
+    process_mode:    ProcessModes,
+    buffers:         Option<Buffers>,
+    pending_changes: Vec<(ParameterId, ParameterPoint)>,
+}
+
+impl TestPlugin {
+    pub fn component(&self) -> &Component { &self.component }
+    pub fn controller(&self) -> Option<&Controller> { self.controller.as_ref() }
+    pub fn is_processing(&self) -> bool { self.buffers.is_some() }
+
+    /// Sets up processing, activates all buses and the component
+    pub fn start(&mut self, setup: TestSetup) -> bool {
+        self.stop();
+
+        let symbolic_sample_size = match setup.sample_size {
+            SymbolicSampleSizes::kSample32 => K_SAMPLE32,
+            SymbolicSampleSizes::kSample64 => K_SAMPLE64,
+        };
+
+        let process_setup = ProcessSetup {
+            process_mode: setup.process_mode as i32,
+            symbolic_sample_size,
+            max_samples_per_block: setup.max_block_size as i32,
+            sample_rate: setup.sample_rate,
+        };
+
+        if !self.component.can_process_sample_size(symbolic_sample_size) ||
+            !self.component.setup_processing(&process_setup)
+        {
+            return false;
+        }
+
+        for media_type in [MediaType::Audio, MediaType::Event] {
+            for dir in [BusDirection::Input, BusDirection::Output] {
+                for i in 0..self.component.bus_count(media_type, dir) {
+                    self.component.activate_bus(media_type, dir, i, true);
+                }
+            }
+        }
+
+        let channels =
+            |dir| self.component.buses(MediaType::Audio, dir).iter().map(|b| b.channel_count).collect::<Vec<_>>();
+
+        let (inputs, outputs) = (channels(BusDirection::Input), channels(BusDirection::Output));
+
+        if !self.component.set_active(true) {
+            return false;
+        }
+
+        self.component.set_processing(true);
+        self.process_mode = setup.process_mode;
+
+        self.buffers = Some(match setup.sample_size {
+            SymbolicSampleSizes::kSample32 => {
+                Buffers::F32(ProcessBuffers::new(&inputs, &outputs, setup.max_block_size, setup.sample_rate))
+            }
+
+            SymbolicSampleSizes::kSample64 => {
+                Buffers::F64(ProcessBuffers::new(&inputs, &outputs, setup.max_block_size, setup.sample_rate))
+            }
+        });
+
+        true
+    }
+
+    pub fn stop(&mut self) {
+        if self.buffers.take().is_some() {
+            self.component.set_processing(false);
+            self.component.set_active(false);
+        }
+    }
+
+    /// Sets the value in the controller and passes the change to the processor in the next block, like a host does
+    /// for user edits
+    pub fn set_parameter(&mut self, id: ParameterId, value: NormalizedParameterValue) {
+        if let Some(c) = self.controller.as_ref() {
+            c.set_normalized(id, value);
+        }
+
+        self.pending_changes.push((
+            id,
+            ParameterPoint {
+                sample_offset: 0,
+                value,
+            },
+        ));
+    }
+
+    /// Returns None if processing hasn't been started, output parameter changes are forwarded to the controller
+    pub fn process(&mut self, block: &TestBlock) -> Option<TestOutput> {
+        let buffers = self.buffers.as_mut()?;
+
+        for (id, point) in self.pending_changes.drain(..) {
+            match buffers {
+                Buffers::F32(b) => b.add_parameter_change(id, point),
+                Buffers::F64(b) => b.add_parameter_change(id, point),
+            }
+        }
+
+        let output = match buffers {
+            Buffers::F32(b) => run(b, &self.component, self.process_mode, block),
+            Buffers::F64(b) => run(b, &self.component, self.process_mode, block),
+        };
+
+        if let Some(c) = self.controller.as_ref() {
+            for (id, points) in output.param_changes.iter() {
+                if let Some(p) = points.last() {
+                    c.set_normalized(*id, p.value);
+                }
+            }
+        }
+
+        Some(output)
+    }
+
+    /// Processes consecutive blocks of at most `block_size` and the max block size and concatenates the outputs,
+    /// returns None if `block_size` is 0
+    pub fn render(&mut self, num_samples: usize, block_size: usize) -> Option<Vec<Vec<Vec<f64>>>> {
+        let max_block_size = match self.buffers.as_ref()? {
+            Buffers::F32(b) => b.max_block_size(),
+            Buffers::F64(b) => b.max_block_size(),
+        };
+
+        let block_size = block_size.min(max_block_size);
+
+        if block_size == 0 {
+            return None;
+        }
+
+        let mut result: Vec<Vec<Vec<f64>>> = Vec::new();
+        let mut pos = 0;
+
+        while pos < num_samples {
+            let n = block_size.min(num_samples - pos);
+            let output = self.process(&TestBlock::new(n))?;
+
+            if !output.is_ok() {
+                return None;
+            }
+
+            result.resize_with(output.outputs.len(), Vec::new);
+
+            for (bus, channels) in output.outputs.into_iter().enumerate() {
+                result[bus].resize_with(channels.len(), Vec::new);
+
+                for (channel, samples) in channels.into_iter().enumerate() {
+                    result[bus][channel].extend(samples);
+                }
+            }
+
+            pos += n;
+        }
+
+        Some(result)
+    }
+}
+
+impl Drop for TestPlugin {
+    fn drop(&mut self) {
+        self.stop();
+
+        if let Some(c) = self.controller.take() {
+            c.terminate();
+        }
+
+        self.component.terminate();
+    }
+}
@@ -0,0 +1,279 @@
+use vst3_sys::vst::ProcessContext;
+
+// ProcessContext state flags
+pub const PLAYING: u32 = 1 << 1;
+pub const CYCLE_ACTIVE: u32 = 1 << 2;
+pub const RECORDING: u32 = 1 << 3;
+pub const SYSTEM_TIME_VALID: u32 = 1 << 8;
+pub const PROJECT_TIME_MUSIC_VALID: u32 = 1 << 9;
+pub const TEMPO_VALID: u32 = 1 << 10;
+pub const BAR_POSITION_VALID: u32 = 1 << 11;
+pub const CYCLE_VALID: u32 = 1 << 12;
+pub const TIME_SIG_VALID: u32 = 1 << 13;
+pub const SMPTE_VALID: u32 = 1 << 14;
+pub const CLOCK_VALID: u32 = 1 << 15;
+pub const CONT_TIME_VALID: u32 = 1 << 17;
+pub const CHORD_VALID: u32 = 1 << 18;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TimeSignature {
+    pub numerator:   i32,
+    pub denominator: i32,
+}
+
+impl TimeSignature {
+    /// The length of a bar in quarter notes
+    pub fn bar_length(&self) -> f64 { self.numerator as f64 * 4.0 / self.denominator as f64 }
+}
+
+/// Loop range in quarter notes
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Cycle {
+    pub start: f64,
+    pub end:   f64,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Chord {
+    pub key_note:   u8,
+    pub root_note:  u8,
+    pub chord_mask: i16,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Smpte {
+    /// Offset in 1/80 of a frame
+    pub offset_subframes:  i32,
+    pub frames_per_second: u32,
+    pub frame_rate_flags:  u32,
+}
+
+/// The transport state of the host, only the values marked as valid by the host are set
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Transport {
+    pub sample_rate:             f64,
+    pub is_playing:              bool,
+    pub is_recording:            bool,
+    pub is_cycle_active:         bool,
+    pub project_time_samples:    i64,
+    pub continuous_time_samples: Option<i64>,
+
+    /// System time in nanoseconds
+    pub system_time: Option<i64>,
+
+    /// Musical position in quarter notes
+    pub project_time_music: Option<f64>,
+
+    /// Position of the start of the current bar in quarter notes
+    pub bar_position_music:    Option<f64>,
+    pub cycle:                 Option<Cycle>,
+    pub tempo:                 Option<f64>,
+    pub time_signature:        Option<TimeSignature>,
+    pub chord:                 Option<Chord>,
+    pub smpte:                 Option<Smpte>,
+    pub samples_to_next_clock: Option<i32>,
+}
+
+fn valid<T>(state: u32, flag: u32, value: T) -> Option<T> {
+    if state & flag != 0 {
+        Some(value)
+    }
+    else {
+        None
+    }
+}
+
+impl Transport {
+    /// Decodes a process context, `sample_rate` is used if the context is missing or has no valid sample rate
+    pub fn new(context: Option<&ProcessContext>, sample_rate: f64) -> Self {
+        let c = match context {
+            Some(c) => c,
+            None => {
+                return Self {
+                    sample_rate,
+                    ..Default::default()
+                }
+            }
+        };
+
+        let s = c.state;
+
+        Self {
+            sample_rate:             if c.sample_rate > 0.0 { c.sample_rate } else { sample_rate },
+            is_playing:              s & PLAYING != 0,
+            is_recording:            s & RECORDING != 0,
+            is_cycle_active:         s & CYCLE_ACTIVE != 0,
+            project_time_samples:    c.project_time_samples,
+            continuous_time_samples: valid(s, CONT_TIME_VALID, c.continuous_time_samples),
+            system_time:             valid(s, SYSTEM_TIME_VALID, c.system_time),
+            project_time_music:      valid(s, PROJECT_TIME_MUSIC_VALID, c.project_time_music),
+            bar_position_music:      valid(s, BAR_POSITION_VALID, c.bar_position_music),
+            cycle:                   valid(
+                s,
+                CYCLE_VALID,
+                Cycle {
+                    start: c.cycle_start_music,
+                    end:   c.cycle_end_music,
+                },
+            ),
+            tempo:                   valid(s, TEMPO_VALID, c.tempo).filter(|t| *t > 0.0),
+            time_signature:          valid(
+                s,
+                TIME_SIG_VALID,
+                TimeSignature {
+                    numerator:   c.time_sig_numerator,
+                    denominator: c.time_sig_denominator,
+                },
+            )
+            .filter(|t| t.numerator > 0 && t.denominator > 0),
+            chord:                   valid(
+                s,
+                CHORD_VALID,
+                Chord {
+                    key_note:   c.chord.key_note,
+                    root_note:  c.chord.root_note,
+                    chord_mask: c.chord.chord_mask,
+                },
+            ),
+            smpte:                   valid(
+                s,
+                SMPTE_VALID,
+                Smpte {
+                    offset_subframes:  c.smpte_offset_subframes,
+                    frames_per_second: c.frame_rate.frames_per_second,
+                    frame_rate_flags:  c.frame_rate.flags,
+                },
+            ),
+            samples_to_next_clock:   valid(s, CLOCK_VALID, c.samples_to_next_clock),
+        }
+    }
+
+    /// Project time in seconds
+    pub fn project_time_seconds(&self) -> f64 { self.project_time_samples as f64 / self.sample_rate }
+
+    pub fn samples_per_beat(&self) -> Option<f64> { self.tempo.map(|t| self.sample_rate * 60.0 / t) }
+
+    /// Converts a number of samples to quarter notes
+    pub fn samples_to_beats(&self, samples: f64) -> Option<f64> { self.samples_per_beat().map(|s| samples / s) }
+
+    /// Converts a number of quarter notes to samples
+    pub fn beats_to_samples(&self, beats: f64) -> Option<f64> { self.samples_per_beat().map(|s| beats * s) }
+
+    /// The musical position in quarter notes at a sample offset in the current block
+    pub fn beat_at(&self, sample_offset: i32) -> Option<f64> {
+        Some(self.project_time_music? + self.samples_to_beats(sample_offset as f64)?)
+    }
+
+    /// The sample offset relative to the current block of a musical position, negative if it has already passed
+    pub fn sample_offset_of_beat(&self, beat: f64) -> Option<f64> {
+        self.beats_to_samples(beat - self.project_time_music?)
+    }
+
+    /// The length of a bar in quarter notes
+    pub fn bar_length(&self) -> Option<f64> { self.time_signature.map(|t| t.bar_length()) }
+
+    /// The start of the current bar in quarter notes, calculated from the time signature if the host doesn't provide it
+    pub fn bar_start(&self) -> Option<f64> {
+        match self.bar_position_music {
+            Some(b) => Some(b),
+            None => {
+                let bar_length = self.bar_length()?;
+                Some((self.project_time_music? / bar_length).floor() * bar_length)
+            }
+        }
+    }
+
+    /// The position in quarter notes relative to the start of the current bar
+    pub fn position_in_bar(&self) -> Option<f64> { Some(self.project_time_music? - self.bar_start()?) }
+
+    /// The zero based index of the current bar, assumes a constant time signature
+    pub fn bar_index(&self) -> Option<i64> { Some((self.bar_start()? / self.bar_length()?).round() as i64) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 120 BPM at 48 kHz, so a quarter note is 24000 samples
+    fn transport(project_time_music: f64, numerator: i32, denominator: i32) -> Transport {
+        Transport {
+            sample_rate: 48000.0,
+            project_time_music: Some(project_time_music),
+            tempo: Some(120.0),
+            time_signature: Some(TimeSignature { numerator, denominator }),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn bars_are_calculated_from_the_time_signature() {
+        let t = transport(9.5, 4, 4);
+        assert_eq!(t.bar_length(), Some(4.0));
+        assert_eq!(t.bar_start(), Some(8.0));
+        assert_eq!(t.position_in_bar(), Some(1.5));
+        assert_eq!(t.bar_index(), Some(2));
+
+        let t = transport(7.0, 6, 8);
+        assert_eq!(t.bar_length(), Some(3.0));
+        assert_eq!(t.bar_start(), Some(6.0));
+        assert_eq!(t.position_in_bar(), Some(1.0));
+        assert_eq!(t.bar_index(), Some(2));
+    }
+
+    #[test]
+    fn bar_position_of_the_host_is_preferred() {
+        let t = Transport {
+            bar_position_music: Some(8.0),
+            ..transport(9.5, 4, 4)
+        };
+
+        assert_eq!(t.bar_start(), Some(8.0));
+        assert_eq!(t.position_in_bar(), Some(1.5));
+
+        let t = Transport {
+            time_signature: None,
+            ..t
+        };
+
+        assert_eq!(t.position_in_bar(), Some(1.5));
+        assert_eq!(t.bar_index(), None);
+    }
+
+    #[test]
+    fn beats_are_converted_to_sample_offsets() {
+        let t = transport(2.0, 4, 4);
+        assert_eq!(t.samples_per_beat(), Some(24000.0));
+        assert_eq!(t.beat_at(0), Some(2.0));
+        assert_eq!(t.beat_at(12000), Some(2.5));
+        assert_eq!(t.sample_offset_of_beat(2.5), Some(12000.0));
+        assert_eq!(t.sample_offset_of_beat(1.0), Some(-24000.0));
+    }
+
+    #[test]
+    fn missing_values_give_none() {
+        let t = Transport::new(None, 44100.0);
+        assert_eq!(t.sample_rate, 44100.0);
+        assert_eq!(t.beat_at(0), None);
+        assert_eq!(t.sample_offset_of_beat(1.0), None);
+        assert_eq!(t.bar_start(), None);
+        assert_eq!(t.position_in_bar(), None);
+        assert_eq!(t.bar_index(), None);
+    }
+
+    #[test]
+    fn only_valid_context_values_are_decoded() {
+        let mut c: ProcessContext = unsafe { std::mem::zeroed() };
+        c.state = PLAYING | TEMPO_VALID;
+        c.tempo = 90.0;
+        c.project_time_music = 3.0;
+        c.time_sig_numerator = 3;
+        c.time_sig_denominator = 4;
+
+        let t = Transport::new(Some(&c), 48000.0);
+        assert!(t.is_playing);
+        assert_eq!(t.sample_rate, 48000.0);
+        assert_eq!(t.tempo, Some(90.0));
+        assert_eq!(t.project_time_music, None);
+        assert_eq!(t.time_signature, None);
+    }
+}
@@ -0,0 +1,432 @@
+use crate::{sysex::SysExMessage, voice::Note};
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use std::fmt::{Display, Formatter};
+use std::io::{Error, ErrorKind, Read, Write};
+use std::path::Path;
+
+/// Frequency of middle C in 12-TET, the default reference of Scala files without a keyboard mapping
+const MIDDLE_C_FREQUENCY: f64 = 261.625_565_300_6;
+
+/// Longer names in a stored tuning are treated as corrupt data
+const MAX_NAME_LEN: u32 = 1024;
+
+#[derive(Debug)]
+pub enum TuningError {
+    Io(std::io::Error),
+
+    /// Line numbers start at 1
+    Parse {
+        line:    usize,
+        message: String,
+    },
+}
+
+impl Display for TuningError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TuningError::Io(e) => write!(f, "Failed to read tuning: {}", e),
+            TuningError::Parse { line, message } => write!(f, "Invalid tuning at line {}: {}", line, message),
+        }
+    }
+}
+
+impl std::error::Error for TuningError {}
+
+impl From<std::io::Error> for TuningError {
+    fn from(e: std::io::Error) -> Self { TuningError::Io(e) }
+}
+
+fn parse_error(line: usize, message: &str) -> TuningError {
+    TuningError::Parse {
+        line,
+        message: message.into(),
+    }
+}
+
+/// The lines of a Scala file that aren't comments, with their line numbers
+fn scala_lines(text: &str) -> impl Iterator<Item = (usize, &str)> {
+    text.lines().enumerate().filter(|(_, l)| !l.starts_with('!')).map(|(i, l)| (i + 1, l.trim()))
+}
+
+/// Parses a pitch of a .scl file to cents, either in cents if it contains a period or as a ratio
+fn parse_scala_pitch(line: usize, text: &str) -> Result<f64, TuningError> {
+    let value = text.split_whitespace().next().ok_or_else(|| parse_error(line, "Missing pitch"))?;
+    let invalid = || parse_error(line, &format!("Invalid pitch '{}'", value));
+
+    if value.contains('.') {
+        return value.parse::<f64>().map_err(|_| invalid());
+    }
+
+    let (n, d) = match value.split_once('/') {
+        Some((n, d)) => (n.parse::<f64>().map_err(|_| invalid())?, d.parse::<f64>().map_err(|_| invalid())?),
+        None => (value.parse::<f64>().map_err(|_| invalid())?, 1.0),
+    };
+
+    if n <= 0.0 || d <= 0.0 {
+        return Err(invalid());
+    }
+
+    Ok(1200.0 * (n / d).log2())
+}
+
+struct Scale {
+    description: String,
+
+    /// Cents of degrees 1 to N, the last one is the period
+    cents: Vec<f64>,
+}
+
+impl Scale {
+    fn parse(text: &str) -> Result<Self, TuningError> {
+        let mut lines = scala_lines(text);
+        let description = lines.next().map(|(_, l)| l.to_string()).unwrap_or_default();
+        let (line, count) = lines.next().ok_or_else(|| parse_error(0, "Missing note count"))?;
+
+        let count = count
+            .split_whitespace()
+            .next()
+            .and_then(|c| c.parse::<usize>().ok())
+            .ok_or_else(|| parse_error(line, "Invalid note count"))?;
+
+        let cents = lines.take(count).map(|(line, l)| parse_scala_pitch(line, l)).collect::<Result<Vec<_>, _>>()?;
+
+        if cents.len() != count || count == 0 {
+            return Err(parse_error(line, "Wrong number of pitches"));
+        }
+
+        Ok(Self { description, cents })
+    }
+
+    fn period(&self) -> f64 { *self.cents.last().unwrap() }
+
+    /// The cents of any scale degree, degrees outside the scale are repeated with the period
+    fn degree_cents(&self, degree: i32) -> f64 {
+        let n = self.cents.len() as i32;
+        let octave = degree.div_euclid(n);
+
+        match degree.rem_euclid(n) {
+            0 => octave as f64 * self.period(),
+            d => octave as f64 * self.period() + self.cents[d as usize - 1],
+        }
+    }
+}
+
+struct KeyboardMapping {
+    first_key:           i32,
+    last_key:            i32,
+    middle_key:          i32,
+    reference_key:       i32,
+    reference_frequency: f64,
+
+    /// Scale degree of the formal octave, 0 if it's the period of the scale
+    octave_degree: i32,
+
+    /// Scale degrees of the keys starting from the middle key, `None` is unmapped. An empty map maps all keys linearly.
+    map: Vec<Option<i32>>,
+}
+
+impl Default for KeyboardMapping {
+    fn default() -> Self {
+        Self {
+            first_key:           0,
+            last_key:            127,
+            middle_key:          60,
+            reference_key:       60,
+            reference_frequency: MIDDLE_C_FREQUENCY,
+            octave_degree:       0,
+            map:                 Vec::new(),
+        }
+    }
+}
+
+impl KeyboardMapping {
+    fn parse(text: &str) -> Result<Self, TuningError> {
+        let mut lines = scala_lines(text).filter(|(_, l)| !l.is_empty());
+
+        let mut number = |name: &str| -> Result<f64, TuningError> {
+            let (line, l) = lines.next().ok_or_else(|| parse_error(0, &format!("Missing {}", name)))?;
+
+            l.split_whitespace()
+                .next()
+                .and_then(|v| v.parse::<f64>().ok())
+                .ok_or_else(|| parse_error(line, &format!("Invalid {}", name)))
+        };
+
+        let map_size = number("map size")? as usize;
+        let first_key = number("first note")? as i32;
+        let last_key = number("last note")? as i32;
+        let middle_key = number("middle note")? as i32;
+        let reference_key = number("reference note")? as i32;
+        let reference_frequency = number("reference frequency")?;
+        let octave_degree = number("octave degree")? as i32;
+
+        let map = lines
+            .take(map_size)
+            .map(|(line, l)| match l.split_whitespace().next() {
+                Some("x") | Some("X") => Ok(None),
+                Some(v) => v.parse::<i32>().map(Some).map_err(|_| parse_error(line, "Invalid scale degree")),
+                None => Err(parse_error(line, "Missing scale degree")),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        if reference_frequency <= 0.0 {
+            return Err(parse_error(0, "Invalid reference frequency"));
+        }
+
+        Ok(Self {
+            first_key,
+            last_key,
+            middle_key,
+            reference_key,
+            reference_frequency,
+            octave_degree,
+            map,
+        })
+    }
+
+    /// The cents of a key relative to the middle key, `None` if it's unmapped
+    fn key_cents(&self, scale: &Scale, key: i32) -> Option<f64> {
+        let i = key - self.middle_key;
+
+        if self.map.is_empty() {
+            return Some(scale.degree_cents(i));
+        }
+
+        let size = self.map.len() as i32;
+        let degree = self.map[i.rem_euclid(size) as usize]?;
+
+        let octave_cents = match self.octave_degree {
+            0 => scale.period(),
+            d => scale.degree_cents(d),
+        };
+
+        Some(i.div_euclid(size) as f64 * octave_cents + scale.degree_cents(degree))
+    }
+}
+
+/// Maps the 128 MIDI keys to fractional note numbers, the default is 12-TET with A4 at 440 Hz
+#[derive(Clone, Debug, PartialEq)]
+pub struct Tuning {
+    name:    String,
+    pitches: Vec<f64>,
+}
+
+impl Default for Tuning {
+    fn default() -> Self {
+        Self {
+            name:    "12-TET".into(),
+            pitches: (0..128).map(|k| k as f64).collect(),
+        }
+    }
+}
+
+impl Tuning {
+    pub fn name(&self) -> &str { &self.name }
+
+    /// Parses the contents of a .scl file and optionally a .kbm file, unmapped keys keep their 12-TET pitch
+    pub fn from_scala(scl: &str, kbm: Option<&str>) -> Result<Self, TuningError> {
+        let scale = Scale::parse(scl)?;
+
+        let mapping = match kbm {
+            Some(kbm) => KeyboardMapping::parse(kbm)?,
+            None => KeyboardMapping::default(),
+        };
+
+        let reference_cents = mapping
+            .key_cents(&scale, mapping.reference_key)
+            .ok_or_else(|| parse_error(0, "The reference note is unmapped"))?;
+
+        let mut tuning = Tuning {
+            name: scale.description.clone(),
+            ..Default::default()
+        };
+
+        for key in mapping.first_key.max(0)..=mapping.last_key.min(127) {
+            if let Some(cents) = mapping.key_cents(&scale, key) {
+                let frequency = mapping.reference_frequency * 2f64.powf((cents - reference_cents) / 1200.0);
+                tuning.pitches[key as usize] = 69.0 + 12.0 * (frequency / 440.0).log2();
+            }
+        }
+
+        Ok(tuning)
+    }
+
+    /// Loads a .scl file and optionally a .kbm file
+    pub fn load_scala(scl: &Path, kbm: Option<&Path>) -> Result<Self, TuningError> {
+        let kbm = kbm.map(std::fs::read_to_string).transpose()?;
+        Self::from_scala(&std::fs::read_to_string(scl)?, kbm.as_deref())
+    }
+
+    /// Applies a MIDI Tuning Standard message, returns false if it's not a tuning message
+    pub fn apply_sysex(&mut self, message: &SysExMessage) -> bool {
+        match message {
+            SysExMessage::BulkTuningDump { name, tunings, .. } => {
+                self.name = name.clone();
+
+                for t in tunings.iter().flatten() {
+                    self.pitches[t.key as usize & 0x7F] = t.pitch();
+                }
+            }
+
+            SysExMessage::NoteTuningChange { changes, .. } => {
+                for t in changes.iter() {
+                    self.pitches[t.key as usize & 0x7F] = t.pitch();
+                }
+            }
+
+            SysExMessage::ScaleOctaveTuning { offsets, .. } => {
+                for (key, pitch) in self.pitches.iter_mut().enumerate() {
+                    *pitch = key as f64 + offsets[key % 12] / 100.0;
+                }
+            }
+
+            _ => return false,
+        }
+
+        true
+    }
+
+    /// The fractional note number of a key, keys outside the MIDI range are 12-TET
+    pub fn pitch(&self, key: i16) -> f64 { self.pitches.get(key as usize).copied().unwrap_or(key as f64) }
+
+    pub fn set_pitch(&mut self, key: u8, pitch: f64) {
+        if let Some(p) = self.pitches.get_mut(key as usize) {
+            *p = pitch;
+        }
+    }
+
+    /// The frequency in Hz of a key detuned by `cents`
+    pub fn frequency(&self, key: i16, cents: f32) -> f64 {
+        440.0 * 2f64.powf((self.pitch(key) + cents as f64 / 100.0 - 69.0) / 12.0)
+    }
+
+    /// The frequency in Hz of a note including the tuning of its note on event
+    pub fn note_frequency(&self, note: &Note) -> f64 { self.frequency(note.pitch, note.tuning) }
+
+    /// Reads a tuning written by `write`, e.g. as part of the plugin state
+    pub fn read(stream: &mut impl Read) -> std::io::Result<Self> {
+        let name_len = stream.read_u32::<LittleEndian>()?;
+
+        if name_len > MAX_NAME_LEN {
+            return Err(Error::new(ErrorKind::InvalidData, "Invalid tuning name length"));
+        }
+
+        let mut name = vec![0; name_len as usize];
+        stream.read_exact(&mut name)?;
+
+        let mut pitches = vec![0.0; 128];
+        stream.read_f64_into::<LittleEndian>(&mut pitches)?;
+
+        Ok(Self {
+            name: String::from_utf8_lossy(&name).into(),
+            pitches,
+        })
+    }
+
+    pub fn write(&self, stream: &mut impl Write) -> std::io::Result<()> {
+        stream.write_u32::<LittleEndian>(self.name.len() as u32)?;
+        stream.write_all(self.name.as_bytes())?;
+
+        for p in self.pitches.iter() {
+            stream.write_f64::<LittleEndian>(*p)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PYTHAGOREAN: &str = "! pythagorean.scl
+!
+Pythagorean
+ 12
+!
+ 256/243
+ 9/8
+ 32/27
+ 81/64
+ 4/3
+ 729/512
+ 3/2
+ 128/81
+ 27/16
+ 16/9
+ 243/128
+ 2/1
+";
+
+    fn assert_close(a: f64, b: f64) {
+        assert!((a - b).abs() < 1e-6, "{} != {}", a, b);
+    }
+
+    #[test]
+    fn default_is_equal_temperament() {
+        let tuning = Tuning::default();
+        assert_close(tuning.frequency(69, 0.0), 440.0);
+        assert_close(tuning.frequency(81, 0.0), 880.0);
+        assert_close(tuning.frequency(69, 100.0), tuning.frequency(70, 0.0));
+    }
+
+    #[test]
+    fn parses_scala_pitches() {
+        assert_close(parse_scala_pitch(1, "3/2").unwrap(), 1200.0 * 1.5f64.log2());
+        assert_close(parse_scala_pitch(1, "100.0 cents").unwrap(), 100.0);
+        assert_close(parse_scala_pitch(1, "2").unwrap(), 1200.0);
+        assert!(parse_scala_pitch(1, "0/1").is_err());
+        assert!(parse_scala_pitch(1, "x").is_err());
+    }
+
+    #[test]
+    fn scale_without_mapping_is_relative_to_middle_c() {
+        let tuning = Tuning::from_scala(PYTHAGOREAN, None).unwrap();
+        assert_eq!(tuning.name(), "Pythagorean");
+        assert_close(tuning.pitch(60), 60.0);
+        assert_close(tuning.pitch(72), 72.0);
+        assert_close(tuning.frequency(67, 0.0), MIDDLE_C_FREQUENCY * 1.5);
+    }
+
+    #[test]
+    fn keyboard_mapping_sets_reference_frequency() {
+        // Reference A4 at 432 Hz, middle note C4
+        let kbm = "0\n0\n127\n60\n69\n432.0\n0\n";
+        let tuning = Tuning::from_scala(PYTHAGOREAN, Some(kbm)).unwrap();
+        assert_close(tuning.frequency(69, 0.0), 432.0);
+        assert_close(tuning.frequency(81, 0.0), 864.0);
+    }
+
+    #[test]
+    fn unmapped_keys_keep_equal_temperament() {
+        // Only C and G are mapped
+        let kbm = "12\n0\n127\n60\n60\n261.6255653006\n12\n0\nx\nx\nx\nx\nx\nx\n7\nx\nx\nx\nx\n";
+        let tuning = Tuning::from_scala(PYTHAGOREAN, Some(kbm)).unwrap();
+        assert_close(tuning.frequency(67, 0.0), MIDDLE_C_FREQUENCY * 1.5);
+        assert_close(tuning.pitch(64), 64.0);
+    }
+
+    #[test]
+    fn reports_parse_errors_with_line() {
+        match Tuning::from_scala("Broken\n 2\n 3/2\n nope\n", None) {
+            Err(TuningError::Parse { line, .. }) => assert_eq!(line, 4),
+            _ => panic!("Expected a parse error"),
+        }
+
+        assert!(Tuning::from_scala("Short\n 3\n 3/2\n", None).is_err());
+    }
+
+    #[test]
+    fn round_trips_through_state() {
+        let tuning = Tuning::from_scala(PYTHAGOREAN, None).unwrap();
+        let mut data = Vec::new();
+        tuning.write(&mut data).unwrap();
+        assert_eq!(Tuning::read(&mut data.as_slice()).unwrap(), tuning);
+    }
+
+    #[test]
+    fn rejects_oversized_names() {
+        let data = u32::MAX.to_le_bytes();
+        assert_eq!(Tuning::read(&mut &data[..]).unwrap_err().kind(), ErrorKind::InvalidData);
+    }
+}
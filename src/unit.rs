@@ -0,0 +1,216 @@
+use std::{
+    collections::HashMap,
+    fmt::{Display, Formatter},
+};
+
+use crate::{
+    edit_controller::ProgramListId,
+    plugin_parameter::{ParameterId, ParameterInfo},
+    type_cell::TypeCell,
+};
+
+#[derive(Clone, Copy, Default, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct UnitIdTag;
+
+pub type UnitId = TypeCell<UnitIdTag, i32>;
+
+pub const ROOT_UNIT_ID: i32 = 0;
+pub const NO_PARENT_UNIT_ID: i32 = -1;
+pub const NO_PROGRAM_LIST_ID: i32 = -1;
+
+#[derive(Clone)]
+pub struct UnitInfo {
+    pub id:              UnitId,
+    pub parent_unit_id:  UnitId,
+    pub name:            String,
+    pub program_list_id: ProgramListId,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum UnitTreeError {
+    DuplicateUnit(UnitId),
+    /// Only the root unit may have no parent
+    InvalidRoot(UnitId),
+    MissingParent {
+        unit:   UnitId,
+        parent: UnitId,
+    },
+    Cycle(UnitId),
+    MissingUnit {
+        parameter: ParameterId,
+        unit:      UnitId,
+    },
+}
+
+impl Display for UnitTreeError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            UnitTreeError::DuplicateUnit(id) => write!(f, "Unit {} is declared more than once", **id),
+            UnitTreeError::InvalidRoot(id) => write!(f, "Unit {} has no parent but isn't the root unit", **id),
+            UnitTreeError::MissingParent { unit, parent } => {
+                write!(f, "Parent unit {} of unit {} doesn't exist", **parent, **unit)
+            }
+            UnitTreeError::Cycle(id) => write!(f, "Unit {} is part of a cycle", **id),
+            UnitTreeError::MissingUnit { parameter, unit } => {
+                write!(f, "Unit {} of parameter {} doesn't exist", **unit, **parameter)
+            }
+        }
+    }
+}
+
+impl std::error::Error for UnitTreeError {}
+
+pub struct UnitTreeBuilder {
+    units: Vec<UnitInfo>,
+}
+
+impl Default for UnitTreeBuilder {
+    fn default() -> Self { Self::new("Root") }
+}
+
+impl UnitTreeBuilder {
+    /// Creates a builder containing only the root unit
+    pub fn new(root_name: &str) -> Self {
+        Self {
+            units: vec![UnitInfo {
+                id:              ROOT_UNIT_ID.into(),
+                parent_unit_id:  NO_PARENT_UNIT_ID.into(),
+                name:            root_name.into(),
+                program_list_id: NO_PROGRAM_LIST_ID.into(),
+            }],
+        }
+    }
+
+    pub fn unit(self, id: UnitId, parent: UnitId, name: &str) -> Self {
+        self.unit_with_program_list(id, parent, name, NO_PROGRAM_LIST_ID.into())
+    }
+
+    pub fn unit_with_program_list(
+        mut self,
+        id: UnitId,
+        parent: UnitId,
+        name: &str,
+        program_list: ProgramListId,
+    ) -> Self {
+        self.units.push(UnitInfo {
+            id,
+            parent_unit_id: parent,
+            name: name.into(),
+            program_list_id: program_list,
+        });
+
+        self
+    }
+
+    /// Validates the unit tree and that the unit of every parameter exists
+    pub fn build(self, params: &[&ParameterInfo]) -> Result<UnitTree, UnitTreeError> {
+        let mut index_by_id = HashMap::with_capacity(self.units.len());
+
+        for (i, u) in self.units.iter().enumerate() {
+            if index_by_id.insert(u.id, i).is_some() {
+                return Err(UnitTreeError::DuplicateUnit(u.id));
+            }
+        }
+
+        // Exactly one unit, the root unit, has no parent
+        for u in self.units.iter() {
+            if (*u.id == ROOT_UNIT_ID) != (*u.parent_unit_id == NO_PARENT_UNIT_ID) {
+                return Err(UnitTreeError::InvalidRoot(u.id));
+            }
+        }
+
+        for u in self.units.iter() {
+            let mut parent = u.parent_unit_id;
+            let mut depth = 0;
+
+            while *parent != NO_PARENT_UNIT_ID {
+                let p = index_by_id.get(&parent).ok_or(UnitTreeError::MissingParent { unit: u.id, parent })?;
+
+                depth += 1;
+
+                if parent == u.id || depth > self.units.len() {
+                    return Err(UnitTreeError::Cycle(u.id));
+                }
+
+                parent = self.units[*p].parent_unit_id;
+            }
+        }
+
+        for p in params {
+            if !index_by_id.contains_key(&p.unit_id) {
+                return Err(UnitTreeError::MissingUnit {
+                    parameter: p.id,
+                    unit:      p.unit_id,
+                });
+            }
+        }
+
+        Ok(UnitTree {
+            units: self.units,
+            index_by_id,
+        })
+    }
+}
+
+pub struct UnitTree {
+    units:       Vec<UnitInfo>,
+    index_by_id: HashMap<UnitId, usize>,
+}
+
+impl UnitTree {
+    pub fn units(&self) -> &[UnitInfo] { &self.units }
+    pub fn get(&self, id: UnitId) -> Option<&UnitInfo> { self.index_by_id.get(&id).map(|i| &self.units[*i]) }
+
+    pub fn children(&self, id: UnitId) -> impl Iterator<Item = &UnitInfo> {
+        self.units.iter().filter(move |u| u.parent_unit_id == id)
+    }
+
+    /// Returns the path from the root unit to the given unit
+    pub fn path(&self, id: UnitId) -> Vec<&UnitInfo> {
+        let mut path = Vec::new();
+        let mut unit = self.get(id);
+
+        while let Some(u) = unit {
+            path.push(u);
+            unit = self.get(u.parent_unit_id);
+        }
+
+        path.reverse();
+        path
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builds_nested_units() {
+        let tree =
+            UnitTreeBuilder::default().unit(1.into(), 0.into(), "Osc").unit(2.into(), 1.into(), "Env").build(&[]);
+        let tree = tree.expect("valid tree");
+        let path = tree.path(2.into()).iter().map(|u| *u.id).collect::<Vec<_>>();
+        assert_eq!(path, vec![0, 1, 2]);
+    }
+
+    #[test]
+    fn rejects_units_without_parent() {
+        let result = UnitTreeBuilder::default().unit(1.into(), NO_PARENT_UNIT_ID.into(), "Second root").build(&[]);
+        assert_eq!(result.err(), Some(UnitTreeError::InvalidRoot(1.into())));
+    }
+
+    #[test]
+    fn rejects_missing_parents_and_cycles() {
+        let result = UnitTreeBuilder::default().unit(1.into(), 5.into(), "Orphan").build(&[]);
+        assert_eq!(
+            result.err(),
+            Some(UnitTreeError::MissingParent {
+                unit:   1.into(),
+                parent: 5.into(),
+            })
+        );
+
+        let result = UnitTreeBuilder::default().unit(1.into(), 2.into(), "A").unit(2.into(), 1.into(), "B").build(&[]);
+        assert_eq!(result.err(), Some(UnitTreeError::Cycle(1.into())));
+    }
+}
@@ -1,11 +1,99 @@
+use log::error;
+use std::any::Any;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::ptr::{null_mut, NonNull};
+use vst3_com::{c_void, ComInterface, VstPtr};
 use vst3_sys::base::{char16, char8};
+use vst3_sys::utils::SharedVstPtr;
 
+pub(crate) unsafe fn shared_vst_ptr<I: ComInterface + ?Sized>(ptr: *mut c_void) -> Option<VstPtr<I>> {
+    NonNull::new(ptr as *mut *mut <I as ComInterface>::VTable).map(|p| VstPtr::shared(p))
+}
+
+/// Takes over the reference already held by `ptr`, e.g. an object returned from `create_instance`
+pub(crate) unsafe fn owned_vst_ptr<I: ComInterface + ?Sized>(ptr: *mut c_void) -> Option<VstPtr<I>> {
+    NonNull::new(ptr as *mut *mut <I as ComInterface>::VTable).map(|p| VstPtr::owned(p))
+}
+
+/// Borrows a pointer for passing to a plugin, no reference is added
+pub(crate) fn to_shared_vst_ptr<I: ComInterface + ?Sized>(ptr: Option<&VstPtr<I>>) -> SharedVstPtr<I> {
+    unsafe { raw_to_shared_vst_ptr(ptr.map(|p| p.as_raw() as *mut c_void).unwrap_or(null_mut())) }
+}
+
+/// Like `to_shared_vst_ptr` for a raw pointer to an `I` interface, e.g. one handed out to the host
+pub(crate) unsafe fn raw_to_shared_vst_ptr<I: ComInterface + ?Sized>(raw: *mut c_void) -> SharedVstPtr<I> {
+    std::mem::transmute_copy(&raw)
+}
+
+/// A slice of host data, empty if the pointer is null or the length isn't positive
+pub(crate) unsafe fn host_slice<'a, T>(ptr: *const T, len: i32) -> &'a [T] {
+    if ptr.is_null() || len <= 0 {
+        &[]
+    }
+    else {
+        std::slice::from_raw_parts(ptr, len as usize)
+    }
+}
+
+pub(crate) unsafe fn host_slice_mut<'a, T>(ptr: *mut T, len: i32) -> &'a mut [T] {
+    if ptr.is_null() || len <= 0 {
+        &mut []
+    }
+    else {
+        std::slice::from_raw_parts_mut(ptr, len as usize)
+    }
+}
+
+fn panic_message(payload: &(dyn Any + Send)) -> &str {
+    if let Some(s) = payload.downcast_ref::<&str>() {
+        s
+    }
+    else if let Some(s) = payload.downcast_ref::<String>() {
+        s
+    }
+    else {
+        "unknown panic"
+    }
+}
+
+/// Runs `f` and catches any panic so it never unwinds into the host, a panic is logged and `on_panic` returned
+pub(crate) fn guard<R>(method: &str, on_panic: R, f: impl FnOnce() -> R) -> R {
+    match catch_unwind(AssertUnwindSafe(f)) {
+        Ok(r) => r,
+        Err(e) => {
+            error!("Panic in {}: {}", method, panic_message(e.as_ref()));
+            on_panic
+        }
+    }
+}
+
+/// Converts a null terminated UTF-16 string, invalid characters are replaced
 pub(crate) fn char16_to_string(src: &[char16]) -> String {
-    let v = src.iter().map(|v| *v as u16).collect::<Vec<_>>();
-    String::from_utf16(&v).expect("Invalid string value!")
+    let v = src.iter().take_while(|c| **c != 0).map(|c| *c as u16).collect::<Vec<_>>();
+    String::from_utf16_lossy(&v)
+}
+
+/// Converts a null terminated UTF-16 string of at most `max_len` characters without reading past the terminator
+pub(crate) unsafe fn char16_ptr_to_string(src: *const char16, max_len: usize) -> String {
+    if src.is_null() {
+        return String::new();
+    }
+
+    let len = (0..max_len).take_while(|i| *src.add(*i) != 0).count();
+    char16_to_string(std::slice::from_raw_parts(src, len))
+}
+
+/// Converts a null terminated fixed width string
+pub(crate) fn fixed_width_to_string(src: &[char8]) -> String {
+    let v = src.iter().take_while(|c| **c != 0).map(|c| *c as u8).collect::<Vec<_>>();
+    String::from_utf8_lossy(&v).into_owned()
 }
 
 pub(crate) fn string_copy_into_i16(src: &str, dst: &mut [i16]) {
+    if dst.is_empty() {
+        return;
+    }
+
     let mut i = 0;
 
     for ch in src.encode_utf16() {
@@ -21,6 +109,10 @@ pub(crate) fn string_copy_into_i16(src: &str, dst: &mut [i16]) {
 }
 
 pub(crate) fn string_copy_into_u16(src: &str, dst: &mut [u16]) {
+    if dst.is_empty() {
+        return;
+    }
+
     let mut i = 0;
 
     for ch in src.encode_utf16() {
@@ -35,10 +127,11 @@ pub(crate) fn string_copy_into_u16(src: &str, dst: &mut [u16]) {
     dst[i] = 0;
 }
 
+/// Truncates the string if needed, the result is always null terminated
 pub(crate) fn string_to_fixed_width<const LEN: usize>(text: &str) -> [char8; LEN] {
     let mut a = [0; LEN];
 
-    for (i, ch) in text.chars().enumerate() {
+    for (i, ch) in text.chars().take(LEN.saturating_sub(1)).enumerate() {
         a[i] = ch as char8
     }
 
@@ -0,0 +1,638 @@
+use crate::{
+    audio_processor::{EventData, ProcessInput, ProcessOutput},
+    midi_mapping::MidiController,
+    sample::Sample,
+};
+use std::cmp::Reverse;
+
+const SUSTAIN_CC: u8 = 64;
+const ALL_NOTES_OFF_CC: u8 = 123;
+
+/// Limits the held notes remembered in mono and legato mode, e.g. if the host drops note offs
+const MAX_HELD_NOTES: usize = 128;
+
+/// A note started by a note on event
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Note {
+    pub channel:  i16,
+    pub pitch:    i16,
+    pub velocity: f32,
+
+    /// Offset in cents
+    pub tuning: f32,
+
+    /// -1 if the host doesn't provide note ids
+    pub note_id: i32,
+}
+
+impl Note {
+    /// The frequency of the note in Hz including the tuning offset, A4 is 440 Hz
+    pub fn frequency(&self) -> f64 { 440.0 * 2f64.powf((self.pitch as f64 - 69.0 + self.tuning as f64 / 100.0) / 12.0) }
+
+    pub(crate) fn matches(&self, channel: i16, pitch: i16, note_id: i32) -> bool {
+        if note_id != -1 && self.note_id != -1 {
+            self.note_id == note_id
+        }
+        else {
+            self.channel == channel && self.pitch == pitch
+        }
+    }
+}
+
+/// A voice driven by a `VoiceManager`
+#[allow(unused_variables)]
+pub trait Voice {
+    /// Starts playing a note, `legato` is true if the voice is already playing and shouldn't be retriggered
+    fn note_on(&mut self, note: &Note, legato: bool);
+
+    /// Starts the release of the voice
+    fn note_off(&mut self, velocity: f32);
+
+    /// Stops the voice immediately, called when it's stolen or the manager is reset
+    fn kill(&mut self);
+
+    /// False when the voice is silent and can be reused
+    fn is_active(&self) -> bool;
+
+    /// Adds the output of the voice to the samples `start..end` of the output
+    fn render<'t, T: Sample>(&mut self, output: &mut ProcessOutput<'t, T>, start: usize, end: usize);
+
+    fn poly_pressure(&mut self, pressure: f32) {}
+    fn note_expression(&mut self, type_id: u32, value: f64) {}
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum VoiceMode {
+    Poly,
+
+    /// A single voice retriggered by every note, releasing a note returns to the previous held note
+    Mono,
+
+    /// Like `Mono` but notes played while another note is held aren't retriggered
+    Legato,
+}
+
+/// Selects the voice to steal when all voices are in use, released voices are always stolen first
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StealMode {
+    Oldest,
+    Lowest,
+    Highest,
+
+    /// Held voices are never stolen, new notes are ignored instead
+    None,
+}
+
+#[derive(Clone, Copy, Debug)]
+enum Action {
+    NoteOn(Note),
+    NoteOff {
+        channel:  i16,
+        pitch:    i16,
+        note_id:  i32,
+        velocity: f32,
+    },
+    PolyPressure {
+        channel:  i16,
+        pitch:    i16,
+        note_id:  i32,
+        pressure: f32,
+    },
+    NoteExpression {
+        note_id: i32,
+        type_id: u32,
+        value:   f64,
+    },
+    Sustain {
+        channel: i16,
+        on:      bool,
+    },
+    AllNotesOff {
+        channel: i16,
+    },
+}
+
+struct Slot<V> {
+    voice:     V,
+    note:      Note,
+    held:      bool,
+    sustained: bool,
+    age:       u64,
+}
+
+impl<V: Voice> Slot<V> {
+    fn is_free(&self) -> bool { !self.held && !self.sustained && !self.voice.is_active() }
+
+    /// Released voices are stolen before sustained ones, which are stolen before held ones
+    fn steal_rank(&self) -> u8 {
+        if self.held {
+            2
+        }
+        else if self.sustained {
+            1
+        }
+        else {
+            0
+        }
+    }
+}
+
+/// Allocates voices for the notes of the input events, all voices are created up front so nothing is allocated
+/// while processing
+pub struct VoiceManager<V> {
+    slots:      Vec<Slot<V>>,
+    polyphony:  usize,
+    mode:       VoiceMode,
+    steal_mode: StealMode,
+    sustain:    [bool; 16],
+
+    /// The held notes in mono and legato mode, the last one is playing
+    note_stack: Vec<Note>,
+    counter:    u64,
+    actions:    Vec<(i32, Action)>,
+}
+
+impl<V: Voice> VoiceManager<V> {
+    pub fn new(polyphony: usize, mut create_voice: impl FnMut() -> V) -> Self {
+        let polyphony = polyphony.max(1);
+
+        Self {
+            slots: (0..polyphony)
+                .map(|_| Slot {
+                    voice:     create_voice(),
+                    note:      Note::default(),
+                    held:      false,
+                    sustained: false,
+                    age:       0,
+                })
+                .collect(),
+            polyphony,
+            mode: VoiceMode::Poly,
+            steal_mode: StealMode::Oldest,
+            sustain: [false; 16],
+            note_stack: Vec::with_capacity(MAX_HELD_NOTES),
+            counter: 0,
+            actions: Vec::with_capacity(256),
+        }
+    }
+
+    pub fn mode(&self) -> VoiceMode { self.mode }
+
+    /// Stops all voices if the mode is changed
+    pub fn set_mode(&mut self, mode: VoiceMode) {
+        if mode != self.mode {
+            self.mode = mode;
+            self.reset();
+        }
+    }
+
+    pub fn steal_mode(&self) -> StealMode { self.steal_mode }
+    pub fn set_steal_mode(&mut self, steal_mode: StealMode) { self.steal_mode = steal_mode; }
+    pub fn polyphony(&self) -> usize { self.polyphony }
+
+    /// Limited to the number of voices created by `new`
+    pub fn set_polyphony(&mut self, polyphony: usize) {
+        self.polyphony = polyphony.clamp(1, self.slots.len());
+
+        for slot in self.slots[self.polyphony..].iter_mut() {
+            Self::stop(slot);
+        }
+    }
+
+    /// Stops all voices immediately, e.g. when the processor is activated
+    pub fn reset(&mut self) {
+        for slot in self.slots.iter_mut() {
+            Self::stop(slot);
+        }
+
+        self.note_stack.clear();
+        self.sustain = [false; 16];
+    }
+
+    pub fn active_voice_count(&self) -> usize { self.slots.iter().filter(|s| s.voice.is_active()).count() }
+
+    /// All voices, e.g. for updating their parameters
+    pub fn voices_mut(&mut self) -> impl Iterator<Item = &mut V> + '_ { self.slots.iter_mut().map(|s| &mut s.voice) }
+
+    /// The voice playing the note with an id assigned by the host
+    pub fn voice_for_note_id(&mut self, note_id: i32) -> Option<&mut V> {
+        if note_id == -1 {
+            return None;
+        }
+
+        self.slots.iter_mut().find(|s| !s.is_free() && s.note.note_id == note_id).map(|s| &mut s.voice)
+    }
+
+    /// Handles the note events and sustain pedal changes of the input and renders the voices, notes are started at
+    /// their sample offsets
+    pub fn process<'t, T: Sample>(&mut self, input: &ProcessInput<'t, T>, output: &mut ProcessOutput<'t, T>) {
+        self.collect_actions(input);
+
+        let len = input.sample_count as usize;
+        let actions = std::mem::take(&mut self.actions);
+        let mut pos = 0;
+
+        for (offset, action) in actions.iter() {
+            let offset = (*offset).max(0) as usize;
+            let offset = offset.clamp(pos, len);
+            self.render(output, pos, offset);
+            pos = offset;
+            self.apply(*action);
+        }
+
+        self.render(output, pos, len);
+        self.actions = actions;
+    }
+
+    fn collect_actions<T>(&mut self, input: &ProcessInput<T>) {
+        self.actions.clear();
+
+        for e in input.events.iter() {
+            let action = match e.event {
+                EventData::NoteOn(n) => Action::NoteOn(Note {
+                    channel:  n.channel,
+                    pitch:    n.pitch,
+                    velocity: n.velocity,
+                    tuning:   n.tuning,
+                    note_id:  n.note_id,
+                }),
+
+                EventData::NoteOff(n) => Action::NoteOff {
+                    channel:  n.channel,
+                    pitch:    n.pitch,
+                    note_id:  n.note_id,
+                    velocity: n.velocity,
+                },
+
+                EventData::PolyPressure(p) => Action::PolyPressure {
+                    channel:  p.channel,
+                    pitch:    p.pitch,
+                    note_id:  p.note_id,
+                    pressure: p.pressure,
+                },
+
+                EventData::NoteExpressionValue(v) => Action::NoteExpression {
+                    note_id: v.note_id,
+                    type_id: v.type_id,
+                    value:   v.value,
+                },
+
+                _ => continue,
+            };
+
+            self.actions.push((e.sample_offset, action));
+        }
+
+        for c in input.controller_events.iter() {
+            let action = match c.controller {
+                MidiController::ControlChange(SUSTAIN_CC) => Action::Sustain {
+                    channel: c.channel,
+                    on:      c.value >= 0.5,
+                },
+
+                MidiController::ControlChange(ALL_NOTES_OFF_CC) => Action::AllNotesOff { channel: c.channel },
+                _ => continue,
+            };
+
+            self.actions.push((c.sample_offset, action));
+        }
+
+        // Stable sort so events at the same offset keep their order
+        self.actions.sort_by_key(|(offset, _)| *offset);
+    }
+
+    fn render<'t, T: Sample>(&mut self, output: &mut ProcessOutput<'t, T>, start: usize, end: usize) {
+        if start >= end {
+            return;
+        }
+
+        for slot in self.slots.iter_mut().filter(|s| s.voice.is_active()) {
+            slot.voice.render(output, start, end);
+        }
+    }
+
+    fn apply(&mut self, action: Action) {
+        match action {
+            Action::NoteOn(note) => self.note_on(note),
+
+            Action::NoteOff {
+                channel,
+                pitch,
+                note_id,
+                velocity,
+            } => self.note_off(channel, pitch, note_id, velocity),
+
+            Action::PolyPressure {
+                channel,
+                pitch,
+                note_id,
+                pressure,
+            } => {
+                for slot in self.slots.iter_mut().filter(|s| s.held && s.note.matches(channel, pitch, note_id)) {
+                    slot.voice.poly_pressure(pressure);
+                }
+            }
+
+            Action::NoteExpression {
+                note_id,
+                type_id,
+                value,
+            } => {
+                if let Some(v) = self.voice_for_note_id(note_id) {
+                    v.note_expression(type_id, value);
+                }
+            }
+
+            Action::Sustain { channel, on } => self.set_sustain(channel, on),
+            Action::AllNotesOff { channel } => self.all_notes_off(channel),
+        }
+    }
+
+    fn note_on(&mut self, note: Note) {
+        self.counter += 1;
+
+        if self.mode != VoiceMode::Poly {
+            let legato = self.mode == VoiceMode::Legato && !self.note_stack.is_empty();
+
+            // A key pressed again without a note off is moved to the top
+            self.note_stack.retain(|n| n.channel != note.channel || n.pitch != note.pitch);
+
+            if self.note_stack.len() >= MAX_HELD_NOTES {
+                self.note_stack.remove(0);
+            }
+
+            self.note_stack.push(note);
+            self.start(0, note, legato);
+            return;
+        }
+
+        if let Some(i) = self.find_slot() {
+            if !self.slots[i].is_free() {
+                Self::stop(&mut self.slots[i]);
+            }
+
+            self.start(i, note, false);
+        }
+    }
+
+    fn note_off(&mut self, channel: i16, pitch: i16, note_id: i32, velocity: f32) {
+        if self.mode != VoiceMode::Poly {
+            let playing = match self.note_stack.last() {
+                Some(n) => n.matches(channel, pitch, note_id),
+                None => false,
+            };
+
+            self.note_stack.retain(|n| !n.matches(channel, pitch, note_id));
+
+            if playing {
+                match self.note_stack.last().copied() {
+                    Some(previous) => self.start(0, previous, self.mode == VoiceMode::Legato),
+                    None => self.release(0, velocity),
+                }
+            }
+
+            return;
+        }
+
+        for i in 0..self.polyphony {
+            if self.slots[i].held && self.slots[i].note.matches(channel, pitch, note_id) {
+                self.release(i, velocity);
+            }
+        }
+    }
+
+    fn find_slot(&self) -> Option<usize> {
+        let slots = &self.slots[..self.polyphony];
+
+        if let Some(i) = slots.iter().position(|s| s.is_free()) {
+            return Some(i);
+        }
+
+        let rank = slots.iter().map(|s| s.steal_rank()).min()?;
+
+        if rank == 2 && self.steal_mode == StealMode::None {
+            return None;
+        }
+
+        let candidates = slots.iter().enumerate().filter(|(_, s)| s.steal_rank() == rank);
+
+        match self.steal_mode {
+            StealMode::Oldest | StealMode::None => candidates.min_by_key(|(_, s)| s.age),
+            StealMode::Lowest => candidates.min_by_key(|(_, s)| (s.note.pitch, s.age)),
+            StealMode::Highest => candidates.max_by_key(|(_, s)| (s.note.pitch, Reverse(s.age))),
+        }
+        .map(|(i, _)| i)
+    }
+
+    fn start(&mut self, index: usize, note: Note, legato: bool) {
+        let slot = &mut self.slots[index];
+        slot.note = note;
+        slot.held = true;
+        slot.sustained = false;
+        slot.age = self.counter;
+        slot.voice.note_on(&note, legato);
+    }
+
+    fn release(&mut self, index: usize, velocity: f32) {
+        let sustained = self.is_sustained(self.slots[index].note.channel);
+        let slot = &mut self.slots[index];
+        slot.held = false;
+
+        if sustained {
+            slot.sustained = true;
+        }
+        else {
+            slot.voice.note_off(velocity);
+        }
+    }
+
+    fn stop(slot: &mut Slot<V>) {
+        slot.held = false;
+        slot.sustained = false;
+        slot.voice.kill();
+    }
+
+    fn is_sustained(&self, channel: i16) -> bool { self.sustain.get(channel as usize).copied().unwrap_or(false) }
+
+    fn set_sustain(&mut self, channel: i16, on: bool) {
+        if let Some(s) = self.sustain.get_mut(channel as usize) {
+            *s = on;
+        }
+
+        if !on {
+            for slot in self.slots.iter_mut().filter(|s| s.sustained && s.note.channel == channel) {
+                slot.sustained = false;
+                slot.voice.note_off(0.0);
+            }
+        }
+    }
+
+    fn all_notes_off(&mut self, channel: i16) {
+        self.note_stack.retain(|n| n.channel != channel);
+
+        for slot in self.slots.iter_mut().filter(|s| (s.held || s.sustained) && s.note.channel == channel) {
+            slot.held = false;
+            slot.sustained = false;
+            slot.voice.note_off(0.0);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audio_processor::Event;
+    use crate::midi_mapping::MidiControllerEvent;
+    use vst3_sys::vst::{NoteOffEvent, NoteOnEvent};
+
+    const BLOCK_SIZE: u32 = 256;
+
+    #[derive(Default)]
+    struct TestVoice {
+        note:     Option<Note>,
+        legato:   bool,
+        rendered: usize,
+    }
+
+    impl Voice for TestVoice {
+        fn note_on(&mut self, note: &Note, legato: bool) {
+            self.note = Some(*note);
+            self.legato = legato;
+        }
+
+        // Voices stop immediately when released
+        fn note_off(&mut self, _velocity: f32) { self.note = None; }
+        fn kill(&mut self) { self.note = None; }
+        fn is_active(&self) -> bool { self.note.is_some() }
+
+        fn render<'t, T: Sample>(&mut self, _output: &mut ProcessOutput<'t, T>, start: usize, end: usize) {
+            self.rendered += end - start;
+        }
+    }
+
+    fn note_on(sample_offset: i32, pitch: i16) -> Event<'static> {
+        Event::new(
+            sample_offset,
+            EventData::NoteOn(NoteOnEvent {
+                channel: 0,
+                pitch,
+                tuning: 0.0,
+                velocity: 1.0,
+                length: 0,
+                note_id: -1,
+            }),
+        )
+    }
+
+    fn note_off(sample_offset: i32, pitch: i16) -> Event<'static> {
+        Event::new(
+            sample_offset,
+            EventData::NoteOff(NoteOffEvent {
+                channel: 0,
+                pitch,
+                velocity: 0.0,
+                note_id: -1,
+                tuning: 0.0,
+            }),
+        )
+    }
+
+    fn sustain(on: bool) -> MidiControllerEvent {
+        MidiControllerEvent {
+            bus_index:     0,
+            channel:       0,
+            sample_offset: 0,
+            controller:    MidiController::ControlChange(SUSTAIN_CC),
+            value:         if on { 1.0 } else { 0.0 },
+        }
+    }
+
+    fn process(
+        vm: &mut VoiceManager<TestVoice>,
+        events: Vec<Event<'static>>,
+        controller_events: Vec<MidiControllerEvent>,
+    ) {
+        let input = ProcessInput::<f32>::for_events(BLOCK_SIZE, events, controller_events);
+
+        vm.process(&input, &mut ProcessOutput::new(Vec::new()));
+    }
+
+    fn playing(vm: &mut VoiceManager<TestVoice>) -> Vec<i16> {
+        let mut pitches: Vec<i16> = vm.voices_mut().filter_map(|v| v.note.map(|n| n.pitch)).collect();
+        pitches.sort_unstable();
+        pitches
+    }
+
+    #[test]
+    fn poly_notes_start_and_stop() {
+        let mut vm = VoiceManager::new(4, TestVoice::default);
+        process(&mut vm, vec![note_on(0, 60), note_on(0, 64)], Vec::new());
+        assert_eq!(playing(&mut vm), vec![60, 64]);
+
+        process(&mut vm, vec![note_off(0, 60)], Vec::new());
+        assert_eq!(playing(&mut vm), vec![64]);
+        assert_eq!(vm.active_voice_count(), 1);
+    }
+
+    #[test]
+    fn notes_start_at_their_sample_offset() {
+        let mut vm = VoiceManager::new(1, TestVoice::default);
+        process(&mut vm, vec![note_on(100, 60)], Vec::new());
+        assert_eq!(vm.voices_mut().next().unwrap().rendered, BLOCK_SIZE as usize - 100);
+    }
+
+    #[test]
+    fn steals_oldest_voice() {
+        let mut vm = VoiceManager::new(2, TestVoice::default);
+        process(&mut vm, vec![note_on(0, 60), note_on(1, 62), note_on(2, 64)], Vec::new());
+        assert_eq!(playing(&mut vm), vec![62, 64]);
+    }
+
+    #[test]
+    fn steal_mode_none_ignores_new_notes() {
+        let mut vm = VoiceManager::new(1, TestVoice::default);
+        vm.set_steal_mode(StealMode::None);
+        process(&mut vm, vec![note_on(0, 60), note_on(1, 62)], Vec::new());
+        assert_eq!(playing(&mut vm), vec![60]);
+    }
+
+    #[test]
+    fn sustain_pedal_holds_released_notes() {
+        let mut vm = VoiceManager::new(2, TestVoice::default);
+        process(&mut vm, vec![note_on(0, 60)], vec![sustain(true)]);
+        process(&mut vm, vec![note_off(0, 60)], Vec::new());
+        assert_eq!(playing(&mut vm), vec![60]);
+
+        process(&mut vm, Vec::new(), vec![sustain(false)]);
+        assert!(playing(&mut vm).is_empty());
+    }
+
+    #[test]
+    fn legato_returns_to_previous_note() {
+        let mut vm = VoiceManager::new(1, TestVoice::default);
+        vm.set_mode(VoiceMode::Legato);
+        process(&mut vm, vec![note_on(0, 60), note_on(1, 64)], Vec::new());
+        assert_eq!(playing(&mut vm), vec![64]);
+        assert!(vm.voices_mut().next().unwrap().legato);
+
+        process(&mut vm, vec![note_off(0, 64)], Vec::new());
+        assert_eq!(playing(&mut vm), vec![60]);
+
+        process(&mut vm, vec![note_off(0, 60)], Vec::new());
+        assert!(playing(&mut vm).is_empty());
+    }
+
+    #[test]
+    fn repeated_mono_notes_are_held_once() {
+        let mut vm = VoiceManager::new(1, TestVoice::default);
+        vm.set_mode(VoiceMode::Mono);
+        process(&mut vm, (0..200).map(|i| note_on(i, 60)).collect(), Vec::new());
+        assert_eq!(vm.note_stack.len(), 1);
+
+        process(&mut vm, vec![note_off(0, 60)], Vec::new());
+        assert!(playing(&mut vm).is_empty());
+
+        process(&mut vm, (0..200).map(|i| note_on(i, i as i16 % 128)).collect(), Vec::new());
+        assert_eq!(vm.note_stack.len(), MAX_HELD_NOTES);
+    }
+}
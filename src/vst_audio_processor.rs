@@ -1,20 +1,25 @@
-use crate::audio_processor::{
-    AudioProcessor, Event, EventData, InBus, InChannel, OutBus, OutChannel, ProcessInput, ProcessOutput,
-};
+use crate::audio_processor::{AudioProcessor, InBus, InChannel, OutBus, OutChannel, ProcessInput, ProcessOutput};
+use crate::bus::{BusDeclaration, BusRole, EventBusDeclaration};
+use crate::bypass::Bypass;
+use crate::edit_controller;
+use crate::midi_mapping::MidiMapping;
 use crate::plugin_parameter::{ParameterId, ParameterPoint};
-use crate::utils::string_copy_into_i16;
+use crate::sample::Sample;
+use crate::sysex::SysEx;
+use crate::transport::Transport;
+use crate::utils::{guard, host_slice, host_slice_mut, string_copy_into_i16};
+use crate::vst_event::{empty_vst_event, to_owned_vst_event, to_plugin_event};
 use crate::vst_stream::{VstInStream, VstOutStream};
-use core::slice;
 use log::info;
 use std::cell::{Cell, RefCell};
 use std::collections::HashMap;
-use std::ptr::null_mut;
+use std::ptr::{null_mut, write_bytes};
 use uuid::Uuid;
 use vst3_com::{c_void, IID};
-use vst3_sys::base::{kInvalidArgument, kNotImplemented, kResultTrue, IBStream, TBool};
+use vst3_sys::base::{kInternalError, kInvalidArgument, kNotImplemented, kResultTrue, IBStream, TBool};
 use vst3_sys::utils::SharedVstPtr;
 use vst3_sys::vst::{
-    BusDirection, BusInfo, BusType, IEventList, IParamValueQueue, IParameterChanges, IoMode, MediaType, ProcessModes,
+    BusDirection, BusInfo, IEventList, IParamValueQueue, IParameterChanges, IoMode, MediaType, ProcessModes,
     ProcessSetup, RoutingInfo, SpeakerArrangement, SymbolicSampleSizes,
 };
 use vst3_sys::VST3;
@@ -23,47 +28,49 @@ use vst3_sys::{
     vst::{IAudioProcessor, IComponent, K_SAMPLE32, K_SAMPLE64},
 };
 
-unsafe fn to_plugin_event(e: &vst3_sys::vst::Event) -> Option<Event> {
-    let t = match e.type_ {
-        0 => Some(EventData::NoteOn(e.event.note_on)),
-        1 => Some(EventData::NoteOff(e.event.note_off)),
-        2 => Some(EventData::Data(e.event.data)),
-        3 => Some(EventData::PolyPressure(e.event.poly_pressure)),
-        4 => Some(EventData::NoteExpressionValue(e.event.note_expression_value)),
-        5 => Some(EventData::NoteExpressionText(e.event.note_expression_text)),
-        6 => Some(EventData::Chord(e.event.chord)),
-        7 => Some(EventData::Scale(e.event.scale)),
-        65535 => Some(EventData::LegacyMidiCcOut(e.event.legacy_midi_cc_out)),
-        _ => None,
-    };
-
-    t.map(|event| Event {
-        bus_index: e.bus_index,
-        sample_offset: e.sample_offset,
-        ppq_position: e.ppq_position,
-        flags: e.flags,
-        event,
-    })
+pub struct AudioBus {
+    decl:   BusDeclaration,
+    active: bool,
 }
 
-pub struct AudioBus {
-    name:        String,
-    bus_type:    BusType,
-    flags:       i32,
-    active:      bool,
-    speaker_arr: SpeakerArrangement,
+pub struct EventBus {
+    decl:   EventBusDeclaration,
+    active: bool,
+}
+
+impl AudioBus {
+    /// Returns the name, role and active state of a bus, buses not declared by the processor are treated as main buses
+    fn describe(buses: &[AudioBus], index: usize) -> (&str, BusRole, bool) {
+        buses.get(index).map_or(("", BusRole::Main, true), |b| (b.decl.name.as_str(), b.decl.role, b.active))
+    }
+}
+
+/// The values of `Vst::ProcessModes` in the SDK, realtime is 0 and offline is 2
+fn to_process_mode(mode: i32) -> Option<ProcessModes> {
+    match mode {
+        0 => Some(ProcessModes::kRealtime),
+        1 => Some(ProcessModes::kPrefetch),
+        2 => Some(ProcessModes::kOffline),
+        _ => None,
+    }
 }
 
-fn get_channel_count(arr: SpeakerArrangement) -> i32 {
-    let mut arr = arr;
-    let mut count = 0;
-    while arr != 0 {
-        if (arr & 1) == 1 {
-            count += 1;
+/// Clears all output buffers and flags them as silent
+unsafe fn silence_outputs(data: &vst3_sys::vst::ProcessData) {
+    let sample_size = if data.symbolic_sample_size == K_SAMPLE64 { 8 } else { 4 };
+
+    for bus in host_slice_mut(data.outputs, data.num_outputs) {
+        for ch in host_slice(bus.buffers, bus.num_channels) {
+            if !ch.is_null() {
+                write_bytes(*ch as *mut u8, 0, data.num_samples.max(0) as usize * sample_size);
+            }
         }
-        arr >>= 1;
+
+        bus.silence_flags = match bus.num_channels.clamp(0, 64) {
+            64 => u64::MAX,
+            n => (1 << n) - 1,
+        };
     }
-    count
 }
 
 #[VST3(implements(IComponent, IAudioProcessor, IPluginBase))]
@@ -74,13 +81,24 @@ pub struct VstAudioProcessor {
     process_setup:        Cell<ProcessSetup>,
     audio_inputs:         RefCell<Vec<AudioBus>>,
     audio_outputs:        RefCell<Vec<AudioBus>>,
+    event_inputs:         RefCell<Vec<EventBus>>,
+    event_outputs:        RefCell<Vec<EventBus>>,
     gain:                 Cell<f64>,
-    bypass:               Cell<bool>,
-    context:              Cell<*mut c_void>,
+    bypass_parameter:     Option<ParameterId>,
+    bypass:               RefCell<Option<Bypass>>,
+
+    /// SysEx data of the output events, kept until the host has read them
+    output_sysex:        RefCell<Vec<SysEx<'static>>>,
+    context:             Cell<*mut c_void>,
+    initialized:         Cell<bool>,
+    silence_after_panic: bool,
+    panicked:            Cell<bool>,
 }
 
 impl VstAudioProcessor {
-    pub fn new(controller_cid: Uuid, processor: Box<dyn AudioProcessor>) -> Box<Self> {
+    pub fn new(controller_cid: Uuid, processor: Box<dyn AudioProcessor>, bypass: Option<ParameterId>) -> Box<Self> {
+        let silence_after_panic = processor.silence_after_panic();
+
         Self::allocate(
             controller_cid,
             processor,
@@ -88,51 +106,172 @@ impl VstAudioProcessor {
             Cell::default(),
             RefCell::default(),
             RefCell::default(),
+            RefCell::default(),
+            RefCell::default(),
             Cell::default(),
-            Cell::default(),
+            bypass,
+            RefCell::default(),
+            RefCell::default(),
             Cell::new(null_mut()),
+            Cell::new(false),
+            silence_after_panic,
+            Cell::new(false),
         )
     }
 
+    /// Clears everything set up since `initialize`, a terminated processor can be initialized again
+    fn reset(&self) {
+        self.audio_inputs.borrow_mut().clear();
+        self.audio_outputs.borrow_mut().clear();
+        self.event_inputs.borrow_mut().clear();
+        self.event_outputs.borrow_mut().clear();
+        self.current_process_mode.set(0);
+        self.process_setup.set(Default::default());
+        *self.bypass.borrow_mut() = None;
+        self.context.set(null_mut());
+        self.initialized.set(false);
+        self.panicked.set(false);
+    }
+
     pub unsafe fn setup_processing_ae(&self, new_setup: *const ProcessSetup) -> tresult {
         if self.can_process_sample_size((*new_setup).symbolic_sample_size) != kResultTrue {
             return kResultFalse;
         }
 
         self.process_setup.set(*new_setup);
+
+        let mut bypass = self.bypass.borrow_mut();
+        let bypassed = bypass.as_ref().map_or(false, |b| b.is_bypassed());
+
+        *bypass = self.bypass_parameter.map(|id| {
+            let input_channels =
+                self.audio_inputs.borrow().iter().map(|b| b.decl.channel_count().max(0) as usize).collect::<Vec<_>>();
+
+            let mut b = Bypass::new(
+                id,
+                self.processor.get_latency_samples(),
+                (*new_setup).sample_rate,
+                &input_channels,
+                (*new_setup).max_samples_per_block.max(0) as usize,
+            );
+
+            b.reset(bypassed);
+            b
+        });
+
         kResultOk
     }
 
-    pub fn add_audio_input(&self, name: &str, arr: SpeakerArrangement) {
-        let new_bus = AudioBus {
-            name:        name.into(),
-            bus_type:    0,
-            flags:       1,
-            active:      false,
-            speaker_arr: arr,
+    /// Runs the processor, applies bypass and reports the output silence flags
+    unsafe fn process_block<'t, T: Sample>(
+        &self,
+        data: &vst3_sys::vst::ProcessData,
+        input: &ProcessInput<'t, T>,
+        output: &mut ProcessOutput<'t, T>,
+        process: impl FnOnce(&ProcessInput<'t, T>, &mut ProcessOutput<'t, T>),
+    ) {
+        let mut bypass = self.bypass.borrow_mut();
+
+        if let Some(b) = bypass.as_mut() {
+            b.begin(input);
+        }
+
+        // The processor is called even when bypassed so events are still handled and notes don't hang
+        process(input, output);
+
+        if let Some(b) = bypass.as_mut() {
+            b.end(output);
+        }
+
+        // Raw controller values are echoed to the edit controller, e.g. for MIDI learn
+        if let Some(mapping) = self.processor.get_midi_mapping() {
+            for (id, points) in input.param_changes.iter() {
+                if mapping.is_raw_controller_parameter(*id) {
+                    output.param_changes.entry(*id).or_insert_with(|| points.clone());
+                }
+            }
+        }
+
+        self.finish_output(data, output);
+        self.write_output_events(data, output);
+        self.write_output_param_changes(data, output);
+    }
+
+    unsafe fn write_output_param_changes<T>(&self, data: &vst3_sys::vst::ProcessData, output: &ProcessOutput<T>) {
+        let opc = match data.output_param_changes.upgrade() {
+            Some(opc) => opc,
+            None => return,
         };
 
-        self.audio_inputs.borrow_mut().push(new_bus);
+        for (id, points) in output.param_changes.iter() {
+            let mut index = 0;
+
+            if let Some(queue) = opc.add_parameter_data(&**id, &mut index).upgrade() {
+                for p in points.iter() {
+                    queue.add_point(p.sample_offset, *p.value, &mut index);
+                }
+            }
+        }
     }
 
-    pub fn add_audio_output(&self, name: &str, arr: SpeakerArrangement) {
-        let new_bus = AudioBus {
-            name:        name.into(),
-            bus_type:    0,
-            flags:       1,
-            active:      false,
-            speaker_arr: arr,
+    /// Sends the output events to the host, SysEx data is copied since the host reads it after `process` returns
+    unsafe fn write_output_events<T>(&self, data: &vst3_sys::vst::ProcessData, output: &ProcessOutput<T>) {
+        let mut sysex = self.output_sysex.borrow_mut();
+        sysex.clear();
+
+        let oe = match data.output_events.upgrade() {
+            Some(oe) => oe,
+            None => return,
         };
 
-        self.audio_outputs.borrow_mut().push(new_bus);
+        for e in output.events.iter() {
+            let mut ve = to_owned_vst_event(e, &mut sysex);
+            oe.add_event(&mut ve);
+        }
+    }
+
+    /// Reports the output silence flags set by the plugin to the host
+    unsafe fn finish_output<T: Sample>(&self, data: &vst3_sys::vst::ProcessData, output: &mut ProcessOutput<T>) {
+        if self.processor.detect_output_silence() {
+            output.detect_silence(T::ZERO);
+        }
+
+        let buses = host_slice_mut(data.outputs, data.num_outputs);
+
+        for (bus, flags) in buses.iter_mut().zip(output.silence_flags()) {
+            bus.silence_flags = flags;
+        }
+    }
+
+    pub fn add_audio_input(&self, decl: BusDeclaration) {
+        self.audio_inputs.borrow_mut().push(AudioBus { decl, active: false });
+    }
+
+    pub fn add_audio_output(&self, decl: BusDeclaration) {
+        self.audio_outputs.borrow_mut().push(AudioBus { decl, active: false });
+    }
+
+    pub fn add_event_input(&self, decl: EventBusDeclaration) {
+        self.event_inputs.borrow_mut().push(EventBus { decl, active: false });
+    }
+
+    pub fn add_event_output(&self, decl: EventBusDeclaration) {
+        self.event_outputs.borrow_mut().push(EventBus { decl, active: false });
     }
 }
 
 impl IComponent for VstAudioProcessor {
     unsafe fn get_controller_class_id(&self, tuid: *mut IID) -> tresult {
         info!("IComponent::get_controller_class_id");
-        (*tuid).data = *self.controller_cid.as_bytes();
-        kResultOk
+
+        guard("IComponent::get_controller_class_id", kInternalError, || {
+            if tuid.is_null() {
+                return kInvalidArgument;
+            }
+
+            (*tuid).data = *self.controller_cid.as_bytes();
+            kResultOk
+        })
     }
 
     unsafe fn set_io_mode(&self, _mode: IoMode) -> tresult {
@@ -143,39 +282,69 @@ impl IComponent for VstAudioProcessor {
     unsafe fn get_bus_count(&self, type_: MediaType, dir: BusDirection) -> i32 {
         info!("IComponent::get_bus_count");
 
-        match type_ {
+        guard("IComponent::get_bus_count", 0, || match type_ {
             0 => match dir {
                 0 => self.audio_inputs.borrow().len() as i32,
                 _ => self.audio_outputs.borrow().len() as i32,
             },
 
+            1 => match dir {
+                0 => self.event_inputs.borrow().len() as i32,
+                _ => self.event_outputs.borrow().len() as i32,
+            },
+
             _ => 0,
-        }
+        })
     }
 
     unsafe fn get_bus_info(&self, type_: MediaType, dir: BusDirection, index: i32, info: *mut BusInfo) -> tresult {
         info!("IComponent::get_bus_info");
-        (*info).media_type = type_;
-        (*info).direction = dir;
 
-        match type_ {
-            0 => {
-                let buses = if dir == 0 { &self.audio_inputs } else { &self.audio_outputs };
+        guard("IComponent::get_bus_info", kInternalError, || {
+            if info.is_null() || index < 0 {
+                return kInvalidArgument;
+            }
 
-                if let Some(bus) = buses.borrow().get(index as usize) {
-                    string_copy_into_i16(&bus.name, &mut (*info).name);
-                    (*info).channel_count = get_channel_count(bus.speaker_arr);
-                    (*info).bus_type = bus.bus_type;
-                    (*info).flags = bus.flags as u32;
-                    kResultTrue
+            match type_ {
+                0 => {
+                    let buses = if dir == 0 { &self.audio_inputs } else { &self.audio_outputs };
+
+                    if let Some(bus) = buses.borrow().get(index as usize) {
+                        let info = &mut *info;
+                        info.media_type = type_;
+                        info.direction = dir;
+                        string_copy_into_i16(&bus.decl.name, &mut info.name);
+                        info.channel_count = bus.decl.channel_count();
+                        info.bus_type = bus.decl.role.to_vst();
+                        info.flags = if bus.decl.default_active { 1 } else { 0 };
+                        kResultTrue
+                    }
+                    else {
+                        kInvalidArgument
+                    }
                 }
-                else {
-                    kInvalidArgument
+
+                1 => {
+                    let buses = if dir == 0 { &self.event_inputs } else { &self.event_outputs };
+
+                    if let Some(bus) = buses.borrow().get(index as usize) {
+                        let info = &mut *info;
+                        info.media_type = type_;
+                        info.direction = dir;
+                        string_copy_into_i16(&bus.decl.name, &mut info.name);
+                        info.channel_count = bus.decl.channel_count;
+                        info.bus_type = bus.decl.role.to_vst();
+                        info.flags = 1;
+                        kResultTrue
+                    }
+                    else {
+                        kInvalidArgument
+                    }
                 }
-            }
 
-            _ => kResultFalse,
-        }
+                _ => kInvalidArgument,
+            }
+        })
     }
 
     unsafe fn get_routing_info(&self, _in_info: *mut RoutingInfo, _out_info: *mut RoutingInfo) -> tresult {
@@ -186,66 +355,97 @@ impl IComponent for VstAudioProcessor {
     unsafe fn activate_bus(&self, type_: MediaType, dir: BusDirection, index: i32, state: TBool) -> tresult {
         info!("IComponent::activate_bus");
 
-        match type_ {
+        guard("IComponent::activate_bus", kInternalError, || match type_ {
             0 => {
                 let buses = if dir == 0 { &self.audio_inputs } else { &self.audio_outputs };
 
                 if let Some(bus) = buses.borrow_mut().get_mut(index as usize) {
                     bus.active = state != 0;
-                    kResultTrue
                 }
                 else {
-                    kInvalidArgument
+                    return kInvalidArgument;
+                }
+
+                let dir =
+                    if dir == 0 { edit_controller::BusDirection::Input } else { edit_controller::BusDirection::Output };
+                self.processor.bus_activated(dir, index, state != 0);
+                kResultTrue
+            }
+
+            1 => {
+                let buses = if dir == 0 { &self.event_inputs } else { &self.event_outputs };
+
+                match buses.borrow_mut().get_mut(index as usize) {
+                    Some(bus) => {
+                        bus.active = state != 0;
+                        kResultTrue
+                    }
+
+                    None => kInvalidArgument,
                 }
             }
 
             _ => kInvalidArgument,
-        }
+        })
     }
 
-    unsafe fn set_active(&self, _state: TBool) -> tresult {
+    unsafe fn set_active(&self, state: TBool) -> tresult {
         info!("IComponent::set_active");
-        kResultOk
+
+        guard("IComponent::set_active", kInternalError, || {
+            if state != 0 {
+                if let Some(b) = self.bypass.borrow_mut().as_mut() {
+                    let bypassed = b.is_bypassed();
+                    b.reset(bypassed);
+                }
+            }
+
+            kResultOk
+        })
     }
 
     unsafe fn set_state(&self, state: SharedVstPtr<dyn IBStream>) -> tresult {
         info!("IComponent::set_state");
 
-        if state.is_null() {
-            return kResultFalse;
-        }
+        guard("IComponent::set_state", kInternalError, || {
+            if state.is_null() {
+                return kResultFalse;
+            }
 
-        if let Some(state) = state.upgrade() {
-            if self.processor.set_state(&mut VstInStream::new(&state)).is_ok() {
-                kResultOk
+            if let Some(state) = state.upgrade() {
+                if self.processor.set_state(&mut VstInStream::new(&state)).is_ok() {
+                    kResultOk
+                }
+                else {
+                    kResultFalse
+                }
             }
             else {
                 kResultFalse
             }
-        }
-        else {
-            kResultFalse
-        }
+        })
     }
 
     unsafe fn get_state(&self, state: SharedVstPtr<dyn IBStream>) -> tresult {
         info!("IComponent::get_state");
 
-        if state.is_null() {
-            return kResultFalse;
-        }
+        guard("IComponent::get_state", kInternalError, || {
+            if state.is_null() {
+                return kResultFalse;
+            }
 
-        if let Some(state) = state.upgrade() {
-            if self.processor.get_state(&mut VstOutStream::new(&state)).is_ok() {
-                kResultOk
+            if let Some(state) = state.upgrade() {
+                if self.processor.get_state(&mut VstOutStream::new(&state)).is_ok() {
+                    kResultOk
+                }
+                else {
+                    kResultFalse
+                }
             }
             else {
                 kResultFalse
             }
-        }
-        else {
-            kResultFalse
-        }
+        })
     }
 }
 
@@ -253,22 +453,55 @@ impl IPluginBase for VstAudioProcessor {
     unsafe fn initialize(&self, context: *mut c_void) -> tresult {
         info!("IPluginBase::initialize audio");
 
-        if !self.context.get().is_null() {
-            return kResultFalse;
-        }
+        guard("IPluginBase::initialize", kInternalError, || {
+            if self.initialized.get() {
+                return kResultFalse;
+            }
 
-        self.context.set(context);
-        self.add_audio_input("Stereo In", 3);
-        self.add_audio_output("Stereo Out", 3);
-        kResultOk
+            if !self.processor.initialize() {
+                return kInternalError;
+            }
+
+            self.initialized.set(true);
+            self.context.set(context);
+
+            for decl in self.processor.get_audio_inputs() {
+                self.add_audio_input(decl);
+            }
+
+            for decl in self.processor.get_audio_outputs() {
+                self.add_audio_output(decl);
+            }
+
+            for decl in self.processor.get_event_inputs() {
+                self.add_event_input(decl);
+            }
+
+            for decl in self.processor.get_event_outputs() {
+                self.add_event_output(decl);
+            }
+
+            kResultOk
+        })
     }
 
     unsafe fn terminate(&self) -> tresult {
         info!("IPluginBase::terminate audio");
-        self.audio_inputs.borrow_mut().clear();
-        self.audio_outputs.borrow_mut().clear();
-        self.context.set(null_mut());
-        kResultOk
+
+        guard("IPluginBase::terminate", kInternalError, || {
+            if !self.initialized.get() {
+                return kResultFalse;
+            }
+
+            self.reset();
+
+            if self.processor.terminate() {
+                kResultOk
+            }
+            else {
+                kInternalError
+            }
+        })
     }
 }
 
@@ -286,33 +519,44 @@ impl IAudioProcessor for VstAudioProcessor {
 
     unsafe fn get_bus_arrangement(&self, dir: BusDirection, index: i32, arr: *mut SpeakerArrangement) -> tresult {
         info!("IAudioProcessor::get_bus_arrangement");
-        let buses = if dir == 0 { &self.audio_inputs } else { &self.audio_outputs }.borrow();
 
-        if let Some(bus) = buses.get(index as usize) {
-            *arr = bus.speaker_arr;
-            kResultTrue
-        }
-        else {
-            kResultFalse
-        }
+        guard("IAudioProcessor::get_bus_arrangement", kInternalError, || {
+            let buses = if dir == 0 { &self.audio_inputs } else { &self.audio_outputs }.borrow();
+
+            if let Some(bus) = buses.get(index as usize) {
+                *arr = bus.decl.speaker_arrangement;
+                kResultTrue
+            }
+            else {
+                kResultFalse
+            }
+        })
     }
 
     unsafe fn can_process_sample_size(&self, symbolic_sample_size: i32) -> tresult {
-        match symbolic_sample_size {
-            K_SAMPLE32 | K_SAMPLE64 => kResultTrue,
+        guard("IAudioProcessor::can_process_sample_size", kInternalError, || match symbolic_sample_size {
+            K_SAMPLE32 => kResultTrue,
+            K_SAMPLE64 if self.processor.can_process_f64() => kResultTrue,
             _ => kResultFalse,
-        }
+        })
     }
 
     unsafe fn get_latency_samples(&self) -> u32 {
         info!("IAudioProcessor::get_latency_samples");
-        0
+        guard("IAudioProcessor::get_latency_samples", 0, || self.processor.get_latency_samples())
     }
 
     unsafe fn setup_processing(&self, setup: *const ProcessSetup) -> tresult {
         info!("IAudioProcessor::setup_processing");
-        self.current_process_mode.set((*setup).process_mode);
-        self.setup_processing_ae(setup)
+
+        guard("IAudioProcessor::setup_processing", kInternalError, || {
+            if setup.is_null() {
+                return kInvalidArgument;
+            }
+
+            self.current_process_mode.set((*setup).process_mode);
+            self.setup_processing_ae(setup)
+        })
     }
 
     unsafe fn set_processing(&self, _state: TBool) -> tresult {
@@ -324,17 +568,13 @@ impl IAudioProcessor for VstAudioProcessor {
     unsafe fn process(&self, data: *mut vst3_sys::vst::ProcessData) -> tresult {
         unsafe fn create_data<'t, T>(
             data: &vst3_sys::vst::ProcessData,
+            midi_mapping: Option<&MidiMapping>,
+            sample_rate: f64,
+            inputs: &'t [AudioBus],
+            outputs: &'t [AudioBus],
         ) -> Option<(ProcessInput<'t, T>, ProcessOutput<'t, T>)> {
-            if data.num_inputs == 0 && data.num_outputs == 0 {
-                return None;
-            }
-
-            let process_mode = match data.process_mode {
-                0 => ProcessModes::kOffline,
-                1 => ProcessModes::kPrefetch,
-                2 => ProcessModes::kRealtime,
-                _ => return None,
-            };
+            // Blocks without audio buses are still processed, e.g. for the events and parameter changes of MIDI effects
+            let process_mode = to_process_mode(data.process_mode)?;
 
             let sample_size = match data.symbolic_sample_size {
                 0 => SymbolicSampleSizes::kSample32,
@@ -388,31 +628,15 @@ impl IAudioProcessor for VstAudioProcessor {
 
                 // Convert input events
                 for i in 0..ec {
-                    let mut e = vst3_sys::vst::Event {
-                        bus_index:     0,
-                        sample_offset: 0,
-                        ppq_position:  0.0,
-                        flags:         0,
-                        type_:         0,
-                        event:         vst3_sys::vst::EventData {
-                            note_on: vst3_sys::vst::NoteOnEvent {
-                                channel:  0,
-                                pitch:    0,
-                                tuning:   0f32,
-                                velocity: 0f32,
-                                length:   0,
-                                note_id:  0,
-                            },
-                        },
-                    };
+                    let mut e = empty_vst_event();
 
+                    // Events that can't be read or are of unknown types are skipped
                     if ie.get_event(i, &mut e as *mut _) != kResultOk {
-                        return None;
+                        continue;
                     }
 
-                    match to_plugin_event(&e) {
-                        Some(event) => events.push(event),
-                        _ => return None,
+                    if let Some(event) = to_plugin_event(&e) {
+                        events.push(event);
                     }
                 }
             }
@@ -420,33 +644,42 @@ impl IAudioProcessor for VstAudioProcessor {
             let mut input_buses: Vec<InBus<'t, T>> = Vec::new();
             let mut output_buses: Vec<OutBus<'t, T>> = Vec::new();
 
-            for bus in slice::from_raw_parts(data.inputs, data.num_inputs as usize) {
-                let channels = (0..bus.num_channels)
-                    .map(|ci| {
-                        let b = bus.buffers.offset(ci as isize);
-
-                        InChannel::<'t, T> {
-                            is_silenced: (bus.silence_flags >> ci) == 1,
-                            samples:     slice::from_raw_parts(b as *const T, data.num_samples as usize),
-                        }
+            // Buses without audio, e.g. of instruments or MIDI effects, may be passed as null pointers
+            for (bi, bus) in host_slice(data.inputs, data.num_inputs).iter().enumerate() {
+                let channels = host_slice(bus.buffers, bus.num_channels)
+                    .iter()
+                    .enumerate()
+                    .map(|(ci, b)| InChannel::<'t, T> {
+                        is_silenced: ci < 64 && bus.silence_flags & (1 << ci) != 0,
+                        samples:     host_slice(*b as *const T, data.num_samples),
                     })
                     .collect::<Vec<InChannel<'t, T>>>();
 
-                input_buses.push(InBus { channels });
+                let (name, role, active) = AudioBus::describe(inputs, bi);
+
+                input_buses.push(InBus {
+                    name,
+                    role,
+                    active,
+                    channels,
+                });
             }
 
-            for bus in slice::from_raw_parts(data.outputs, data.num_outputs as usize) {
-                let channels = slice::from_raw_parts_mut(bus.buffers as *mut *mut T, bus.num_channels as usize)
+            for (bi, bus) in host_slice(data.outputs, data.num_outputs).iter().enumerate() {
+                let channels = host_slice(bus.buffers as *const *mut T, bus.num_channels)
                     .iter()
                     .map(|ch| OutChannel {
                         is_silenced: false,
-                        samples:     slice::from_raw_parts_mut(*ch, data.num_samples as usize),
+                        samples:     host_slice_mut(*ch, data.num_samples),
                     })
                     .collect();
 
-                output_buses.push(OutBus::new(channels));
+                let (name, role, active) = AudioBus::describe(outputs, bi);
+                output_buses.push(OutBus::with_info(name, role, active, channels));
             }
 
+            let controller_events = midi_mapping.map(|m| m.to_controller_events(&param_changes)).unwrap_or_default();
+
             let input = ProcessInput {
                 process_mode,
                 sample_size,
@@ -454,28 +687,70 @@ impl IAudioProcessor for VstAudioProcessor {
                 buses: input_buses,
                 param_changes,
                 events,
-                context: &*data.context,
+                controller_events,
+                context: data.context.as_ref(),
+                transport: Transport::new(data.context.as_ref(), sample_rate),
             };
 
             let output = ProcessOutput::new(output_buses);
             Some((input, output))
         }
 
+        if data.is_null() {
+            return kInvalidArgument;
+        }
+
         let data = &*data;
 
-        if data.symbolic_sample_size == K_SAMPLE32 {
-            if let Some((i, mut o)) = create_data(data) {
-                self.processor.process_f32(&i, &mut o);
-                return kResultOk;
-            }
-        }
-        else if let Some((i, mut o)) = create_data(data) {
-            self.processor.process_f64(&i, &mut o);
+        if self.panicked.get() {
+            silence_outputs(data);
             return kResultOk;
         }
 
-        kInvalidArgument
+        let result = guard("IAudioProcessor::process", None, || {
+            let midi_mapping = self.processor.get_midi_mapping();
+            let sample_rate = self.process_setup.get().sample_rate;
+            let inputs = self.audio_inputs.borrow();
+            let outputs = self.audio_outputs.borrow();
+
+            if data.symbolic_sample_size == K_SAMPLE32 {
+                if let Some((i, mut o)) = create_data(data, midi_mapping, sample_rate, &inputs, &outputs) {
+                    self.process_block(data, &i, &mut o, |i, o| self.processor.process_f32(i, o));
+                    return Some(kResultOk);
+                }
+            }
+            else if self.processor.can_process_f64() {
+                if let Some((i, mut o)) = create_data(data, midi_mapping, sample_rate, &inputs, &outputs) {
+                    self.process_block(data, &i, &mut o, |i, o| self.processor.process_f64(i, o));
+                    return Some(kResultOk);
+                }
+            }
+
+            Some(kInvalidArgument)
+        });
+
+        // The output buffers may be half written after a panic
+        result.unwrap_or_else(|| {
+            silence_outputs(data);
+            self.panicked.set(self.silence_after_panic);
+            kInternalError
+        })
     }
 
-    unsafe fn get_tail_samples(&self) -> u32 { self.processor.get_tail_samples() }
+    unsafe fn get_tail_samples(&self) -> u32 {
+        guard("IAudioProcessor::get_tail_samples", 0, || self.processor.get_tail_samples())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn process_modes_match_the_sdk() {
+        assert!(matches!(to_process_mode(0), Some(ProcessModes::kRealtime)));
+        assert!(matches!(to_process_mode(1), Some(ProcessModes::kPrefetch)));
+        assert!(matches!(to_process_mode(2), Some(ProcessModes::kOffline)));
+        assert!(to_process_mode(3).is_none());
+    }
 }
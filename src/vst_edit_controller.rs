@@ -1,25 +1,36 @@
 #![allow(unused_variables)]
 
+use crate::component_handler::ComponentHandler;
 use crate::edit_controller::{BusDirection, EditController, KnobMode, MediaType};
-use crate::plugin_parameter::ParameterInfo;
-use crate::utils::{char16_to_string, string_copy_into_i16, string_copy_into_u16, string_to_fixed_width_i16};
-use crate::vst_stream::{VstInStream, VstOutStream};
+use crate::editor::EditorContext;
+use crate::note_expression::{PhysicalUi, INVALID_TYPE_ID};
+use crate::plugin_parameter::{ParameterId, ParameterInfo};
+use crate::program::ProgramList;
+use crate::utils::{
+    char16_ptr_to_string, guard, string_copy_into_i16, string_copy_into_u16, string_to_fixed_width_i16,
+};
+use crate::vst_plug_view::VstPlugView;
+use crate::vst_stream::{SeekMode, VstInStream, VstOutStream, VstStream};
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
 use core::slice;
 use log::info;
 use std::cell::Cell;
 use std::ffi::CStr;
+use std::io::{ErrorKind, Read, Write};
 use std::ptr::null_mut;
+use std::rc::Rc;
 use vst3_com::c_void;
 use vst3_sys::base::{kInternalError, kInvalidArgument, kResultFalse, kResultOk, kResultTrue};
 use vst3_sys::utils::SharedVstPtr;
 use vst3_sys::vst::{
-    CtrlNumber, IComponentHandler, IEditController, IEditController2, IEditControllerHostEditing, IMidiMapping,
-    IUnitInfo, ParamID, ParameterFlags, ProgramListInfo, TChar, UnitInfo,
+    CtrlNumber, IComponentHandler, IEditController, IEditController2, IEditControllerHostEditing, IKeyswitchController,
+    IMidiMapping, INoteExpressionController, INoteExpressionPhysicalUIMapping, IUnitInfo, KeyswitchInfo,
+    NoteExpressionTypeID, NoteExpressionTypeInfo, NoteExpressionValue, NoteExpressionValueDescription, ParamID,
+    PhysicalUIMapList, ProgramListInfo, RestartFlags, String128, TChar, UnitInfo,
 };
 use vst3_sys::VST3;
 use vst3_sys::{
     base::{tresult, FIDString, IBStream, IPluginBase},
-    utils::VstPtr,
     vst,
 };
 
@@ -31,67 +42,156 @@ fn to_vst_parameter_info(info: &ParameterInfo) -> vst::ParameterInfo {
         units:                    string_to_fixed_width_i16(&info.units),
         step_count:               info.step_count,
         default_normalized_value: *info.default_normalized_value,
-        unit_id:                  info.unit_id,
-
-        flags: if info.flags.can_automate { ParameterFlags::kCanAutomate as i32 } else { 0 } |
-            if info.flags.is_read_only { ParameterFlags::kIsReadOnly as i32 } else { 0 } |
-            if info.flags.is_wrap_around { ParameterFlags::kIsWrapAround as i32 } else { 0 } |
-            if info.flags.is_list { ParameterFlags::kIsList as i32 } else { 0 } |
-            if info.flags.is_program_change { ParameterFlags::kIsProgramChange as i32 } else { 0 } |
-            if info.flags.is_bypass { ParameterFlags::kIsBypass as i32 } else { 0 },
+        unit_id:                  *info.unit_id,
+        flags:                    info.flags.to_bits(),
     }
 }
 
+/// Marks the block of MIDI learn assignments in the controller state
+const MIDI_LEARN_TAG: &[u8; 8] = b"VstMLrn1";
+
 #[VST3(implements(
     IEditController,
     IEditController2,
     IUnitInfo,
     IPluginBase,
     IEditControllerHostEditing,
-    IMidiMapping
+    IMidiMapping,
+    INoteExpressionController,
+    INoteExpressionPhysicalUIMapping,
+    IKeyswitchController
 ))]
 pub struct VstEditController {
-    controller:        Box<dyn EditController>,
-    component_handler: Cell<Option<VstPtr<dyn IComponentHandler>>>,
+    controller:        Rc<dyn EditController>,
+    component_handler: ComponentHandler,
     context:           Cell<*mut c_void>,
+
+    /// Created for the bypass declared with the processor, it follows the parameters of the controller
+    bypass:       Option<ParameterInfo>,
+    bypass_value: Cell<f64>,
 }
 
 impl VstEditController {
-    pub fn new(controller: Box<dyn EditController>) -> Box<Self> {
-        Self::allocate(controller, Default::default(), Cell::new(null_mut()))
+    pub fn new(controller: Box<dyn EditController>, bypass: Option<ParameterId>) -> Box<Self> {
+        Self::allocate(
+            controller.into(),
+            Default::default(),
+            Cell::new(null_mut()),
+            bypass.map(ParameterInfo::new_bypass),
+            Cell::new(0.0),
+        )
     }
-}
 
-impl IPluginBase for VstEditController {
-    unsafe fn initialize(&self, context: *mut c_void) -> tresult {
-        info!("IPluginBase::initialize controller");
+    fn get_bypass(&self, id: ParameterId) -> Option<&ParameterInfo> { self.bypass.as_ref().filter(|p| p.id == id) }
+
+    /// The parameters of the controller and the bypass parameter
+    fn get_parameter_by_id(&self, id: ParameterId) -> Option<&ParameterInfo> {
+        self.controller.get_parameter_by_id(id).or_else(|| self.get_bypass(id))
+    }
+
+    /// Program data loaded by the host replaces the snapshot of the program list. The processor only knows the
+    /// snapshots, so the loaded values are sent to it as parameter edits.
+    fn apply_program(&self, list: &ProgramList, index: i32) -> bool {
+        let loaded = self.controller.get_loaded_programs().and_then(|l| l.get(list.info.id, index));
 
-        if !self.context.get().is_null() {
-            return kResultFalse;
+        match loaded {
+            Some(values) => {
+                ProgramList::apply_values(self.controller.as_ref(), &values);
+
+                for (id, value) in values {
+                    if self.controller.get_parameter_by_id(id).map_or(false, |p| !p.flags.is_program_change) {
+                        self.component_handler.begin_edit(id);
+                        self.component_handler.perform_edit(id, value);
+                        self.component_handler.end_edit(id);
+                    }
+                }
+
+                true
+            }
+
+            None => list.apply(self.controller.as_ref(), index),
         }
+    }
 
-        self.context.set(context);
+    /// The MIDI learn assignments are stored in a tagged block in front of the state of the controller, so
+    /// reading them doesn't depend on how much of the stream the controller's `set_state` consumes
+    fn write_state(&self, stream: &mut VstOutStream) -> std::io::Result<()> {
+        if let Some(learn) = self.controller.get_midi_learn() {
+            let mut block = Vec::new();
+            learn.write(&mut block)?;
 
-        if self.controller.initialize() {
-            kResultOk
+            stream.write_all(MIDI_LEARN_TAG)?;
+            stream.write_u32::<LittleEndian>(block.len() as u32)?;
+            stream.write_all(&block)?;
+        }
+
+        self.controller.get_state(stream)
+    }
+
+    fn read_state(&self, stream: &mut VstInStream) -> std::io::Result<()> {
+        let start = stream.tell()?;
+        let mut tag = [0u8; 8];
+
+        if stream.read_exact(&mut tag).is_ok() && &tag == MIDI_LEARN_TAG {
+            let len = stream.read_u32::<LittleEndian>()?;
+            let mut block = Vec::with_capacity((len as usize).min(64 * 1024));
+            stream.by_ref().take(len as u64).read_to_end(&mut block)?;
+
+            if block.len() != len as usize {
+                return Err(ErrorKind::UnexpectedEof.into());
+            }
+
+            if let Some(learn) = self.controller.get_midi_learn() {
+                learn.read(&mut block.as_slice())?;
+            }
         }
         else {
-            kInternalError
+            // States saved before MIDI learn was enabled have no assignments
+            stream.seek(start, SeekMode::Set)?;
+
+            if let Some(learn) = self.controller.get_midi_learn() {
+                learn.clear();
+            }
         }
+
+        self.controller.set_state(stream)
+    }
+}
+
+impl IPluginBase for VstEditController {
+    unsafe fn initialize(&self, context: *mut c_void) -> tresult {
+        info!("IPluginBase::initialize controller");
+
+        guard("IPluginBase::initialize", kInternalError, || {
+            if !self.context.get().is_null() {
+                return kResultFalse;
+            }
+
+            self.context.set(context);
+
+            if self.controller.initialize() {
+                kResultOk
+            }
+            else {
+                kInternalError
+            }
+        })
     }
 
     unsafe fn terminate(&self) -> tresult {
         info!("IPluginBase::terminate controller");
 
-        self.component_handler.set(None);
-        self.context.set(null_mut());
+        guard("IPluginBase::terminate", kInternalError, || {
+            self.component_handler.set(None);
+            self.context.set(null_mut());
 
-        if self.controller.terminate() {
-            kResultOk
-        }
-        else {
-            kInternalError
-        }
+            if self.controller.terminate() {
+                kResultOk
+            }
+            else {
+                kInternalError
+            }
+        })
     }
 }
 
@@ -99,169 +199,247 @@ impl IEditController for VstEditController {
     unsafe fn set_component_state(&self, state: SharedVstPtr<dyn IBStream>) -> tresult {
         info!("IEditController::set_component_state");
 
-        if state.is_null() {
-            return kResultFalse;
-        }
+        guard("IEditController::set_component_state", kInternalError, || {
+            if state.is_null() {
+                return kResultFalse;
+            }
 
-        if let Some(state) = state.upgrade() {
-            if self.controller.set_component_state(&mut VstInStream::new(&state)).is_ok() {
-                kResultOk
+            if let Some(state) = state.upgrade() {
+                if self.controller.set_component_state(&mut VstInStream::new(&state)).is_ok() {
+                    kResultOk
+                }
+                else {
+                    kResultFalse
+                }
             }
             else {
                 kResultFalse
             }
-        }
-        else {
-            kResultFalse
-        }
+        })
     }
 
     unsafe fn set_state(&self, state: SharedVstPtr<dyn IBStream>) -> tresult {
         info!("IEditController::set_state");
 
-        if state.is_null() {
-            return kResultFalse;
-        }
+        guard("IEditController::set_state", kInternalError, || {
+            if state.is_null() {
+                return kResultFalse;
+            }
 
-        if let Some(state) = state.upgrade() {
-            if self.controller.set_state(&mut VstInStream::new(&state)).is_ok() {
-                kResultOk
+            if let Some(state) = state.upgrade() {
+                if self.read_state(&mut VstInStream::new(&state)).is_ok() {
+                    kResultOk
+                }
+                else {
+                    kResultFalse
+                }
             }
             else {
                 kResultFalse
             }
-        }
-        else {
-            kResultFalse
-        }
+        })
     }
 
     unsafe fn get_state(&self, state: SharedVstPtr<dyn IBStream>) -> tresult {
         info!("IEditController::get_state");
 
-        if state.is_null() {
-            return kResultFalse;
-        }
+        guard("IEditController::get_state", kInternalError, || {
+            if state.is_null() {
+                return kResultFalse;
+            }
 
-        if let Some(state) = state.upgrade() {
-            if self.controller.get_state(&mut VstOutStream::new(&state)).is_ok() {
-                kResultOk
+            if let Some(state) = state.upgrade() {
+                if self.write_state(&mut VstOutStream::new(&state)).is_ok() {
+                    kResultOk
+                }
+                else {
+                    kResultFalse
+                }
             }
             else {
                 kResultFalse
             }
-        }
-        else {
-            kResultFalse
-        }
+        })
     }
 
     unsafe fn get_parameter_count(&self) -> i32 {
         info!("IEditController::get_parameter_count");
-        self.controller.get_parameters().len() as i32
+
+        guard("IEditController::get_parameter_count", 0, || {
+            (self.controller.get_parameters().len() + self.bypass.iter().count()) as i32
+        })
     }
 
     unsafe fn get_parameter_info(&self, param_index: i32, info: *mut vst::ParameterInfo) -> tresult {
         info!("IEditController::get_parameter_info {}", param_index);
 
-        if let Some(p) = self.controller.get_parameters().get(param_index as usize) {
-            *info = to_vst_parameter_info(p);
-            kResultOk
-        }
-        else {
-            kInvalidArgument
-        }
+        guard("IEditController::get_parameter_info", kInternalError, || {
+            let params = self.controller.get_parameters();
+            let param = match usize::try_from(param_index) {
+                Ok(i) if i == params.len() => self.bypass.as_ref(),
+                Ok(i) => params.get(i).copied(),
+                Err(_) => None,
+            };
+
+            if let Some(p) = param {
+                *info = to_vst_parameter_info(p);
+                kResultOk
+            }
+            else {
+                kInvalidArgument
+            }
+        })
     }
 
     unsafe fn get_param_string_by_value(&self, id: u32, value_normalized: f64, string: *mut TChar) -> tresult {
         info!("IEditController::get_param_string_by_value {} {}", id, value_normalized);
 
-        if let Some(p) = self.controller.get_parameter_by_id(id.into()) {
-            let s = self.controller.normalized_parameter_value_to_string(p, value_normalized.into());
-            string_copy_into_i16(&s, slice::from_raw_parts_mut(string, 128));
-            return kResultOk;
-        }
+        guard("IEditController::get_param_string_by_value", kInternalError, || {
+            if let Some(p) = self.get_parameter_by_id(id.into()) {
+                let s = self.controller.normalized_parameter_value_to_string(p, value_normalized.into());
+                string_copy_into_i16(&s, slice::from_raw_parts_mut(string, 128));
+                return kResultOk;
+            }
 
-        kInvalidArgument
+            kInvalidArgument
+        })
     }
 
     unsafe fn get_param_value_by_string(&self, id: u32, string: *const TChar, value_normalized: *mut f64) -> tresult {
         info!("IEditController::get_param_value_by_string {}", id);
 
-        if let Some(p) = self.controller.get_parameter_by_id(id.into()) {
-            if let Some(v) = self
-                .controller
-                .string_to_normalized_parameter_value(p, &char16_to_string(slice::from_raw_parts(string, 128)))
-            {
-                *value_normalized = *v;
-                return kResultOk;
+        guard("IEditController::get_param_value_by_string", kInternalError, || {
+            if let Some(p) = self.get_parameter_by_id(id.into()) {
+                if let Some(v) =
+                    self.controller.string_to_normalized_parameter_value(p, &char16_ptr_to_string(string, 128))
+                {
+                    *value_normalized = *v;
+                    return kResultOk;
+                }
             }
-        }
 
-        kInvalidArgument
+            kInvalidArgument
+        })
     }
 
     unsafe fn normalized_param_to_plain(&self, id: u32, value_normalized: f64) -> f64 {
         info!("IEditController::normalized_param_to_plain {} {}", id, value_normalized);
 
-        if let Some(p) = self.controller.get_parameter_by_id(id.into()) {
-            *p.normalized_to_plain_converter.convert(value_normalized.into())
-        }
-        else {
-            0.0
-        }
+        guard("IEditController::normalized_param_to_plain", 0.0, || {
+            if let Some(p) = self.get_parameter_by_id(id.into()) {
+                *p.normalized_to_plain_converter.convert(value_normalized.into())
+            }
+            else {
+                0.0
+            }
+        })
     }
 
     unsafe fn plain_param_to_normalized(&self, id: u32, plain_value: f64) -> f64 {
         info!("IEditController::plain_param_to_normalized {} {}", id, plain_value);
 
-        if let Some(p) = self.controller.get_parameter_by_id(id.into()) {
-            *p.normalized_to_plain_converter.convert_inverse(plain_value.into())
-        }
-        else {
-            0.0
-        }
+        guard("IEditController::plain_param_to_normalized", 0.0, || {
+            if let Some(p) = self.get_parameter_by_id(id.into()) {
+                *p.normalized_to_plain_converter.convert_inverse(plain_value.into())
+            }
+            else {
+                0.0
+            }
+        })
     }
 
     unsafe fn get_param_normalized(&self, id: u32) -> f64 {
         info!("IEditController::get_param_normalized {}", id);
 
-        if let Some(p) = self.controller.get_parameter_by_id(id.into()) {
-            *self.controller.get_normalized_parameter_value(p)
-        }
-        else {
-            0.0
-        }
+        guard("IEditController::get_param_normalized", 0.0, || {
+            if self.get_bypass(id.into()).is_some() {
+                self.bypass_value.get()
+            }
+            else if let Some(p) = self.controller.get_parameter_by_id(id.into()) {
+                *self.controller.get_normalized_parameter_value(p)
+            }
+            else {
+                0.0
+            }
+        })
     }
 
     unsafe fn set_param_normalized(&self, id: u32, value: f64) -> tresult {
         info!("IEditController::set_param_normalized {} {}", id, value);
 
-        if let Some(p) = self.controller.get_parameter_by_id(id.into()) {
-            self.controller.set_normalized_parameter_value(p, value.into());
-            kResultOk
-        }
-        else {
-            kInvalidArgument
-        }
+        guard("IEditController::set_param_normalized", kInternalError, || {
+            if self.get_bypass(id.into()).is_some() {
+                self.bypass_value.set(value);
+                kResultOk
+            }
+            else if let Some(p) = self.controller.get_parameter_by_id(id.into()) {
+                self.controller.set_normalized_parameter_value(p, value.into());
+
+                // Raw controller values are echoed by the processor, the first one after MIDI learn was started is
+                // assigned to the learning parameter
+                if let (Some(learn), Some(mapping)) =
+                    (self.controller.get_midi_learn(), self.controller.get_midi_mapping())
+                {
+                    if let Some((bus_index, channel, controller)) = mapping.get_source(p.id) {
+                        learn.capture(bus_index, channel, controller);
+                    }
+                }
+
+                if p.flags.is_program_change {
+                    if let Some(list) = self.controller.get_programs().and_then(|pl| pl.get_by_parameter(p.id)) {
+                        if self.apply_program(list, list.index_from_normalized(value.into())) {
+                            self.component_handler.restart_component(RestartFlags::kParamValuesChanged);
+                        }
+                    }
+                }
+
+                kResultOk
+            }
+            else {
+                kInvalidArgument
+            }
+        })
     }
 
     unsafe fn set_component_handler(&self, handler: SharedVstPtr<dyn IComponentHandler>) -> tresult {
         info!("IEditController::set_component_handler");
 
-        if let Some(handler) = handler.upgrade() {
-            self.component_handler.set(Some(handler));
-            kResultOk
-        }
-        else {
-            kResultFalse
-        }
+        guard("IEditController::set_component_handler", kInternalError, || {
+            if let Some(handler) = handler.upgrade() {
+                self.component_handler.set(Some(handler));
+
+                if let Some(learn) = self.controller.get_midi_learn() {
+                    learn.set_component_handler(self.component_handler.clone());
+                }
+
+                kResultOk
+            }
+            else {
+                kResultFalse
+            }
+        })
     }
 
     unsafe fn create_view(&self, name: FIDString) -> *mut c_void {
         info!("IEditController::create_view");
-        //TODO:
-        null_mut()
+
+        guard("IEditController::create_view", null_mut(), || {
+            if name.is_null() || CStr::from_ptr(name).to_bytes() != b"editor" {
+                return null_mut();
+            }
+
+            let context = EditorContext {
+                controller:        self.controller.clone(),
+                component_handler: self.component_handler.clone(),
+            };
+
+            if let Some(editor) = self.controller.create_editor(context) {
+                VstPlugView::create(editor)
+            }
+            else {
+                null_mut()
+            }
+        })
     }
 }
 
@@ -269,117 +447,135 @@ impl IEditController2 for VstEditController {
     unsafe fn set_knob_mode(&self, mode: vst3_sys::vst::KnobMode) -> vst3_sys::base::tresult {
         info!("IEditController2::set_knob_mode {}", mode);
 
-        if self.controller.set_knob_mode(match mode {
-            0 => KnobMode::Circular,
-            1 => KnobMode::RelativeCircular,
-            2 => KnobMode::Linear,
-            _ => return kInvalidArgument,
-        }) {
-            kResultOk
-        }
-        else {
-            kResultFalse
-        }
+        guard("IEditController2::set_knob_mode", kInternalError, || {
+            if self.controller.set_knob_mode(match mode {
+                0 => KnobMode::Circular,
+                1 => KnobMode::RelativeCircular,
+                2 => KnobMode::Linear,
+                _ => return kInvalidArgument,
+            }) {
+                kResultOk
+            }
+            else {
+                kResultFalse
+            }
+        })
     }
 
     unsafe fn open_help(&self, only_check: vst3_sys::base::TBool) -> vst3_sys::base::tresult {
         info!("IEditController2::open_help {}", only_check);
 
-        if only_check == 0 {
-            self.controller.open_help();
-            kResultOk
-        }
-        else if self.controller.is_open_help_supported() {
-            kResultOk
-        }
-        else {
-            kResultFalse
-        }
+        guard("IEditController2::open_help", kInternalError, || {
+            if only_check == 0 {
+                self.controller.open_help();
+                kResultOk
+            }
+            else if self.controller.is_open_help_supported() {
+                kResultOk
+            }
+            else {
+                kResultFalse
+            }
+        })
     }
 
     unsafe fn oepn_about_box(&self, only_check: vst3_sys::base::TBool) -> vst3_sys::base::tresult {
         info!("IEditController2::oepn_about_box {}", only_check);
 
-        if only_check == 0 {
-            self.controller.open_about_box();
-            kResultOk
-        }
-        else if self.controller.is_open_about_box_supported() {
-            kResultOk
-        }
-        else {
-            kResultFalse
-        }
+        guard("IEditController2::oepn_about_box", kInternalError, || {
+            if only_check == 0 {
+                self.controller.open_about_box();
+                kResultOk
+            }
+            else if self.controller.is_open_about_box_supported() {
+                kResultOk
+            }
+            else {
+                kResultFalse
+            }
+        })
     }
 }
 
 impl IUnitInfo for VstEditController {
-    unsafe fn get_unit_count(&self) -> i32 { self.controller.get_units().map(|l| l.len() as i32).unwrap_or(1) }
+    unsafe fn get_unit_count(&self) -> i32 {
+        guard("IUnitInfo::get_unit_count", 0, || self.controller.get_units().map(|l| l.len() as i32).unwrap_or(1))
+    }
 
     unsafe fn get_unit_info(&self, unit_index: i32, info: *mut UnitInfo) -> i32 {
         info!("IUnitInfo::get_unit_info {}", unit_index);
-        let mut i = &mut *info;
-
-        if let Some(units) = self.controller.get_units() {
-            if let Some(u) = units.get(unit_index as usize) {
-                i.id = *u.id;
-                i.parent_unit_id = *u.parent_unit_id;
-                string_copy_into_i16(&u.name, &mut i.name);
-                i.program_list_id = *u.program_list_id;
+
+        guard("IUnitInfo::get_unit_info", kInternalError, || {
+            let mut i = &mut *info;
+
+            if let Some(units) = self.controller.get_units() {
+                if let Some(u) = units.get(unit_index as usize) {
+                    i.id = *u.id;
+                    i.parent_unit_id = *u.parent_unit_id;
+                    string_copy_into_i16(&u.name, &mut i.name);
+                    i.program_list_id = *u.program_list_id;
+                    kResultTrue
+                }
+                else {
+                    kInvalidArgument
+                }
+            }
+            else if unit_index == 0 {
+                i.id = 1;
+                i.parent_unit_id = 0;
+                string_copy_into_i16("Unit1", &mut i.name);
+                i.program_list_id = -1;
                 kResultTrue
             }
             else {
-                kInvalidArgument
+                kResultFalse
             }
-        }
-        else if unit_index == 0 {
-            i.id = 1;
-            i.parent_unit_id = 0;
-            string_copy_into_i16("Unit1", &mut i.name);
-            i.program_list_id = -1;
-            kResultTrue
-        }
-        else {
-            kResultFalse
-        }
+        })
     }
 
     unsafe fn get_program_list_count(&self) -> i32 {
         info!("IUnitInfo::get_program_list_count");
-        self.controller.get_program_lists().map(|p| p.len() as i32).unwrap_or(0)
+
+        guard("IUnitInfo::get_program_list_count", 0, || {
+            self.controller.get_program_lists().map(|p| p.len() as i32).unwrap_or(0)
+        })
     }
 
     unsafe fn get_program_list_info(&self, list_index: i32, info: *mut ProgramListInfo) -> i32 {
         info!("IUnitInfo::get_program_list_info {}", list_index);
 
-        if let Some(pl) = self.controller.get_program_lists() {
-            if let Some(p) = pl.get(list_index as usize) {
-                let mut i = &mut *info;
-                i.id = *p.id;
-                string_copy_into_u16(&p.name, &mut i.name);
-                i.program_count = p.program_count;
-                return kResultOk;
+        guard("IUnitInfo::get_program_list_info", kInternalError, || {
+            if let Some(pl) = self.controller.get_program_lists() {
+                if let Some(p) = pl.get(list_index as usize) {
+                    let mut i = &mut *info;
+                    i.id = *p.id;
+                    string_copy_into_u16(&p.name, &mut i.name);
+                    i.program_count = p.program_count;
+                    return kResultOk;
+                }
             }
-        }
 
-        kResultFalse
+            kResultFalse
+        })
     }
 
     unsafe fn get_program_name(&self, list_id: i32, program_index: i32, name: *mut u16) -> i32 {
         info!("IUnitInfo::get_program_name {} {}", list_id, program_index);
 
-        if let Some(pl) = self.controller.get_program_list_by_id(list_id.into()) {
-            if program_index >= 0 && program_index < pl.program_count {
-                string_copy_into_u16(
-                    self.controller.get_program_name(pl, program_index),
-                    slice::from_raw_parts_mut(name, 128),
-                );
+        guard("IUnitInfo::get_program_name", kInternalError, || {
+            if let Some(pl) = self.controller.get_program_list_by_id(list_id.into()) {
+                if program_index >= 0 && program_index < pl.program_count {
+                    string_copy_into_u16(
+                        self.controller.get_program_name(pl, program_index),
+                        slice::from_raw_parts_mut(name, 128),
+                    );
 
-                return kResultOk;
+                    return kResultOk;
+                }
             }
-        }
 
-        kInvalidArgument
+            kInvalidArgument
+        })
     }
 
     unsafe fn get_program_info(
@@ -391,118 +587,156 @@ impl IUnitInfo for VstEditController {
     ) -> i32 {
         info!("IUnitInfo::get_program_info {} {}", list_id, program_index);
 
-        if let Some(pl) = self.controller.get_program_list_by_id(list_id.into()) {
-            if program_index >= 0 && program_index < pl.program_count {
-                if let Ok(attr) = CStr::from_ptr(attribute_id as *const i8).to_str() {
-                    if let Some(value) = self.controller.get_program_info(pl, program_index, attr) {
-                        string_copy_into_u16(value, slice::from_raw_parts_mut(attribute_value, 128));
-                        return kResultOk;
+        guard("IUnitInfo::get_program_info", kInternalError, || {
+            if let Some(pl) = self.controller.get_program_list_by_id(list_id.into()) {
+                if program_index >= 0 && program_index < pl.program_count {
+                    if let Ok(attr) = CStr::from_ptr(attribute_id as *const i8).to_str() {
+                        if let Some(value) = self.controller.get_program_info(pl, program_index, attr) {
+                            string_copy_into_u16(value, slice::from_raw_parts_mut(attribute_value, 128));
+                            return kResultOk;
+                        }
                     }
                 }
             }
-        }
 
-        kInvalidArgument
+            kInvalidArgument
+        })
     }
 
     unsafe fn has_program_pitch_names(&self, id: i32, index: i32) -> i32 {
         info!("IUnitInfo::has_program_pitch_names {} {}", id, index);
 
-        if let Some(pl) = self.controller.get_program_list_by_id(id.into()) {
-            if self.controller.has_program_pitch_names(pl, index) {
-                return kResultTrue;
+        guard("IUnitInfo::has_program_pitch_names", kInternalError, || {
+            if let Some(pl) = self.controller.get_program_list_by_id(id.into()) {
+                if self.controller.has_program_pitch_names(pl, index) {
+                    return kResultTrue;
+                }
             }
-        }
 
-        kResultFalse
+            kResultFalse
+        })
     }
 
     unsafe fn get_program_pitch_name(&self, id: i32, index: i32, pitch: i16, name: *mut u16) -> i32 {
         info!("IUnitInfo::get_program_pitch_name {} {} {}", id, index, pitch);
 
-        if let Some(pl) = self.controller.get_program_list_by_id(id.into()) {
-            if self.controller.has_program_pitch_names(pl, index) && index >= 0 && index < pl.program_count {
-                string_copy_into_u16(
-                    self.controller.get_program_pitch_name(pl, index, pitch),
-                    slice::from_raw_parts_mut(name, 128),
-                );
+        guard("IUnitInfo::get_program_pitch_name", kInternalError, || {
+            if let Some(pl) = self.controller.get_program_list_by_id(id.into()) {
+                if self.controller.has_program_pitch_names(pl, index) && index >= 0 && index < pl.program_count {
+                    let pitch_name = self.controller.get_program_pitch_name(pl, index, pitch);
 
-                return kResultTrue;
+                    if !pitch_name.is_empty() {
+                        string_copy_into_u16(pitch_name, slice::from_raw_parts_mut(name, 128));
+                        return kResultTrue;
+                    }
+                }
             }
-        }
 
-        kResultFalse
+            kResultFalse
+        })
     }
 
     unsafe fn get_selected_unit(&self) -> i32 {
         info!("IUnitInfo::get_selected_unit");
 
-        if self.controller.get_units().is_some() {
-            *self.controller.get_selected_unit()
-        }
-        else {
-            1
-        }
+        guard("IUnitInfo::get_selected_unit", 0, || {
+            if self.controller.get_units().is_some() {
+                *self.controller.get_selected_unit()
+            }
+            else {
+                1
+            }
+        })
     }
 
     unsafe fn select_unit(&self, id: i32) -> i32 {
         info!("IUnitInfo::select_unit {}", id);
 
-        if self.controller.get_units().is_some() {
-            if self.controller.select_unit(id.into()) {
+        guard("IUnitInfo::select_unit", kInternalError, || {
+            if self.controller.get_units().is_some() {
+                if self.controller.select_unit(id.into()) {
+                    kResultOk
+                }
+                else {
+                    kInvalidArgument
+                }
+            }
+            else if id == 1 {
                 kResultOk
             }
             else {
                 kInvalidArgument
             }
-        }
-        else if id == 1 {
-            kResultOk
-        }
-        else {
-            kInvalidArgument
-        }
+        })
     }
 
     unsafe fn get_unit_by_bus(&self, type_: i32, dir: i32, index: i32, channel: i32, unit_id: *mut i32) -> i32 {
         info!("IUnitInfo::get_unit_by_bus {} {} {} {}", type_, dir, index, channel);
 
-        if self.controller.get_units().is_some() {
-            if let Some(id) = self.controller.get_unit_by_bus(
-                match type_ {
-                    0 => MediaType::Audio,
-                    1 => MediaType::Event,
-                    _ => return kInvalidArgument,
-                },
-                match dir {
-                    0 => BusDirection::Input,
-                    1 => BusDirection::Output,
-                    _ => return kInvalidArgument,
-                },
-                index,
-                channel,
-            ) {
-                *unit_id = *id;
-                kResultOk
+        guard("IUnitInfo::get_unit_by_bus", kInternalError, || {
+            if self.controller.get_units().is_some() {
+                if let Some(id) = self.controller.get_unit_by_bus(
+                    match type_ {
+                        0 => MediaType::Audio,
+                        1 => MediaType::Event,
+                        _ => return kInvalidArgument,
+                    },
+                    match dir {
+                        0 => BusDirection::Input,
+                        1 => BusDirection::Output,
+                        _ => return kInvalidArgument,
+                    },
+                    index,
+                    channel,
+                ) {
+                    *unit_id = *id;
+                    kResultOk
+                }
+                else {
+                    kInvalidArgument
+                }
             }
             else {
-                kInvalidArgument
+                *unit_id = 1;
+                kResultOk
             }
-        }
-        else {
-            *unit_id = 1;
-            kResultOk
-        }
+        })
     }
 
     unsafe fn set_unit_program_data(
         &self,
         list_or_unit: i32,
         program_index: i32,
-        _data: SharedVstPtr<dyn IBStream>,
+        data: SharedVstPtr<dyn IBStream>,
     ) -> i32 {
         info!("IUnitInfo::set_unit_program_data {} {}", list_or_unit, program_index);
-        kResultFalse
+
+        guard("IUnitInfo::set_unit_program_data", kInternalError, || {
+            if let Some(data) = data.upgrade() {
+                if self
+                    .controller
+                    .set_unit_program_data(list_or_unit, program_index, &mut VstInStream::new(&data))
+                    .is_ok()
+                {
+                    let list = self
+                        .controller
+                        .get_programs()
+                        .and_then(|p| p.get_by_list_or_unit(list_or_unit, self.controller.get_units()));
+
+                    // The data of the selected program is applied immediately
+                    if let Some(list) = list {
+                        if list.get_selected_index(self.controller.as_ref()) == program_index {
+                            self.apply_program(list, program_index);
+                        }
+                    }
+
+                    self.component_handler.restart_component(RestartFlags::kParamValuesChanged);
+                    return kResultOk;
+                }
+            }
+
+            kResultFalse
+        })
     }
 }
 
@@ -510,25 +744,29 @@ impl IEditControllerHostEditing for VstEditController {
     unsafe fn begin_edit_from_host(&self, id: ParamID) -> tresult {
         info!("IEditControllerHostEditing::begin_edit_from_host {}", id);
 
-        if let Some(p) = self.controller.get_parameter_by_id(id.into()) {
-            self.controller.begin_edit_from_host(p);
-            kResultOk
-        }
-        else {
-            kInvalidArgument
-        }
+        guard("IEditControllerHostEditing::begin_edit_from_host", kInternalError, || {
+            if let Some(p) = self.controller.get_parameter_by_id(id.into()) {
+                self.controller.begin_edit_from_host(p);
+                kResultOk
+            }
+            else {
+                kInvalidArgument
+            }
+        })
     }
 
     unsafe fn end_edit_from_host(&self, id: ParamID) -> tresult {
         info!("IEditControllerHostEditing::end_edit_from_host {}", id);
 
-        if let Some(p) = self.controller.get_parameter_by_id(id.into()) {
-            self.controller.end_edit_from_host(p);
-            kResultOk
-        }
-        else {
-            kInvalidArgument
-        }
+        guard("IEditControllerHostEditing::end_edit_from_host", kInternalError, || {
+            if let Some(p) = self.controller.get_parameter_by_id(id.into()) {
+                self.controller.end_edit_from_host(p);
+                kResultOk
+            }
+            else {
+                kInvalidArgument
+            }
+        })
     }
 }
 
@@ -542,12 +780,186 @@ impl IMidiMapping for VstEditController {
     ) -> tresult {
         info!("IMidiMapping::get_midi_controller_assignment {} {} {}", bus_index, channel, midi_cc_number);
 
-        if let Some(id) = self.controller.get_midi_controller_assignment(bus_index, channel, midi_cc_number) {
-            *param_id = *id;
-            kResultTrue
-        }
-        else {
-            kResultFalse
-        }
+        guard("IMidiMapping::get_midi_controller_assignment", kInternalError, || {
+            if let Some(id) = self.controller.get_midi_controller_assignment(bus_index, channel, midi_cc_number) {
+                *param_id = *id;
+                kResultTrue
+            }
+            else {
+                kResultFalse
+            }
+        })
+    }
+}
+
+impl INoteExpressionController for VstEditController {
+    unsafe fn get_note_expression_count(&self, bus_index: i32, channel: i16) -> i32 {
+        info!("INoteExpressionController::get_note_expression_count {} {}", bus_index, channel);
+
+        guard("INoteExpressionController::get_note_expression_count", 0, || {
+            self.controller.get_note_expressions(bus_index, channel).len() as i32
+        })
+    }
+
+    unsafe fn get_note_expression_info(
+        &self,
+        bus_index: i32,
+        channel: i16,
+        note_expression_index: i32,
+        info: *mut NoteExpressionTypeInfo,
+    ) -> tresult {
+        info!(
+            "INoteExpressionController::get_note_expression_info {} {} {}",
+            bus_index, channel, note_expression_index
+        );
+
+        guard("INoteExpressionController::get_note_expression_info", kInternalError, || {
+            if info.is_null() || note_expression_index < 0 {
+                return kInvalidArgument;
+            }
+
+            let e = match self.controller.get_note_expressions(bus_index, channel).get(note_expression_index as usize) {
+                Some(e) => e,
+                None => return kInvalidArgument,
+            };
+
+            let info = &mut *info;
+            info.type_id = *e.id;
+            string_copy_into_i16(&e.title, &mut info.title);
+            string_copy_into_i16(&e.short_title, &mut info.short_title);
+            string_copy_into_i16(&e.units, &mut info.units);
+            info.unit_id = *e.unit_id;
+
+            info.value_desc = NoteExpressionValueDescription {
+                default_value: *e.default_normalized_value,
+                minimum:       0.0,
+                maximum:       1.0,
+                step_count:    e.step_count,
+            };
+
+            info.associated_parameter_id = e.associated_parameter.map_or(0, |id| *id);
+            info.flags = e.flag_bits();
+            kResultOk
+        })
+    }
+
+    unsafe fn get_note_expression_string_by_value(
+        &self,
+        bus_index: i32,
+        channel: i16,
+        id: NoteExpressionTypeID,
+        value: NoteExpressionValue,
+        string: *mut String128,
+    ) -> tresult {
+        info!("INoteExpressionController::get_note_expression_string_by_value {} {}", id, value);
+
+        guard("INoteExpressionController::get_note_expression_string_by_value", kInternalError, || {
+            if string.is_null() {
+                return kInvalidArgument;
+            }
+
+            if let Some(e) = self.controller.get_note_expression(bus_index, channel, id.into()) {
+                let s = self.controller.note_expression_value_to_string(e, value.into());
+                string_copy_into_i16(&s, &mut *string);
+                return kResultOk;
+            }
+
+            kInvalidArgument
+        })
+    }
+
+    unsafe fn get_note_expression_value_by_string(
+        &self,
+        bus_index: i32,
+        channel: i16,
+        id: NoteExpressionTypeID,
+        string: *const TChar,
+        value: *mut NoteExpressionValue,
+    ) -> tresult {
+        info!("INoteExpressionController::get_note_expression_value_by_string {}", id);
+
+        guard("INoteExpressionController::get_note_expression_value_by_string", kInternalError, || {
+            if string.is_null() || value.is_null() {
+                return kInvalidArgument;
+            }
+
+            if let Some(e) = self.controller.get_note_expression(bus_index, channel, id.into()) {
+                if let Some(v) = self.controller.string_to_note_expression_value(e, &char16_ptr_to_string(string, 128))
+                {
+                    *value = *v;
+                    return kResultOk;
+                }
+            }
+
+            kInvalidArgument
+        })
+    }
+}
+
+impl INoteExpressionPhysicalUIMapping for VstEditController {
+    unsafe fn get_physical_ui_mapping(&self, bus_index: i32, channel: i16, list: *mut PhysicalUIMapList) -> tresult {
+        info!("INoteExpressionPhysicalUIMapping::get_physical_ui_mapping {} {}", bus_index, channel);
+
+        guard("INoteExpressionPhysicalUIMapping::get_physical_ui_mapping", kInternalError, || {
+            if list.is_null() || (*list).map.is_null() {
+                return kInvalidArgument;
+            }
+
+            let mappings = self.controller.get_physical_ui_mapping(bus_index, channel);
+
+            // The host fills in the physical UI types, the mapped note expression types are filled in here
+            for m in slice::from_raw_parts_mut((*list).map, (*list).count as usize) {
+                let ui = PhysicalUi::from_vst(m.physical_ui_type_id);
+
+                m.note_expression_type_id = mappings
+                    .iter()
+                    .find(|pm| Some(pm.physical_ui) == ui)
+                    .map_or(INVALID_TYPE_ID, |pm| *pm.note_expression);
+            }
+
+            kResultOk
+        })
+    }
+}
+
+impl IKeyswitchController for VstEditController {
+    unsafe fn get_keyswitch_count(&self, bus_index: i32, channel: i16) -> i32 {
+        info!("IKeyswitchController::get_keyswitch_count {} {}", bus_index, channel);
+
+        guard("IKeyswitchController::get_keyswitch_count", 0, || {
+            self.controller.get_keyswitches(bus_index, channel).len() as i32
+        })
+    }
+
+    unsafe fn get_keyswitch_info(
+        &self,
+        bus_index: i32,
+        channel: i16,
+        keyswitch_index: i32,
+        info: *mut KeyswitchInfo,
+    ) -> tresult {
+        info!("IKeyswitchController::get_keyswitch_info {} {} {}", bus_index, channel, keyswitch_index);
+
+        guard("IKeyswitchController::get_keyswitch_info", kInternalError, || {
+            if info.is_null() || keyswitch_index < 0 {
+                return kInvalidArgument;
+            }
+
+            let k = match self.controller.get_keyswitches(bus_index, channel).get(keyswitch_index as usize) {
+                Some(k) => k,
+                None => return kInvalidArgument,
+            };
+
+            let info = &mut *info;
+            info.type_id = k.keyswitch_type.to_vst();
+            string_copy_into_i16(&k.title, &mut info.title);
+            string_copy_into_i16(&k.short_title, &mut info.short_title);
+            info.keyswitch_min = *k.keys.min() as i32;
+            info.keyswitch_max = *k.keys.max() as i32;
+            info.key_remapped = k.remapped_key.map_or(-1, |key| key as i32);
+            info.unit_id = *k.unit_id;
+            info.flags = 0;
+            kResultOk
+        })
     }
 }
@@ -0,0 +1,211 @@
+use crate::audio_processor::{Event, EventData, EventType};
+use crate::sysex::SysEx;
+use core::slice;
+use vst3_sys::vst::{DataEvent, EventData as VstEventData};
+
+/// MIDI SysEx is the only defined type of data events
+const DATA_TYPE_MIDI_SYSEX: u32 = 0;
+
+/// An event without data, e.g. to be filled in by `IEventList::get_event`
+pub(crate) fn empty_vst_event() -> vst3_sys::vst::Event {
+    // All fields are plain numbers or pointers so all zeros is a valid event
+    unsafe { std::mem::zeroed() }
+}
+
+/// Returns `None` for event types unknown to this crate, flags are kept as is. SysEx data is borrowed from the event,
+/// it must stay valid for `'t`.
+pub(crate) unsafe fn to_plugin_event<'t>(e: &vst3_sys::vst::Event) -> Option<Event<'t>> {
+    let event = match EventType::from_vst(e.type_)? {
+        EventType::NoteOn => EventData::NoteOn(e.event.note_on),
+        EventType::NoteOff => EventData::NoteOff(e.event.note_off),
+        EventType::Data => {
+            let d = e.event.data;
+
+            if d.type_ != DATA_TYPE_MIDI_SYSEX || d.bytes.is_null() {
+                return None;
+            }
+
+            EventData::SysEx(SysEx::borrowed(slice::from_raw_parts(d.bytes, d.size as usize)))
+        }
+
+        EventType::PolyPressure => EventData::PolyPressure(e.event.poly_pressure),
+        EventType::NoteExpressionValue => EventData::NoteExpressionValue(e.event.note_expression_value),
+        EventType::NoteExpressionText => EventData::NoteExpressionText(e.event.note_expression_text),
+        EventType::Chord => EventData::Chord(e.event.chord),
+        EventType::Scale => EventData::Scale(e.event.scale),
+        EventType::LegacyMidiCcOut => EventData::LegacyMidiCcOut(e.event.legacy_midi_cc_out),
+    };
+
+    Some(Event {
+        bus_index: e.bus_index,
+        sample_offset: e.sample_offset,
+        ppq_position: e.ppq_position,
+        flags: e.flags,
+        event,
+    })
+}
+
+/// The SysEx data of the returned event points to the data of `e`
+pub(crate) fn to_vst_event(e: &Event) -> vst3_sys::vst::Event {
+    let event = match &e.event {
+        EventData::NoteOn(note_on) => VstEventData { note_on: *note_on },
+        EventData::NoteOff(note_off) => VstEventData { note_off: *note_off },
+        EventData::SysEx(sysex) => VstEventData {
+            data: DataEvent {
+                size:  sysex.bytes().len() as u32,
+                type_: DATA_TYPE_MIDI_SYSEX,
+                bytes: sysex.bytes().as_ptr(),
+            },
+        },
+        EventData::PolyPressure(poly_pressure) => VstEventData {
+            poly_pressure: *poly_pressure,
+        },
+        EventData::NoteExpressionValue(note_expression_value) => VstEventData {
+            note_expression_value: *note_expression_value,
+        },
+        EventData::NoteExpressionText(note_expression_text) => VstEventData {
+            note_expression_text: *note_expression_text,
+        },
+        EventData::Chord(chord) => VstEventData { chord: *chord },
+        EventData::Scale(scale) => VstEventData { scale: *scale },
+        EventData::LegacyMidiCcOut(legacy_midi_cc_out) => VstEventData {
+            legacy_midi_cc_out: *legacy_midi_cc_out,
+        },
+    };
+
+    vst3_sys::vst::Event {
+        bus_index: e.bus_index,
+        sample_offset: e.sample_offset,
+        ppq_position: e.ppq_position,
+        flags: e.flags,
+        type_: e.event_type().to_vst(),
+        event,
+    }
+}
+
+/// Like `to_vst_event` but SysEx data is copied to `sysex`, which must outlive the returned event
+pub(crate) fn to_owned_vst_event(e: &Event, sysex: &mut Vec<SysEx<'static>>) -> vst3_sys::vst::Event {
+    let mut ve = to_vst_event(e);
+
+    if let EventData::SysEx(s) = &e.event {
+        let owned = s.clone().into_owned();
+        ve.event.data.bytes = owned.bytes().as_ptr();
+        sysex.push(owned);
+    }
+
+    ve
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audio_processor::{EVENT_IS_LIVE, EVENT_USER_RESERVED_1, EVENT_USER_RESERVED_2};
+    use std::mem::zeroed;
+
+    const SYSEX: [u8; 6] = [0xF0, 0x7E, 0x7F, 0x06, 0x01, 0xF7];
+
+    fn events() -> Vec<EventData<'static>> {
+        unsafe {
+            let mut note_on: vst3_sys::vst::NoteOnEvent = zeroed();
+            note_on.channel = 3;
+            note_on.pitch = 64;
+            note_on.velocity = 0.5;
+            note_on.note_id = 7;
+
+            let mut note_off: vst3_sys::vst::NoteOffEvent = zeroed();
+            note_off.channel = 3;
+            note_off.pitch = 64;
+            note_off.note_id = 7;
+
+            let mut poly_pressure: vst3_sys::vst::PolyPressureEvent = zeroed();
+            poly_pressure.pitch = 64;
+            poly_pressure.pressure = 0.25;
+
+            let mut expression: vst3_sys::vst::NoteExpressionValueEvent = zeroed();
+            expression.type_id = 2;
+            expression.note_id = 7;
+            expression.value = 0.75;
+
+            vec![
+                EventData::NoteOn(note_on),
+                EventData::NoteOff(note_off),
+                EventData::SysEx(SysEx::new(SYSEX.to_vec())),
+                EventData::PolyPressure(poly_pressure),
+                EventData::NoteExpressionValue(expression),
+                EventData::NoteExpressionText(zeroed()),
+                EventData::Chord(zeroed()),
+                EventData::Scale(zeroed()),
+                EventData::LegacyMidiCcOut(zeroed()),
+            ]
+        }
+    }
+
+    #[test]
+    fn round_trip_all_event_types() {
+        let flags = EVENT_IS_LIVE | EVENT_USER_RESERVED_1 | EVENT_USER_RESERVED_2;
+        let mut types = Vec::new();
+
+        for data in events() {
+            let event = Event::new(12, data).with_bus(1).with_flags(flags);
+            let ve = to_vst_event(&event);
+            assert_eq!(EventType::from_vst(ve.type_), Some(event.event_type()));
+
+            let back = unsafe { to_plugin_event(&ve) }.expect("known event type");
+            assert_eq!(back.event_type(), event.event_type());
+            assert_eq!(back.bus_index, 1);
+            assert_eq!(back.sample_offset, 12);
+            assert_eq!(back.flags, flags);
+            assert!(back.is_live());
+
+            match back.event {
+                EventData::NoteOn(n) => assert_eq!((n.channel, n.pitch, n.velocity, n.note_id), (3, 64, 0.5, 7)),
+                EventData::NoteOff(n) => assert_eq!((n.channel, n.pitch, n.note_id), (3, 64, 7)),
+                EventData::SysEx(s) => assert_eq!(s.bytes(), &SYSEX),
+                EventData::PolyPressure(p) => assert_eq!((p.pitch, p.pressure), (64, 0.25)),
+                EventData::NoteExpressionValue(v) => assert_eq!((v.type_id, v.note_id, v.value), (2, 7, 0.75)),
+                _ => {}
+            }
+
+            types.push(back.event_type());
+        }
+
+        for t in types.iter() {
+            assert_eq!(EventType::from_vst(t.to_vst()), Some(*t));
+        }
+
+        assert_eq!(types.len(), 9);
+    }
+
+    #[test]
+    fn unknown_event_types_are_skipped() {
+        let mut ve = empty_vst_event();
+        ve.type_ = 42;
+        assert!(unsafe { to_plugin_event(&ve) }.is_none());
+
+        // Data events other than SysEx
+        ve.type_ = EventType::Data.to_vst();
+        ve.event.data = DataEvent {
+            size:  SYSEX.len() as u32,
+            type_: 1,
+            bytes: SYSEX.as_ptr(),
+        };
+
+        assert!(unsafe { to_plugin_event(&ve) }.is_none());
+    }
+
+    #[test]
+    fn owned_sysex_outlives_source() {
+        let mut store = Vec::new();
+
+        let ve = {
+            let bytes = SYSEX.to_vec();
+            let event = Event::new(0, EventData::SysEx(SysEx::borrowed(&bytes)));
+            to_owned_vst_event(&event, &mut store)
+        };
+
+        match unsafe { to_plugin_event(&ve) }.map(|e| e.event) {
+            Some(EventData::SysEx(s)) => assert_eq!(s.bytes(), &SYSEX),
+            _ => panic!("expected a SysEx event"),
+        }
+    }
+}
@@ -2,7 +2,8 @@
 
 use crate::audio_processor::AudioProcessor;
 use crate::edit_controller::EditController;
-use crate::utils::{char8_to_16, string_to_fixed_width};
+use crate::plugin_parameter::ParameterId;
+use crate::utils::{char8_to_16, guard, owned_vst_ptr, string_to_fixed_width};
 use crate::vst_audio_processor::VstAudioProcessor;
 use crate::vst_edit_controller::VstEditController;
 use log::info;
@@ -10,11 +11,12 @@ use std::cell::Cell;
 use std::intrinsics::copy_nonoverlapping;
 use std::ptr::null_mut;
 use uuid::Uuid;
+use vst3_com::interfaces::IUnknown;
 use vst3_com::sys::GUID;
 use vst3_com::{c_void, IID};
 use vst3_sys::base::{
-    kInvalidArgument, kResultFalse, kResultOk, tresult, ClassCardinality, FactoryFlags, IPluginFactory,
-    IPluginFactory2, IPluginFactory3, PClassInfo, PClassInfo2, PClassInfoW, PFactoryInfo,
+    kInternalError, kInvalidArgument, kNoInterface, kResultFalse, kResultOk, tresult, ClassCardinality, FactoryFlags,
+    IPluginFactory, IPluginFactory2, IPluginFactory3, PClassInfo, PClassInfo2, PClassInfoW, PFactoryInfo,
 };
 use vst3_sys::VST3;
 
@@ -45,6 +47,9 @@ pub enum AudioProcessorType {
 
 const VST_VERSION_STRING: &str = "VST 3.6.13";
 
+pub const AUDIO_MODULE_CLASS: &str = "Audio Module Class";
+pub const COMPONENT_CONTROLLER_CLASS: &str = "Component Controller Class";
+
 #[derive(Clone)]
 pub struct FactoryInfo {
     pub vendor: String,
@@ -59,6 +64,10 @@ pub struct AudioProcessorInfo {
     pub typ:                   AudioProcessorType,
     pub is_distributable:      bool,
     pub simple_mode_supported: bool,
+
+    /// If set, a bypass parameter with this id is added to the parameters of the controller registered with the
+    /// processor and the processor routes its inputs to its outputs while it's on, see `ParameterInfo::new_bypass`
+    pub bypass: Option<ParameterId>,
 }
 
 pub fn new_pclass_info2(
@@ -114,10 +123,11 @@ impl VstPluginFactory {
         processor_factory: impl Fn() -> Box<dyn AudioProcessor> + 'static,
         controller_factory: impl Fn() -> Box<dyn EditController> + 'static,
     ) {
-        self.add_edit_controller(
+        self.add_edit_controller_with_bypass(
             controller_cid,
             &(info.name.clone() + " Controller"),
             &info.version,
+            info.bypass,
             controller_factory,
         );
 
@@ -132,16 +142,17 @@ impl VstPluginFactory {
         factory: impl Fn() -> Box<dyn AudioProcessor> + 'static,
     ) {
         let fb = Box::new(factory);
+        let bypass = info.bypass;
 
         let f = move || {
             info!("Creating audio processor");
-            let vap = VstAudioProcessor::new(controller_cid, fb());
+            let vap = VstAudioProcessor::new(controller_cid, fb(), bypass);
             Box::into_raw(vap) as *mut c_void
         };
 
         self.add_class_factory(
             cid,
-            "Audio Module Class",
+            AUDIO_MODULE_CLASS,
             &info.name,
             if info.is_distributable { AudioProcessorFlag::Distributable as u32 } else { 0 } |
                 if info.simple_mode_supported { AudioProcessorFlag::SimpleModeSupported as u32 } else { 0 },
@@ -159,16 +170,29 @@ impl VstPluginFactory {
         name: &str,
         version: &str,
         factory: impl Fn() -> Box<dyn EditController> + 'static,
+    ) {
+        self.add_edit_controller_with_bypass(cid, name, version, None, factory)
+    }
+
+    /// The controller exposes the bypass parameter of a processor registered separately with the same id in its
+    /// `AudioProcessorInfo`
+    pub fn add_edit_controller_with_bypass(
+        &mut self,
+        cid: Uuid,
+        name: &str,
+        version: &str,
+        bypass: Option<ParameterId>,
+        factory: impl Fn() -> Box<dyn EditController> + 'static,
     ) {
         let fb = Box::new(factory);
 
         let f = move || {
             info!("Creating edit controller");
-            let vec = VstEditController::new(fb());
+            let vec = VstEditController::new(fb(), bypass);
             Box::into_raw(vec) as *mut c_void
         };
 
-        self.add_class_factory(cid, "Component Controller Class", name, 0, "", version, f)
+        self.add_class_factory(cid, COMPONENT_CONTROLLER_CLASS, name, 0, "", version, f)
     }
 
     pub fn add_class_factory(
@@ -197,13 +221,15 @@ impl VstPluginFactory {
 
 impl IPluginFactory3 for VstPluginFactory {
     unsafe fn get_class_info_unicode(&self, index: i32, info: *mut PClassInfoW) -> tresult {
-        if let Some((ci, _)) = self.classes.get(index as usize) {
-            *info = class_info2_to_w(ci);
-            kResultOk
-        }
-        else {
-            kInvalidArgument
-        }
+        guard("IPluginFactory3::get_class_info_unicode", kInternalError, || {
+            if let Some((ci, _)) = self.classes.get(index as usize) {
+                *info = class_info2_to_w(ci);
+                kResultOk
+            }
+            else {
+                kInvalidArgument
+            }
+        })
     }
 
     unsafe fn set_host_context(&self, context: *mut c_void) -> tresult {
@@ -214,56 +240,76 @@ impl IPluginFactory3 for VstPluginFactory {
 
 impl IPluginFactory2 for VstPluginFactory {
     unsafe fn get_class_info2(&self, index: i32, info: *mut PClassInfo2) -> tresult {
-        if let Some((ci, _)) = self.classes.get(index as usize) {
-            copy_nonoverlapping(ci, info, 1);
-            kResultOk
-        }
-        else {
-            kInvalidArgument
-        }
+        guard("IPluginFactory2::get_class_info2", kInternalError, || {
+            if let Some((ci, _)) = self.classes.get(index as usize) {
+                copy_nonoverlapping(ci, info, 1);
+                kResultOk
+            }
+            else {
+                kInvalidArgument
+            }
+        })
     }
 }
 
 impl IPluginFactory for VstPluginFactory {
     unsafe fn get_factory_info(&self, info: *mut PFactoryInfo) -> tresult {
         info!("IPluginFactory.get_factory_info");
-        copy_nonoverlapping(&self.pinfo, info, 1);
-        kResultOk
+
+        guard("IPluginFactory::get_factory_info", kInternalError, || {
+            copy_nonoverlapping(&self.pinfo, info, 1);
+            kResultOk
+        })
     }
 
     unsafe fn count_classes(&self) -> i32 {
         info!("IPluginFactory.count_classes");
-        self.classes.len() as i32
+
+        guard("IPluginFactory::count_classes", 0, || self.classes.len() as i32)
     }
 
     unsafe fn get_class_info(&self, index: i32, info: *mut PClassInfo) -> tresult {
         info!("IPluginFactory.get_class_info");
 
-        if let Some((ci, _)) = self.classes.get(index as usize) {
-            let info = &mut *info;
-            info.cardinality = ci.cardinality;
-            info.cid = ci.cid;
-            info.category = ci.category;
-            info.name = ci.name;
-            kResultOk
-        }
-        else {
-            kInvalidArgument
-        }
+        guard("IPluginFactory::get_class_info", kInternalError, || {
+            if let Some((ci, _)) = self.classes.get(index as usize) {
+                let info = &mut *info;
+                info.cardinality = ci.cardinality;
+                info.cid = ci.cid;
+                info.category = ci.category;
+                info.name = ci.name;
+                kResultOk
+            }
+            else {
+                kInvalidArgument
+            }
+        })
     }
 
-    unsafe fn create_instance(&self, cid: *const IID, _iid: *const IID, obj: *mut *mut c_void) -> tresult {
+    unsafe fn create_instance(&self, cid: *const IID, iid: *const IID, obj: *mut *mut c_void) -> tresult {
         info!("IPluginFactory.create_instance");
 
-        for (ci, f) in self.classes.iter() {
-            if ci.cid == *cid {
-                info!("IPluginFactory.create_instance found class");
-                *obj = f();
-                return kResultOk;
+        guard("IPluginFactory::create_instance", kInternalError, || {
+            if cid.is_null() || iid.is_null() || obj.is_null() {
+                return kInvalidArgument;
+            }
+
+            *obj = null_mut();
+
+            for (ci, f) in self.classes.iter() {
+                if ci.cid == *cid {
+                    info!("IPluginFactory.create_instance found class");
+
+                    // The new object is released again if it doesn't implement the requested interface
+                    return match owned_vst_ptr::<dyn IUnknown>(f()) {
+                        Some(unknown) if unknown.query_interface(iid, obj) == kResultOk => kResultOk,
+                        _ => kNoInterface,
+                    };
+                }
             }
-        }
 
-        info!("IPluginFactory.create_instance end");
-        kResultFalse
+            info!("IPluginFactory.create_instance end");
+            kResultFalse
+        })
     }
 }
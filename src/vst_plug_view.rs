@@ -0,0 +1,307 @@
+use crate::editor::{Editor, KeyEvent, KeyModifiers, ParentWindow, Platform, ViewRect};
+use crate::utils::{guard, raw_to_shared_vst_ptr, shared_vst_ptr};
+use log::info;
+use std::cell::{Cell, RefCell};
+use std::ffi::CStr;
+use std::ptr::null_mut;
+use std::rc::Rc;
+use vst3_com::c_void;
+use vst3_sys::base::{
+    char16, kInternalError, kInvalidArgument, kResultFalse, kResultOk, kResultTrue, tresult, FIDString, TBool,
+};
+use vst3_sys::gui::{IPlugFrame, IPlugView, IPlugViewContentScaleSupport};
+use vst3_sys::utils::VstPtr;
+use vst3_sys::VST3;
+
+#[cfg(target_os = "linux")]
+use crate::utils::owned_vst_ptr;
+#[cfg(target_os = "linux")]
+use vst3_sys::gui::linux::{IRunLoop, ITimerHandler};
+
+const IDLE_TIMER_INTERVAL_MS: u64 = 16;
+
+fn to_view_rect(r: &vst3_sys::gui::ViewRect) -> ViewRect {
+    ViewRect {
+        left:   r.left,
+        top:    r.top,
+        right:  r.right,
+        bottom: r.bottom,
+    }
+}
+
+fn from_view_rect(r: ViewRect) -> vst3_sys::gui::ViewRect {
+    vst3_sys::gui::ViewRect {
+        left:   r.left,
+        top:    r.top,
+        right:  r.right,
+        bottom: r.bottom,
+    }
+}
+
+unsafe fn to_platform(type_: FIDString) -> Option<Platform> {
+    if type_.is_null() {
+        return None;
+    }
+
+    CStr::from_ptr(type_).to_str().ok().and_then(Platform::from_type_string)
+}
+
+fn to_key_event(key: char16, key_code: i16, modifiers: i16) -> KeyEvent {
+    KeyEvent {
+        character: if key != 0 { char::from_u32(key as u16 as u32) } else { None },
+        key_code,
+        modifiers: KeyModifiers::from_bits(modifiers),
+    }
+}
+
+#[cfg(target_os = "linux")]
+#[VST3(implements(ITimerHandler))]
+struct VstTimerHandler {
+    editor: Rc<dyn Editor>,
+}
+
+#[cfg(target_os = "linux")]
+impl ITimerHandler for VstTimerHandler {
+    unsafe fn on_timer(&self) { guard("ITimerHandler::on_timer", (), || self.editor.on_idle()) }
+}
+
+/// Host run loop registration keeping the editor idle timer alive while attached
+#[cfg(target_os = "linux")]
+struct IdleTimer {
+    run_loop: VstPtr<dyn IRunLoop>,
+    handler:  VstPtr<dyn ITimerHandler>,
+}
+
+#[cfg(target_os = "linux")]
+impl IdleTimer {
+    unsafe fn register(frame: &VstPtr<dyn IPlugFrame>, editor: Rc<dyn Editor>) -> Option<Self> {
+        let run_loop = frame.get_interface::<dyn IRunLoop>()?;
+        // Takes over the only reference, the handler is released when the timer is dropped
+        let handler =
+            owned_vst_ptr::<dyn ITimerHandler>(Box::into_raw(VstTimerHandler::allocate(editor)) as *mut c_void)?;
+
+        if run_loop.register_timer(handler.as_raw() as *mut c_void, IDLE_TIMER_INTERVAL_MS) == kResultOk {
+            Some(Self { run_loop, handler })
+        }
+        else {
+            None
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl Drop for IdleTimer {
+    fn drop(&mut self) { unsafe { self.run_loop.unregister_timer(self.handler.as_raw() as *mut c_void) }; }
+}
+
+#[cfg(not(target_os = "linux"))]
+struct IdleTimer;
+
+#[cfg(not(target_os = "linux"))]
+impl IdleTimer {
+    unsafe fn register(_frame: &VstPtr<dyn IPlugFrame>, _editor: Rc<dyn Editor>) -> Option<Self> { None }
+}
+
+#[VST3(implements(IPlugView, IPlugViewContentScaleSupport))]
+pub struct VstPlugView {
+    editor:     Rc<dyn Editor>,
+    frame:      RefCell<Option<VstPtr<dyn IPlugFrame>>>,
+    idle_timer: RefCell<Option<IdleTimer>>,
+    attached:   Cell<bool>,
+
+    /// The pointer handed to the host by `create`, no reference is held
+    view: Cell<*mut c_void>,
+}
+
+impl VstPlugView {
+    pub fn new(editor: Box<dyn Editor>) -> Box<Self> {
+        Self::allocate(editor.into(), Default::default(), Default::default(), Cell::new(false), Cell::new(null_mut()))
+    }
+
+    /// Returns the `IPlugView` pointer for the host, the view passes it to the frame when resizing itself
+    pub fn create(editor: Box<dyn Editor>) -> *mut c_void {
+        let view = Box::into_raw(Self::new(editor));
+        unsafe { (*view).view.set(view as *mut c_void) };
+        view as *mut c_void
+    }
+}
+
+impl IPlugView for VstPlugView {
+    unsafe fn is_platform_type_supported(&self, type_: FIDString) -> tresult {
+        info!("IPlugView::is_platform_type_supported");
+
+        guard("IPlugView::is_platform_type_supported", kInternalError, || match to_platform(type_) {
+            Some(p) if self.editor.is_platform_supported(p) => kResultTrue,
+            _ => kResultFalse,
+        })
+    }
+
+    unsafe fn attached(&self, parent: *mut c_void, type_: FIDString) -> tresult {
+        info!("IPlugView::attached");
+
+        guard("IPlugView::attached", kInternalError, || {
+            if self.attached.get() || parent.is_null() {
+                return kResultFalse;
+            }
+
+            let platform = match to_platform(type_) {
+                Some(p) if self.editor.is_platform_supported(p) => p,
+                _ => return kInvalidArgument,
+            };
+
+            if !self.editor.attached(ParentWindow::new(platform, parent)) {
+                return kResultFalse;
+            }
+
+            self.attached.set(true);
+
+            if let Some(frame) = self.frame.borrow().as_ref() {
+                self.idle_timer.replace(IdleTimer::register(frame, self.editor.clone()));
+            }
+
+            kResultOk
+        })
+    }
+
+    unsafe fn removed(&self) -> tresult {
+        info!("IPlugView::removed");
+
+        guard("IPlugView::removed", kInternalError, || {
+            if !self.attached.get() {
+                return kResultFalse;
+            }
+
+            self.idle_timer.replace(None);
+            self.editor.removed();
+            self.attached.set(false);
+            kResultOk
+        })
+    }
+
+    unsafe fn on_wheel(&self, distance: f32) -> tresult {
+        guard("IPlugView::on_wheel", kInternalError, || {
+            if self.editor.on_wheel(distance) {
+                kResultTrue
+            }
+            else {
+                kResultFalse
+            }
+        })
+    }
+
+    unsafe fn on_key_down(&self, key: char16, key_code: i16, modifiers: i16) -> tresult {
+        guard("IPlugView::on_key_down", kInternalError, || {
+            if self.editor.on_key_down(to_key_event(key, key_code, modifiers)) {
+                kResultTrue
+            }
+            else {
+                kResultFalse
+            }
+        })
+    }
+
+    unsafe fn on_key_up(&self, key: char16, key_code: i16, modifiers: i16) -> tresult {
+        guard("IPlugView::on_key_up", kInternalError, || {
+            if self.editor.on_key_up(to_key_event(key, key_code, modifiers)) {
+                kResultTrue
+            }
+            else {
+                kResultFalse
+            }
+        })
+    }
+
+    unsafe fn get_size(&self, size: *mut vst3_sys::gui::ViewRect) -> tresult {
+        guard("IPlugView::get_size", kInternalError, || {
+            if size.is_null() {
+                return kInvalidArgument;
+            }
+
+            *size = from_view_rect(self.editor.get_size());
+            kResultOk
+        })
+    }
+
+    unsafe fn on_size(&self, new_size: *mut vst3_sys::gui::ViewRect) -> tresult {
+        info!("IPlugView::on_size");
+
+        guard("IPlugView::on_size", kInternalError, || {
+            if new_size.is_null() {
+                return kInvalidArgument;
+            }
+
+            if self.editor.on_size(to_view_rect(&*new_size)) {
+                kResultOk
+            }
+            else {
+                kResultFalse
+            }
+        })
+    }
+
+    unsafe fn on_focus(&self, state: TBool) -> tresult {
+        guard("IPlugView::on_focus", kInternalError, || {
+            self.editor.on_focus(state != 0);
+            kResultOk
+        })
+    }
+
+    unsafe fn set_frame(&self, frame: *mut c_void) -> tresult {
+        info!("IPlugView::set_frame");
+
+        guard("IPlugView::set_frame", kInternalError, || {
+            let frame = shared_vst_ptr::<dyn IPlugFrame>(frame);
+
+            if frame.is_none() {
+                self.idle_timer.replace(None);
+            }
+
+            self.frame.replace(frame);
+            kResultOk
+        })
+    }
+
+    unsafe fn can_resize(&self) -> tresult {
+        guard("IPlugView::can_resize", kInternalError, || {
+            if self.editor.can_resize() {
+                kResultTrue
+            }
+            else {
+                kResultFalse
+            }
+        })
+    }
+
+    unsafe fn check_size_constraint(&self, rect: *mut vst3_sys::gui::ViewRect) -> tresult {
+        guard("IPlugView::check_size_constraint", kInternalError, || {
+            if rect.is_null() {
+                return kInvalidArgument;
+            }
+
+            *rect = from_view_rect(self.editor.check_size_constraint(to_view_rect(&*rect)));
+            kResultTrue
+        })
+    }
+}
+
+impl IPlugViewContentScaleSupport for VstPlugView {
+    unsafe fn set_content_scale_factor(&self, factor: f32) -> tresult {
+        info!("IPlugViewContentScaleSupport::set_content_scale_factor {}", factor);
+
+        guard("IPlugViewContentScaleSupport::set_content_scale_factor", kInternalError, || {
+            if !self.editor.set_content_scale_factor(factor) {
+                return kResultFalse;
+            }
+
+            // The size of the editor may change with the scale factor
+            if self.attached.get() && !self.view.get().is_null() {
+                if let Some(frame) = self.frame.borrow().as_ref() {
+                    let mut rect = from_view_rect(self.editor.get_size());
+                    frame.resize_view(raw_to_shared_vst_ptr(self.view.get()), &mut rect);
+                }
+            }
+
+            kResultOk
+        })
+    }
+}